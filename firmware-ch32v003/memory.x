@@ -1,6 +1,7 @@
 MEMORY
 {
-  FLASH : ORIGIN = 0x00000000, LENGTH = 16K
+  /* The last 2K (0x08003800, two 1K pages) hold persistent settings */
+  FLASH : ORIGIN = 0x00000000, LENGTH = 14K
   RAM : ORIGIN = 0x20000000, LENGTH = 2K
 }
 
@@ -29,8 +29,10 @@ use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU8, Ordering};
 use core::cell::RefCell;
 use riscv_rt::entry;
 use keyer_core::{
-    KeyerFSM, PaddleInput, PaddleSide, KeyerConfig, KeyerMode, Element,
-    hal::{Duration, Instant, InputPaddle, OutputKey, HalError}
+    KeyerFSM, PaddleInput, PaddleSide, KeyerConfig, KeyerMode, SqueezeTieRule, SharedConfig, Element,
+    Beacon, BeaconSettings, CommandMode, ContestMacros, MessageMemory,
+    AutoSave, ConfigStore, Settings, SettingsLog, StorageError,
+    hal::{Clock, Duration, Instant, InputPaddle, OutputKey, HalError}
 };
 use heapless::spsc::Queue;
 
@@ -103,6 +105,22 @@ const SYSTICK_CSR: u32 = 0x00;  // Control and Status Register
 const SYSTICK_RVR: u32 = 0x04;  // Reload Value Register  
 const SYSTICK_CVR: u32 = 0x08;  // Current Value Register
 
+/// Flash controller (FPEC) registers
+const FLASH_BASE: u32 = 0x4002_2000;
+const FLASH_KEYR: u32 = 0x04;   // Key Register
+const FLASH_STATR: u32 = 0x0C;  // Status Register
+const FLASH_CTLR: u32 = 0x10;   // Control Register
+const FLASH_ADDR: u32 = 0x14;   // Address Register
+const FLASH_KEY1: u32 = 0x4567_0123;
+const FLASH_KEY2: u32 = 0xCDEF_89AB;
+
+/// Settings pages: the last 2KB of flash, excluded from FLASH in memory.x
+const SETTINGS_BASE: u32 = 0x0800_3800;
+const SETTINGS_PAGE_SIZE: usize = 1024;
+const SETTINGS_PAGES: usize = 2;
+/// Save configuration changes once they have settled this long
+const SETTINGS_SAVE_DELAY_MS: u64 = 5_000;
+
 // ========================================
 // Hardware Abstraction Layer
 // ========================================
@@ -118,11 +136,12 @@ static SYSTEM_TICK_MS: AtomicU32 = AtomicU32::new(0);
 static SYSTEM_EVENTS: AtomicU32 = AtomicU32::new(0);
 const EVENT_PADDLE: u32 = 0x01;      // Paddle state changed
 
-/// Transmission controller (12 bytes)
+/// Transmission controller (16 bytes)
 struct TxController {
     state: AtomicU8,           // Idle(0) / Transmitting(1)
     element_end_ms: AtomicU32, // Current element end time
     next_allowed_ms: AtomicU32, // Next transmission allowed time (space control)
+    space_after_ms: AtomicU32, // Space following the current element
 }
 
 impl TxController {
@@ -131,6 +150,7 @@ impl TxController {
             state: AtomicU8::new(0), // Idle
             element_end_ms: AtomicU32::new(0),
             next_allowed_ms: AtomicU32::new(0),
+            space_after_ms: AtomicU32::new(0),
         }
     }
     
@@ -142,11 +162,16 @@ impl TxController {
         self.state.load(Ordering::Relaxed) == 1
     }
     
-    fn set_transmitting(&self, end_time: u32) {
+    fn set_transmitting(&self, end_time: u32, space_after: u32) {
+        self.space_after_ms.store(space_after, Ordering::Relaxed);
         self.state.store(1, Ordering::Release);
         self.element_end_ms.store(end_time, Ordering::Release);
     }
     
+    fn space_after(&self) -> u32 {
+        self.space_after_ms.load(Ordering::Relaxed)
+    }
+    
     fn set_idle_with_constraint(&self, next_allowed: u32) {
         self.state.store(0, Ordering::Release);
         self.next_allowed_ms.store(next_allowed, Ordering::Release);
@@ -164,11 +189,29 @@ impl TxController {
 /// Global state
 static TX_CONTROLLER: TxController = TxController::new();
 static LAST_ACTIVITY_MS: AtomicU32 = AtomicU32::new(0);
+static LAST_KEY_UP_MS: AtomicU32 = AtomicU32::new(0);
 static PADDLE_CHANGED: AtomicBool = AtomicBool::new(false);
 static PADDLE_STATE: critical_section::Mutex<RefCell<PaddleInput>> = 
     critical_section::Mutex::new(RefCell::new(PaddleInput::new()));
 static KEYER_FSM_INSTANCE: critical_section::Mutex<RefCell<Option<KeyerFSM>>> = 
     critical_section::Mutex::new(RefCell::new(None));
+/// Runtime configuration (applied to the FSM at element boundaries)
+static KEYER_CONFIG: SharedConfig = SharedConfig::new();
+static KEYER_CONFIG_SEEN: AtomicU32 = AtomicU32::new(0);
+
+/// Command mode (long squeeze), run in place of the plain FSM update
+static COMMAND_MODE: critical_section::Mutex<RefCell<Option<CommandMode>>> =
+    critical_section::Mutex::new(RefCell::new(None));
+
+/// Beacon / fox-hunt mode (enabled in the stored settings)
+static BEACON: critical_section::Mutex<RefCell<Option<Beacon>>> =
+    critical_section::Mutex::new(RefCell::new(None));
+/// Built-in message memory: slot 0 is the beacon text, whatever slot `BEACON` names
+const BEACON_TEMPLATE: &str = "VVV DE {CALL}/B";
+
+/// Settings persistence (None if the flash layout is unusable)
+static SETTINGS_STORE: critical_section::Mutex<RefCell<Option<(SettingsLog<Ch32v003Flash>, Settings, AutoSave)>>> =
+    critical_section::Mutex::new(RefCell::new(None));
 
 /// Element queue for FSM communication
 static mut ELEMENT_QUEUE: Queue<Element, 4> = Queue::new();
@@ -183,15 +226,25 @@ fn get_current_instant() -> Instant {
     Instant::from_millis(ms as i64)
 }
 
+/// SysTick-backed time source for keyer-core
+struct SysTickClock;
+
+impl Clock for SysTickClock {
+    fn now(&self) -> Instant {
+        get_current_instant()
+    }
+}
+
 /// Record activity for power management
 fn record_activity() {
     let now_ms = SYSTEM_TICK_MS.load(Ordering::Relaxed);
     LAST_ACTIVITY_MS.store(now_ms, Ordering::Relaxed);
 }
 
-/// Get unit duration in milliseconds (20 WPM = 60ms per unit)
-fn get_unit_duration_ms() -> u32 {
-    60 // Fixed 20 WPM for now
+/// Get element timing in milliseconds as (key-down, space) from the current configuration
+fn get_element_timing_ms(element: Element) -> (u32, u32) {
+    let timing = KEYER_CONFIG.get().unwrap_or_default().element_timing(element);
+    (timing.key_down.as_millis() as u32, timing.space.as_millis() as u32)
 }
 
 /// Debug logging for transmission (feature-gated)
@@ -210,19 +263,375 @@ macro_rules! tx_debug {
 /// Initialize keyer FSM
 fn initialize_keyer_fsm() {
     critical_section::with(|cs| {
-        let config = KeyerConfig {
+        let mut config = KeyerConfig {
             mode: KeyerMode::ModeA,  // Unified to ModeA for compatibility
             char_space_enabled: true,
             unit: Duration::from_millis(60),
             debounce_ms: 10,  // Unified 10ms debounce for noise immunity
             queue_size: 4,
+            squeeze_tie: SqueezeTieRule::Dit,
+            sidetone_enabled: true,
+            dah_ratio_tenths: 30,
+            weighting: 50,
+            farnsworth_wpm: None,
+            paddle_swap: false,
+            single_lever: false,
+            tx_enabled: true,
         };
+
+        // Saved settings override the defaults
+        // Beacon (off until enabled with `BEACON`) with a 5 s carrier
+        let beacon = BeaconSettings { carrier_s: 5, ..BeaconSettings::default() };
+        let defaults = Settings { keyer: config, beacon, ..Settings::default() };
+        let mut log = SettingsLog::new(Ch32v003Flash).ok();
+        let settings = log.as_mut().map_or_else(|| defaults.clone(), |log| log.load(&defaults));
+        config = settings.keyer;
+        
+        // A mono plug shorts the ring (Dah) contact: treat it as a straight key
+        // for this session only; the stored mode is what gets saved
+        let straight_key = DAH_INPUT.is_low();
+        if straight_key {
+            config.mode = KeyerMode::StraightKey;
+        }
+        KEYER_CONFIG.set(config);
+        KEYER_CONFIG_SEEN.store(KEYER_CONFIG.generation(), Ordering::Relaxed);
+        let mut autosave = AutoSave::new(&KEYER_CONFIG, Duration::from_millis(SETTINGS_SAVE_DELAY_MS));
+        if straight_key {
+            autosave = autosave.with_mode_override(KeyerMode::StraightKey, settings.keyer.mode);
+        }
+        #[cfg(feature = "cli")]
+        serial_cli::SAVE_REQUEST.set_callsign(&settings.callsign).ok();
+        *SETTINGS_STORE.borrow(cs).borrow_mut() = log.map(|log| (log, settings, autosave));
+        
         let fsm = KeyerFSM::new(config);
         *KEYER_FSM_INSTANCE.borrow(cs).borrow_mut() = Some(fsm);
+        *COMMAND_MODE.borrow(cs).borrow_mut() = Some(CommandMode::default());
     });
     info!("🎛️ Keyer FSM initialized");
 }
 
+/// Enter beacon mode if the stored settings enable it
+fn initialize_beacon() {
+    critical_section::with(|cs| {
+        let store = SETTINGS_STORE.borrow(cs).borrow();
+        let Some((_, settings, _)) = store.as_ref().filter(|(_, settings, _)| settings.beacon.enabled) else {
+            return;
+        };
+        let mut memory = MessageMemory::<1>::new();
+        memory.set(0, BEACON_TEMPLATE).ok();
+        let mut macros = ContestMacros::new();
+        macros.set_callsign(&settings.callsign).ok();
+        // Only one slot here; the stored one may name a slot of a larger board
+        let beacon = BeaconSettings { slot: 0, ..settings.beacon };
+        // Without a usable message (e.g. no callsign for `{CALL}`) the keyer runs as usual
+        match Beacon::from_slot(&memory, &mut macros, &beacon) {
+            Ok(beacon) => {
+                *BEACON.borrow(cs).borrow_mut() = Some(beacon);
+                info!("📡 Beacon mode");
+            }
+            Err(_error) => {
+                warn!("📡 Beacon message unusable: {}", defmt::Debug2Format(&_error));
+            }
+        }
+    });
+}
+
+/// Speed potentiometer on PC4 (ADC channel 2), wiper between GND and VDD
+#[cfg(feature = "speed-pot")]
+mod speed_pot {
+    use super::*;
+    use keyer_core::hal::AnalogSpeedInput;
+    use keyer_core::{SpeedKnob, SpeedRange};
+
+    const ADC1_BASE: u32 = 0x4001_2400;
+    const ADC_STATR: u32 = 0x00;   // Status Register
+    const ADC_CTLR2: u32 = 0x08;   // Control Register 2
+    const ADC_SAMPTR2: u32 = 0x10; // Sample Time Register 2
+    const ADC_RSQR3: u32 = 0x34;   // Regular Sequence Register 3
+    const ADC_RDATAR: u32 = 0x4C;  // Regular Data Register
+
+    const ADC_CHANNEL: u32 = 2;    // PC4 = A2
+
+    /// Knob travel
+    const SPEED_RANGE: SpeedRange = SpeedRange { min_wpm: 10, max_wpm: 40 };
+
+    static SPEED_KNOB: critical_section::Mutex<RefCell<Option<SpeedKnob>>> =
+        critical_section::Mutex::new(RefCell::new(None));
+
+    /// 10-bit ADC, single software-triggered conversion per reading
+    struct Ch32v003Adc;
+
+    impl AnalogSpeedInput for Ch32v003Adc {
+        type Error = HalError;
+
+        fn read_raw(&mut self) -> Result<u16, Self::Error> {
+            unsafe {
+                let ctlr2 = (ADC1_BASE + ADC_CTLR2) as *mut u32;
+                core::ptr::write_volatile(ctlr2, core::ptr::read_volatile(ctlr2) | (1 << 22)); // SWSTART
+                // Conversion takes a few microseconds
+                while core::ptr::read_volatile((ADC1_BASE + ADC_STATR) as *const u32) & (1 << 1) == 0 {} // EOC
+                Ok((core::ptr::read_volatile((ADC1_BASE + ADC_RDATAR) as *const u32) & 0x3FF) as u16)
+            }
+        }
+
+        fn full_scale(&self) -> u16 {
+            1023
+        }
+    }
+
+    /// Configure PC4 as analog input and calibrate the ADC
+    pub fn configure() {
+        unsafe {
+            let rcc_apb2pcenr = (RCC_BASE + RCC_APB2PCENR) as *mut u32;
+            let current = core::ptr::read_volatile(rcc_apb2pcenr);
+            // Bit 4 = GPIOC, Bit 9 = ADC1
+            core::ptr::write_volatile(rcc_apb2pcenr, current | (1 << 4) | (1 << 9));
+
+            // PC4: analog input (CNF=00, MODE=00)
+            let gpioc_crl = (GPIOC_BASE + GPIO_CRL) as *mut u32;
+            let crl = core::ptr::read_volatile(gpioc_crl);
+            core::ptr::write_volatile(gpioc_crl, crl & !(0xF << 16));
+
+            // Longest sample time for the high-impedance wiper, one-channel sequence
+            let samptr2 = (ADC1_BASE + ADC_SAMPTR2) as *mut u32;
+            core::ptr::write_volatile(samptr2, 0b111 << (3 * ADC_CHANNEL));
+            core::ptr::write_volatile((ADC1_BASE + ADC_RSQR3) as *mut u32, ADC_CHANNEL);
+
+            // ADON, software trigger (EXTSEL=111, EXTTRIG), then calibrate
+            let ctlr2 = (ADC1_BASE + ADC_CTLR2) as *mut u32;
+            core::ptr::write_volatile(ctlr2, 1 | (0b111 << 17) | (1 << 20));
+            core::ptr::write_volatile(ctlr2, core::ptr::read_volatile(ctlr2) | (1 << 3)); // RSTCAL
+            while core::ptr::read_volatile(ctlr2) & (1 << 3) != 0 {}
+            core::ptr::write_volatile(ctlr2, core::ptr::read_volatile(ctlr2) | (1 << 2)); // CAL
+            while core::ptr::read_volatile(ctlr2) & (1 << 2) != 0 {}
+        }
+
+        let knob = SpeedKnob::new(SPEED_RANGE, Ch32v003Adc.full_scale()).ok();
+        critical_section::with(|cs| *SPEED_KNOB.borrow(cs).borrow_mut() = knob);
+        info!("🎚️ Speed pot enabled");
+    }
+
+    /// Read the pot; a new speed reaches the keyer at the next element boundary
+    pub fn update() {
+        critical_section::with(|cs| {
+            if let Some(knob) = SPEED_KNOB.borrow(cs).borrow_mut().as_mut() {
+                if let Ok(Some(_wpm)) = knob.poll(&mut Ch32v003Adc, &KEYER_CONFIG) {
+                    debug!("🎚️ Speed: {} WPM", _wpm);
+                }
+            }
+        });
+    }
+}
+
+/// Serial command line on USART1, remapped to PC0 (TX) / PC1 (RX), 115200 8N1
+///
+/// Received bytes are buffered by the RX interrupt; the main loop assembles
+/// lines, runs them and sends the response one byte per pass. Input arriving
+/// while a response is still going out waits in the RX buffer.
+#[cfg(feature = "cli")]
+mod serial_cli {
+    use super::*;
+    use heapless::Deque;
+    use heapless::spsc::Producer;
+    use keyer_core::cli::{self, LineReader, Response};
+    use keyer_core::{MessagePlayer, PlaybackControl, PlaybackStatus, SaveRequest};
+
+    const USART1_BASE: u32 = 0x4001_3800;
+    const USART_STATR: u32 = 0x00;  // Status Register
+    const USART_DATAR: u32 = 0x04;  // Data Register
+    const USART_BRR: u32 = 0x08;    // Baud Rate Register
+    const USART_CTLR1: u32 = 0x0C;  // Control Register 1
+
+    /// 24MHz / 115200
+    const BAUD_DIVIDER: u32 = 208;
+    /// Shorter lines than on the CH32V203 to save RAM
+    const LINE_LEN: usize = 32;
+
+    pub static PLAYBACK: PlaybackControl = PlaybackControl::new();
+    pub static SAVE_REQUEST: SaveRequest = SaveRequest::new();
+
+    static RX_BUFFER: critical_section::Mutex<RefCell<Deque<u8, 16>>> =
+        critical_section::Mutex::new(RefCell::new(Deque::new()));
+    /// Line being assembled, response being sent and bytes of it already sent
+    static CLI: critical_section::Mutex<RefCell<(LineReader<LINE_LEN>, Response, usize)>> =
+        critical_section::Mutex::new(RefCell::new((LineReader::new(), Response::new(), 0)));
+    static PLAYER: critical_section::Mutex<RefCell<MessagePlayer>> =
+        critical_section::Mutex::new(RefCell::new(MessagePlayer::new()));
+
+    fn status() -> u32 {
+        unsafe { core::ptr::read_volatile((USART1_BASE + USART_STATR) as *const u32) }
+    }
+
+    /// Configure USART1 with the RX interrupt
+    pub fn configure() {
+        unsafe {
+            let rcc_apb2pcenr = (RCC_BASE + RCC_APB2PCENR) as *mut u32;
+            let current = core::ptr::read_volatile(rcc_apb2pcenr);
+            // Bit 0 = AFIO, Bit 4 = GPIOC, Bit 14 = USART1
+            core::ptr::write_volatile(rcc_apb2pcenr, current | 1 | (1 << 4) | (1 << 14));
+
+            // USART1_RM1:USART1_RM = 11 moves TX/RX to PC0/PC1 (PD6 is the key output)
+            let afio_pcfr1 = (AFIO_BASE + AFIO_PCFR1) as *mut u32;
+            let pcfr1 = core::ptr::read_volatile(afio_pcfr1);
+            core::ptr::write_volatile(afio_pcfr1, pcfr1 | (1 << 2) | (1 << 21));
+
+            // PC0: AF push-pull 50MHz, PC1: input with pull-up
+            let gpioc_crl = (GPIOC_BASE + GPIO_CRL) as *mut u32;
+            let crl = core::ptr::read_volatile(gpioc_crl) & !0xFF;
+            core::ptr::write_volatile(gpioc_crl, crl | 0xB | (0x8 << 4));
+            let gpioc_odr = (GPIOC_BASE + GPIO_ODR) as *mut u32;
+            core::ptr::write_volatile(gpioc_odr, core::ptr::read_volatile(gpioc_odr) | (1 << 1));
+
+            core::ptr::write_volatile((USART1_BASE + USART_BRR) as *mut u32, BAUD_DIVIDER);
+            // UE, RXNEIE, TE, RE
+            core::ptr::write_volatile((USART1_BASE + USART_CTLR1) as *mut u32, (1 << 13) | (1 << 5) | (1 << 3) | (1 << 2));
+
+            // USART1 is interrupt 32
+            let nvic_iser1 = (NVIC_BASE + 0x104) as *mut u32;
+            core::ptr::write_volatile(nvic_iser1, core::ptr::read_volatile(nvic_iser1) | 1);
+        }
+        info!("⌨️ Serial command line enabled");
+    }
+
+    /// Called from the USART1 interrupt handler
+    pub fn receive() {
+        // Reading DATAR clears RXNE (and an overrun)
+        let byte = unsafe { core::ptr::read_volatile((USART1_BASE + USART_DATAR) as *const u32) } as u8;
+        critical_section::with(|cs| {
+            // A full buffer drops the byte; the line then fails to parse
+            RX_BUFFER.borrow(cs).borrow_mut().push_back(byte).ok();
+        });
+    }
+
+    /// Send the next response byte, or run the next received line
+    pub fn update() {
+        critical_section::with(|cs| {
+            let mut cli = CLI.borrow(cs).borrow_mut();
+            let (reader, response, sent) = &mut *cli;
+            if let Some(&byte) = response.as_bytes().get(*sent) {
+                if status() & (1 << 7) != 0 {  // TXE
+                    unsafe { core::ptr::write_volatile((USART1_BASE + USART_DATAR) as *mut u32, u32::from(byte)) };
+                    *sent += 1;
+                }
+                return;
+            }
+
+            #[cfg(feature = "trace")]
+            if let Some(line) = paddle_trace::next_line() {
+                response.clear();
+                // Fits: trace lines are shorter than responses
+                response.push_str(&line).ok();
+                response.push_str("\r\n").ok();
+                *sent = 0;
+                return;
+            }
+
+            let mut rx = RX_BUFFER.borrow(cs).borrow_mut();
+            while let Some(byte) = rx.pop_front() {
+                if let Some(line) = reader.push(byte) {
+                    // The dump goes out line by line from the next update on
+                    #[cfg(feature = "trace")]
+                    if line.is_ok_and(|line| line.eq_ignore_ascii_case(b"TRACE")) {
+                        paddle_trace::start_dump();
+                        return;
+                    }
+                    *response = cli::handle_line(line, &KEYER_CONFIG, &PLAYBACK, &SAVE_REQUEST);
+                    // The status line leaves room for the line ending
+                    response.push_str("\r\n").ok();
+                    *sent = 0;
+                    record_activity();
+                    return;
+                }
+            }
+        });
+    }
+
+    /// Play requested messages; returns true while a message owns the queue
+    pub fn update_playback<const N: usize>(fsm: &mut KeyerFSM, paddle: &PaddleInput, producer: &mut Producer<'_, Element, N>) -> bool {
+        critical_section::with(|cs| {
+            let mut player = PLAYER.borrow(cs).borrow_mut();
+            if !fsm.element_in_flight() {
+                let was_playing = player.is_playing();
+                PLAYBACK.apply(&mut player);
+                if player.is_playing() && !was_playing {
+                    // Drop any pending character space from manual keying
+                    fsm.reset();
+                }
+            }
+            let status = player.update(paddle, producer);
+            if matches!(status, PlaybackStatus::Finished | PlaybackStatus::Aborted) {
+                fsm.wait_for_sender();
+            }
+            !matches!(status, PlaybackStatus::Idle | PlaybackStatus::Aborted)
+        })
+    }
+}
+
+/// Paddle edge recorder for reproducing field reports
+///
+/// Keeps the latest edges handed to the keyer. `TRACE` on the serial command
+/// line dumps them; with defmt they are logged, then cleared, at each heartbeat. The
+/// host `keyer-sim` replays a dump through the same keyer state machine.
+#[cfg(feature = "trace")]
+mod paddle_trace {
+    use super::*;
+    use core::cell::Cell;
+    use keyer_core::TraceRecorder;
+
+    /// 4 bytes per edge
+    const TRACE_LEN: usize = 32;
+
+    static RECORDER: critical_section::Mutex<RefCell<TraceRecorder<TRACE_LEN>>> =
+        critical_section::Mutex::new(RefCell::new(TraceRecorder::new()));
+    /// Next dump line; recording pauses while a dump is sent so its lines agree
+    static DUMP_LINE: critical_section::Mutex<Cell<Option<usize>>> =
+        critical_section::Mutex::new(Cell::new(None));
+
+    /// Record the paddle state handed to the keyer
+    pub fn record(dit: bool, dah: bool, now_ms: u32) {
+        critical_section::with(|cs| {
+            if DUMP_LINE.borrow(cs).get().is_none() {
+                let mut recorder = RECORDER.borrow(cs).borrow_mut();
+                recorder.record(PaddleSide::Dit, dit, now_ms);
+                recorder.record(PaddleSide::Dah, dah, now_ms);
+            }
+        });
+    }
+
+    /// Start sending a dump line by line
+    #[cfg(feature = "cli")]
+    pub fn start_dump() {
+        critical_section::with(|cs| DUMP_LINE.borrow(cs).set(Some(0)));
+    }
+
+    /// Next line of the dump being sent
+    #[cfg(feature = "cli")]
+    pub fn next_line() -> Option<keyer_core::trace::TraceLine> {
+        critical_section::with(|cs| {
+            let index = DUMP_LINE.borrow(cs).get()?;
+            let config = KEYER_CONFIG.get().unwrap_or_default();
+            let line = RECORDER.borrow(cs).borrow().line(index, &config);
+            DUMP_LINE.borrow(cs).set(line.as_ref().map(|_| index + 1));
+            line
+        })
+    }
+
+    /// Log recorded edges through defmt and start over
+    #[cfg(feature = "defmt")]
+    pub fn log() {
+        critical_section::with(|cs| {
+            let mut recorder = RECORDER.borrow(cs).borrow_mut();
+            if recorder.is_empty() {
+                return;
+            }
+            let config = KEYER_CONFIG.get().unwrap_or_default();
+            let lines = (0..recorder.line_count()).map_while(|index| recorder.line(index, &config));
+            lines.for_each(|line| info!("{}", line.as_str()));
+            recorder.clear();
+        });
+    }
+}
+
 /// CH32V003 GPIO Input implementation with real register access and debouncing
 struct Ch32v003Input {
     /// GPIO port base address
@@ -385,13 +794,118 @@ impl Ch32v003Pwm {
 // PD6 = Key output (active high)
 // PD7 = Status LED (active high)
 // PA1 = Sidetone PWM output (TIM1_CH1)
+// PC4 = Speed potentiometer (ADC A2, `speed-pot` feature)
+// PC0/PC1 = Serial command line TX/RX (USART1 remap, `cli` feature)
 
+// Left-handed operation: set `paddle_swap` in the keyer config instead of swapping pins
 static DIT_INPUT: Ch32v003Input = Ch32v003Input::new(GPIOA_BASE, 2);  // PA2
 static DAH_INPUT: Ch32v003Input = Ch32v003Input::new(GPIOA_BASE, 3);  // PA3
 static KEY_OUTPUT: Ch32v003Output = Ch32v003Output::new(GPIOD_BASE, 6); // PD6
 static STATUS_LED: Ch32v003Output = Ch32v003Output::new(GPIOD_BASE, 7); // PD7
 static SIDETONE_PWM: Ch32v003Pwm = Ch32v003Pwm::new();
 
+/// Settings pages of the on-chip flash
+struct Ch32v003Flash;
+
+impl Ch32v003Flash {
+    fn reg(offset: u32) -> *mut u32 {
+        (FLASH_BASE + offset) as *mut u32
+    }
+
+    fn address(page: usize, offset: usize, len: usize) -> Result<u32, StorageError> {
+        if page >= SETTINGS_PAGES || offset + len > SETTINGS_PAGE_SIZE {
+            return Err(StorageError::OutOfRange);
+        }
+        Ok(SETTINGS_BASE + (page * SETTINGS_PAGE_SIZE + offset) as u32)
+    }
+
+    /// Run a program/erase operation with the controller unlocked
+    fn unlocked(ctlr_bits: u32, op: impl FnOnce() -> Result<(), StorageError>) -> Result<(), StorageError> {
+        unsafe {
+            if core::ptr::read_volatile(Self::reg(FLASH_CTLR)) & (1 << 7) != 0 {  // LOCK
+                core::ptr::write_volatile(Self::reg(FLASH_KEYR), FLASH_KEY1);
+                core::ptr::write_volatile(Self::reg(FLASH_KEYR), FLASH_KEY2);
+            }
+            let ctlr = core::ptr::read_volatile(Self::reg(FLASH_CTLR));
+            core::ptr::write_volatile(Self::reg(FLASH_CTLR), ctlr | ctlr_bits);
+            let result = op();
+            let ctlr = core::ptr::read_volatile(Self::reg(FLASH_CTLR));
+            core::ptr::write_volatile(Self::reg(FLASH_CTLR), (ctlr & !ctlr_bits) | (1 << 7));
+            result
+        }
+    }
+
+    /// Wait for the operation in progress and clear EOP/WRPRTERR
+    fn wait() -> Result<(), StorageError> {
+        unsafe {
+            while core::ptr::read_volatile(Self::reg(FLASH_STATR)) & 1 != 0 {}  // BSY
+            let statr = core::ptr::read_volatile(Self::reg(FLASH_STATR));
+            core::ptr::write_volatile(Self::reg(FLASH_STATR), statr & ((1 << 5) | (1 << 4)));
+            if statr & (1 << 4) != 0 {
+                return Err(StorageError::Device);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ConfigStore for Ch32v003Flash {
+    fn page_size(&self) -> usize {
+        SETTINGS_PAGE_SIZE
+    }
+
+    fn page_count(&self) -> usize {
+        SETTINGS_PAGES
+    }
+
+    fn read(&mut self, page: usize, offset: usize, buf: &mut [u8]) -> Result<(), StorageError> {
+        let address = Self::address(page, offset, buf.len())?;
+        for (i, byte) in buf.iter_mut().enumerate() {
+            *byte = unsafe { core::ptr::read_volatile((address as usize + i) as *const u8) };
+        }
+        Ok(())
+    }
+
+    fn erase(&mut self, page: usize) -> Result<(), StorageError> {
+        let address = Self::address(page, 0, SETTINGS_PAGE_SIZE)?;
+        // PER: standard page erase
+        Self::unlocked(1 << 1, || unsafe {
+            core::ptr::write_volatile(Self::reg(FLASH_ADDR), address);
+            let ctlr = core::ptr::read_volatile(Self::reg(FLASH_CTLR));
+            core::ptr::write_volatile(Self::reg(FLASH_CTLR), ctlr | (1 << 6));  // STRT
+            Self::wait()
+        })
+    }
+
+    fn write(&mut self, page: usize, offset: usize, data: &[u8]) -> Result<(), StorageError> {
+        let address = Self::address(page, offset, data.len())?;
+        let start = address & !1;
+        let end = address + data.len() as u32;
+        // PG: programmed in halfwords; bytes outside `data` are written as 0xFF (unchanged)
+        Self::unlocked(1 << 0, || {
+            for halfword in (start..end).step_by(2) {
+                let byte = |a: u32| match a.checked_sub(address) {
+                    Some(i) if a < end => data[i as usize],
+                    _ => 0xFF,
+                };
+                let value = u16::from_le_bytes([byte(halfword), byte(halfword + 1)]);
+                if value == 0xFFFF {
+                    continue;
+                }
+                let target = halfword as usize as *mut u16;
+                unsafe {
+                    if core::ptr::read_volatile(target) != 0xFFFF {
+                        return Err(StorageError::NotErased);
+                    }
+                    core::ptr::write_volatile(target, value);
+                }
+                Self::wait()?;
+            }
+            Ok(())
+        })
+    }
+}
+
 /// Combined HAL implementation for keyer-core integration
 struct Ch32v003KeyerHal;
 
@@ -439,7 +953,7 @@ impl OutputKey for Ch32v003KeyerHal {
             KEY_OUTPUT.set_high();
             STATUS_LED.set_high();
             // Enable sidetone
-            SIDETONE_PWM.set_duty(500); // 50% duty cycle
+            SIDETONE_PWM.set_duty(sidetone_duty()); // 50%, or muted when sidetone is off
         } else {
             KEY_OUTPUT.set_low();
             STATUS_LED.set_low(); 
@@ -475,6 +989,8 @@ fn update_paddle_state() {
         paddle.update(PaddleSide::Dit, dit_pressed, now_ms);
         paddle.update(PaddleSide::Dah, dah_pressed, now_ms);
     });
+    #[cfg(feature = "trace")]
+    paddle_trace::record(dit_pressed, dah_pressed, now_ms);
     
     record_activity();
     
@@ -491,15 +1007,90 @@ fn update_keyer_fsm() {
     critical_section::with(|cs| {
         let paddle = PADDLE_STATE.borrow(cs).borrow();
         
-        if let Some(ref mut fsm) = *KEYER_FSM_INSTANCE.borrow(cs).borrow_mut() {
+        let mut command = COMMAND_MODE.borrow(cs).borrow_mut();
+        if let (Some(ref mut fsm), Some(command)) = (&mut *KEYER_FSM_INSTANCE.borrow(cs).borrow_mut(), command.as_mut()) {
+            let now = SysTickClock.now();
             let mut producer = unsafe { ELEMENT_QUEUE.split().0 };
-            fsm.update(&*paddle, &mut producer);
+
+            // Command mode publishes its own settings
+            let published = if command.is_active() {
+                command.update(now, fsm, &paddle, &mut producer)
+            } else if command.watch(now, &paddle, fsm) {
+                #[cfg(feature = "cli")]
+                serial_cli::PLAYBACK.abort();
+                Some(command.enter(now, fsm))
+            } else {
+                None
+            };
+            // Settings only become permanent once command mode exits
+            if let Some(config) = published {
+                if command.is_active() {
+                    KEYER_CONFIG.set_temporary(config);
+                } else {
+                    KEYER_CONFIG.set(config);
+                }
+                KEYER_CONFIG_SEEN.store(KEYER_CONFIG.generation(), Ordering::Relaxed);
+            }
+            if command.is_active() {
+                return;
+            }
+
+            if !fsm.element_in_flight() {
+                let mut seen = KEYER_CONFIG_SEEN.load(Ordering::Relaxed);
+                if let Some(config) = KEYER_CONFIG.changed_since(&mut seen) {
+                    fsm.set_config(config);
+                    KEYER_CONFIG_SEEN.store(seen, Ordering::Relaxed);
+                }
+            }
+            #[cfg(feature = "cli")]
+            if serial_cli::update_playback(fsm, &paddle, &mut producer) {
+                return;
+            }
+            fsm.update_at(now, &*paddle, &mut producer);
         }
     });
     
     record_activity();
 }
 
+/// Save configuration changes to flash once they have settled, or when requested
+fn update_settings() {
+    critical_section::with(|cs| {
+        if let Some((log, settings, autosave)) = SETTINGS_STORE.borrow(cs).borrow_mut().as_mut() {
+            #[cfg(feature = "cli")]
+            let requested = serial_cli::SAVE_REQUEST.take();
+            #[cfg(feature = "cli")]
+            let changed = serial_cli::SAVE_REQUEST.take_changes(settings);
+            #[cfg(not(feature = "cli"))]
+            let (requested, changed) = (false, false);
+            let due = match requested {
+                true => autosave.flush(&KEYER_CONFIG),
+                false => autosave.poll(SysTickClock.now(), &KEYER_CONFIG),
+            };
+            if let Some(config) = due {
+                settings.keyer = config;
+            }
+            if due.is_some() || changed {
+                let _result = log.save(settings);
+                info!("💾 Settings saved: {}", _result.is_ok());
+            }
+        }
+    });
+}
+
+/// Update beacon schedule; returns false when not in beacon mode
+fn update_beacon() -> bool {
+    critical_section::with(|cs| {
+        let mut beacon = BEACON.borrow(cs).borrow_mut();
+        let Some(beacon) = beacon.as_mut() else {
+            return false;
+        };
+        let mut producer = unsafe { ELEMENT_QUEUE.split().0 };
+        beacon.update(SysTickClock.now(), &mut producer);
+        true
+    })
+}
+
 /// Transmission FSM update
 fn update_transmission_fsm(now_ms: u32) {
     if TX_CONTROLLER.is_transmitting() {
@@ -516,33 +1107,82 @@ fn update_transmission_fsm(now_ms: u32) {
     }
 }
 
+/// Sidetone level for key-down (muted when disabled, unless it is the only output)
+fn sidetone_duty() -> u16 {
+    match KEYER_CONFIG.get() {
+        Some(config) if !config.sidetone_enabled && config.tx_enabled => 0,
+        _ => 500,
+    }
+}
+
+/// Key down: transmitter (unless sidetone only), LED and sidetone
+fn key_on() {
+    if !matches!(KEYER_CONFIG.get(), Some(config) if !config.tx_enabled) {
+        KEY_OUTPUT.set_high();
+    }
+    STATUS_LED.set_high();
+    SIDETONE_PWM.set_duty(sidetone_duty());
+}
+
+/// Report sender progress to the keyer FSM (element-synchronous evaluation)
+fn notify_keyer_fsm(f: impl FnOnce(&mut KeyerFSM)) {
+    critical_section::with(|cs| {
+        if let Some(ref mut fsm) = *KEYER_FSM_INSTANCE.borrow(cs).borrow_mut() {
+            f(fsm);
+        }
+    });
+}
+
 /// Start element transmission
 fn start_element_transmission(element: Element, now_ms: u32) {
-    let unit_ms = get_unit_duration_ms();
+    let (on_ms, space_ms) = get_element_timing_ms(element);
+    
+    if element.is_keyed() && !element.is_manual() {
+        notify_keyer_fsm(|fsm| fsm.element_started(get_current_instant()));
+    }
     
     match element {
         Element::Dit => {
-            KEY_OUTPUT.set_high();
-            STATUS_LED.set_high();
-            SIDETONE_PWM.set_duty(500);
-            TX_CONTROLLER.set_transmitting(now_ms + unit_ms);
+            key_on();
+            TX_CONTROLLER.set_transmitting(now_ms + on_ms, space_ms);
             record_activity();
-            tx_debug!("🟢 Dit start: {}ms", unit_ms);
+            tx_debug!("🟢 Dit start: {}ms", on_ms);
         }
         
         Element::Dah => {
-            KEY_OUTPUT.set_high();
-            STATUS_LED.set_high();
-            SIDETONE_PWM.set_duty(500);
-            TX_CONTROLLER.set_transmitting(now_ms + (unit_ms * 3));
+            key_on();
+            TX_CONTROLLER.set_transmitting(now_ms + on_ms, space_ms);
+            record_activity();
+            tx_debug!("🟢 Dah start: {}ms", on_ms);
+        }
+        
+        Element::CharSpace | Element::WordSpace => {
+            // Gap counts from the last key-up; a late marker adds no delay
+            let key_up_ms = LAST_KEY_UP_MS.load(Ordering::Relaxed);
+            let next_allowed = key_up_ms.wrapping_add(space_ms);
+            if next_allowed.wrapping_sub(now_ms) as i32 > 0 {
+                TX_CONTROLLER.set_idle_with_constraint(next_allowed);
+            }
             record_activity();
-            tx_debug!("🟢 Dah start: {}ms", unit_ms * 3);
+            tx_debug!("⏸️ Space: {}ms from key-up", space_ms);
         }
         
-        Element::CharSpace => {
-            TX_CONTROLLER.set_idle_with_constraint(now_ms + (unit_ms * 2));
+        Element::KeyDown => {
+            // Bug mode Dah lever or straight key: stay keyed until KeyUp arrives
+            key_on();
+            TX_CONTROLLER.set_idle_with_constraint(now_ms);
+            record_activity();
+            tx_debug!("🟢 Manual key down");
+        }
+        
+        Element::KeyUp => {
+            KEY_OUTPUT.set_low();
+            STATUS_LED.set_low();
+            SIDETONE_PWM.set_duty(0);
+            LAST_KEY_UP_MS.store(now_ms, Ordering::Relaxed);
+            TX_CONTROLLER.set_idle_with_constraint(now_ms);
             record_activity();
-            tx_debug!("⏸️ CharSpace: +{}ms", unit_ms * 2);
+            tx_debug!("🔴 Manual key up");
         }
     }
 }
@@ -552,17 +1192,24 @@ fn end_element_transmission(now_ms: u32) {
     KEY_OUTPUT.set_low();
     STATUS_LED.set_low();
     SIDETONE_PWM.set_duty(0);
+    LAST_KEY_UP_MS.store(now_ms, Ordering::Relaxed);
+    notify_keyer_fsm(|fsm| fsm.element_finished(get_current_instant()));
     
-    let unit_ms = get_unit_duration_ms();
-    TX_CONTROLLER.set_idle_with_constraint(now_ms + unit_ms);
+    let space_ms = TX_CONTROLLER.space_after();
+    TX_CONTROLLER.set_idle_with_constraint(now_ms + space_ms);
     
-    tx_debug!("🔴 Element end, space: {}ms", unit_ms);
+    tx_debug!("🔴 Element end, space: {}ms", space_ms);
+}
+
+/// No elements waiting to be sent
+fn element_queue_empty() -> bool {
+    unsafe { ELEMENT_QUEUE.is_empty() }
 }
 
 /// Check if can enter low power mode
 fn can_enter_low_power(now_ms: u32) -> bool {
     let tx_idle = TX_CONTROLLER.is_idle();
-    let queue_empty = unsafe { ELEMENT_QUEUE.is_empty() };
+    let queue_empty = element_queue_empty();
     let no_pending_events = SYSTEM_EVENTS.load(Ordering::Relaxed) == 0;
     let last_activity = LAST_ACTIVITY_MS.load(Ordering::Relaxed);
     let idle_long_enough = now_ms.saturating_sub(last_activity) >= 5000;
@@ -578,8 +1225,10 @@ fn debug_heartbeat(last_heartbeat: &mut Instant) {
         info!("💓 Heartbeat - Tx: {}, Queue: {}, Activity: {}ms ago", 
               TX_CONTROLLER.is_transmitting(),
               unsafe { ELEMENT_QUEUE.len() },
-              now_instant.as_millis() - LAST_ACTIVITY_MS.load(Ordering::Relaxed) as i64);
+              now_instant.as_millis().saturating_sub(u64::from(LAST_ACTIVITY_MS.load(Ordering::Relaxed))));
         *last_heartbeat = now_instant;
+        #[cfg(feature = "trace")]
+        paddle_trace::log();
     }
 }
 
@@ -603,23 +1252,36 @@ fn main_loop() {
         // Phase 1: Paddle change processing (highest priority)
         if PADDLE_CHANGED.load(Ordering::Relaxed) {
             update_paddle_state();
-            update_keyer_fsm();
+            if !update_beacon() {
+                update_keyer_fsm();
+            }
             last_keyer_update = now_ms;
         }
         
-        // Phase 2: Periodic FSM update (10ms cycle)
+        // Phase 2: Periodic FSM update (10ms cycle); a beacon replaces the paddles
         else if now_ms.wrapping_sub(last_keyer_update) >= 10 {
-            update_keyer_fsm();
+            #[cfg(feature = "speed-pot")]
+            speed_pot::update();
+            if !update_beacon() {
+                update_keyer_fsm();
+            }
             last_keyer_update = now_ms;
         }
         
         // Phase 3: Transmission FSM update (always active)
         update_transmission_fsm(now_ms);
+        #[cfg(feature = "cli")]
+        serial_cli::update();
         
-        // Phase 4: Debug heartbeat
+        // Phase 4: Settings save (flash programming stalls the CPU, so only when idle)
+        if TX_CONTROLLER.is_idle() && element_queue_empty() {
+            update_settings();
+        }
+        
+        // Phase 5: Debug heartbeat
         debug_heartbeat(&mut last_heartbeat);
         
-        // Phase 5: Power saving
+        // Phase 6: Power saving
         if can_enter_low_power(now_ms) {
             unsafe { riscv::asm::wfi(); }
         }
@@ -634,6 +1296,11 @@ fn hardware_init() {
     configure_exti_interrupts();
     configure_pwm_sidetone();
     initialize_keyer_fsm();
+    initialize_beacon();
+    #[cfg(feature = "speed-pot")]
+    speed_pot::configure();
+    #[cfg(feature = "cli")]
+    serial_cli::configure();
     
     info!("✅ Hardware initialization complete");
 }
@@ -814,6 +1481,13 @@ extern "C" fn SysTick() {
     // Idle time continues WFI for maximum power savings
 }
 
+/// USART1 interrupt handler for the serial command line
+#[cfg(feature = "cli")]
+#[no_mangle]
+extern "C" fn USART1_IRQHandler() {
+    serial_cli::receive();
+}
+
 /// EXTI interrupt handler for paddle edges (new architecture)
 #[no_mangle]
 extern "C" fn EXTI7_0_IRQHandler() {
@@ -2,7 +2,8 @@
 MEMORY
 {
   /* CH32V203C8T6 has 64K flash and 20K RAM */
-  FLASH : ORIGIN = 0x08000000, LENGTH = 64K
+  /* The last 8K (0x0800E000, two 4K pages) hold persistent settings */
+  FLASH : ORIGIN = 0x08000000, LENGTH = 56K
   RAM : ORIGIN = 0x20000000, LENGTH = 20K
 }
 
@@ -332,12 +332,230 @@ pub mod pins {
     
     /// Optional sidetone output pin
     pub const SIDETONE_PIN: u8 = 3; // PA3
+
+    /// Serial command line (USART1)
+    pub const UART_TX_PIN: u8 = 9; // PA9
+    pub const UART_RX_PIN: u8 = 10; // PA10
+}
+
+/// Flash controller (FPEC) driver for the settings pages
+pub mod flash {
+    use keyer_core::storage::{ConfigStore, StorageError};
+
+    const FLASH_BASE: u32 = 0x4002_2000;
+    const FLASH_KEYR: u32 = 0x04;
+    const FLASH_STATR: u32 = 0x0C;
+    const FLASH_CTLR: u32 = 0x10;
+    const FLASH_ADDR: u32 = 0x14;
+
+    const KEY1: u32 = 0x4567_0123;
+    const KEY2: u32 = 0xCDEF_89AB;
+
+    const STATR_BSY: u32 = 1 << 0;
+    const STATR_WRPRTERR: u32 = 1 << 4;
+    const STATR_EOP: u32 = 1 << 5;
+    const CTLR_PG: u32 = 1 << 0;
+    const CTLR_PER: u32 = 1 << 1;
+    const CTLR_STRT: u32 = 1 << 6;
+    const CTLR_LOCK: u32 = 1 << 7;
+
+    /// Settings pages: the last 8KB of flash, excluded from FLASH in memory.x
+    pub const SETTINGS_BASE: u32 = 0x0800_E000;
+    /// Standard erase page size
+    pub const PAGE_SIZE: usize = 4096;
+    /// Pages used for wear leveling
+    pub const PAGES: usize = 2;
+
+    /// Settings pages of the on-chip flash
+    pub struct Ch32v203Flash;
+
+    impl Ch32v203Flash {
+        /// Access the settings pages (no hardware setup needed)
+        pub const fn new() -> Self {
+            Self
+        }
+
+        fn reg(offset: u32) -> *mut u32 {
+            (FLASH_BASE + offset) as *mut u32
+        }
+
+        fn address(page: usize, offset: usize, len: usize) -> Result<u32, StorageError> {
+            if page >= PAGES || offset + len > PAGE_SIZE {
+                return Err(StorageError::OutOfRange);
+            }
+            Ok(SETTINGS_BASE + (page * PAGE_SIZE + offset) as u32)
+        }
+
+        /// Run a program/erase operation with the controller unlocked
+        fn unlocked(ctlr_bits: u32, op: impl FnOnce() -> Result<(), StorageError>) -> Result<(), StorageError> {
+            unsafe {
+                if core::ptr::read_volatile(Self::reg(FLASH_CTLR)) & CTLR_LOCK != 0 {
+                    core::ptr::write_volatile(Self::reg(FLASH_KEYR), KEY1);
+                    core::ptr::write_volatile(Self::reg(FLASH_KEYR), KEY2);
+                }
+                let ctlr = core::ptr::read_volatile(Self::reg(FLASH_CTLR));
+                core::ptr::write_volatile(Self::reg(FLASH_CTLR), ctlr | ctlr_bits);
+                let result = op();
+                let ctlr = core::ptr::read_volatile(Self::reg(FLASH_CTLR));
+                core::ptr::write_volatile(Self::reg(FLASH_CTLR), (ctlr & !ctlr_bits) | CTLR_LOCK);
+                result
+            }
+        }
+
+        /// Wait for the operation in progress and clear its status
+        fn wait() -> Result<(), StorageError> {
+            unsafe {
+                while core::ptr::read_volatile(Self::reg(FLASH_STATR)) & STATR_BSY != 0 {}
+                let statr = core::ptr::read_volatile(Self::reg(FLASH_STATR));
+                core::ptr::write_volatile(Self::reg(FLASH_STATR), statr & (STATR_EOP | STATR_WRPRTERR));
+                if statr & STATR_WRPRTERR != 0 {
+                    return Err(StorageError::Device);
+                }
+            }
+            Ok(())
+        }
+    }
+
+    impl Default for Ch32v203Flash {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl ConfigStore for Ch32v203Flash {
+        fn page_size(&self) -> usize {
+            PAGE_SIZE
+        }
+
+        fn page_count(&self) -> usize {
+            PAGES
+        }
+
+        fn read(&mut self, page: usize, offset: usize, buf: &mut [u8]) -> Result<(), StorageError> {
+            let address = Self::address(page, offset, buf.len())?;
+            for (i, byte) in buf.iter_mut().enumerate() {
+                *byte = unsafe { core::ptr::read_volatile((address as usize + i) as *const u8) };
+            }
+            Ok(())
+        }
+
+        fn erase(&mut self, page: usize) -> Result<(), StorageError> {
+            let address = Self::address(page, 0, PAGE_SIZE)?;
+            Self::unlocked(CTLR_PER, || unsafe {
+                core::ptr::write_volatile(Self::reg(FLASH_ADDR), address);
+                let ctlr = core::ptr::read_volatile(Self::reg(FLASH_CTLR));
+                core::ptr::write_volatile(Self::reg(FLASH_CTLR), ctlr | CTLR_STRT);
+                Self::wait()
+            })
+        }
+
+        fn write(&mut self, page: usize, offset: usize, data: &[u8]) -> Result<(), StorageError> {
+            let address = Self::address(page, offset, data.len())?;
+            let start = address & !1;
+            let end = address + data.len() as u32;
+            // Programmed in halfwords; bytes outside `data` are written as 0xFF (unchanged)
+            Self::unlocked(CTLR_PG, || {
+                for halfword in (start..end).step_by(2) {
+                    let byte = |a: u32| match a.checked_sub(address) {
+                        Some(i) if a < end => data[i as usize],
+                        _ => 0xFF,
+                    };
+                    let value = u16::from_le_bytes([byte(halfword), byte(halfword + 1)]);
+                    if value == 0xFFFF {
+                        continue;
+                    }
+                    let target = halfword as usize as *mut u16;
+                    unsafe {
+                        if core::ptr::read_volatile(target) != 0xFFFF {
+                            return Err(StorageError::NotErased);
+                        }
+                        core::ptr::write_volatile(target, value);
+                    }
+                    Self::wait()?;
+                }
+                Ok(())
+            })
+        }
+    }
+}
+
+/// USART1 driver for the serial command line (PA9 = TX, PA10 = RX)
+pub mod uart {
+    const RCC_APB2PCENR: u32 = 0x4002_1018;
+    const GPIOA_CFGHR: u32 = 0x4001_0804;
+    const USART1_BASE: u32 = 0x4001_3800;
+    const USART_STATR: u32 = 0x00;
+    const USART_DATAR: u32 = 0x04;
+    const USART_BRR: u32 = 0x08;
+    const USART_CTLR1: u32 = 0x0C;
+
+    const STATR_RXNE: u32 = 1 << 5;
+    const STATR_TXE: u32 = 1 << 7;
+    const CTLR1_RE: u32 = 1 << 2;
+    const CTLR1_TE: u32 = 1 << 3;
+    const CTLR1_UE: u32 = 1 << 13;
+
+    /// APB2 clock: HSI after reset, no PLL set up
+    pub const PCLK2_HZ: u32 = 8_000_000;
+    /// Command line baud rate
+    pub const BAUD: u32 = 115_200;
+
+    /// Polled USART1, 8N1
+    pub struct Ch32v203Uart;
+
+    impl Ch32v203Uart {
+        /// Enable USART1 on PA9/PA10
+        pub fn new() -> Self {
+            unsafe {
+                let apb2 = RCC_APB2PCENR as *mut u32;
+                // Bit 2 = GPIOA, bit 14 = USART1
+                core::ptr::write_volatile(apb2, core::ptr::read_volatile(apb2) | (1 << 2) | (1 << 14));
+
+                // PA9: AF push-pull 50MHz, PA10: input with pull-up
+                let cfghr = GPIOA_CFGHR as *mut u32;
+                let cfg = core::ptr::read_volatile(cfghr) & !(0xFF << 4);
+                core::ptr::write_volatile(cfghr, cfg | (0xB << 4) | (0x8 << 8));
+                let outdr = (GPIOA_CFGHR + 0x08) as *mut u32;
+                core::ptr::write_volatile(outdr, core::ptr::read_volatile(outdr) | (1 << 10));
+
+                core::ptr::write_volatile((USART1_BASE + USART_BRR) as *mut u32, (PCLK2_HZ + BAUD / 2) / BAUD);
+                core::ptr::write_volatile((USART1_BASE + USART_CTLR1) as *mut u32, CTLR1_UE | CTLR1_TE | CTLR1_RE);
+            }
+            Self
+        }
+
+        fn status() -> u32 {
+            unsafe { core::ptr::read_volatile((USART1_BASE + USART_STATR) as *const u32) }
+        }
+
+        /// Received byte, if any
+        pub fn read(&mut self) -> Option<u8> {
+            if Self::status() & STATR_RXNE == 0 {
+                return None;
+            }
+            Some(unsafe { core::ptr::read_volatile((USART1_BASE + USART_DATAR) as *const u32) } as u8)
+        }
+
+        /// Send bytes, waiting for the transmitter
+        pub fn write(&mut self, bytes: &[u8]) {
+            for &byte in bytes {
+                while Self::status() & STATR_TXE == 0 {}
+                unsafe { core::ptr::write_volatile((USART1_BASE + USART_DATAR) as *mut u32, u32::from(byte)) };
+            }
+        }
+    }
+
+    impl Default for Ch32v203Uart {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
 }
 
 /// CH32V203 memory layout information
 pub mod memory {
-    /// Available Flash memory (actual usable)
-    pub const FLASH_SIZE: u32 = 60 * 1024; // 60KB usable
+    /// Available Flash memory (actual usable, settings pages excluded)
+    pub const FLASH_SIZE: u32 = 56 * 1024; // 56KB usable
     
     /// Available RAM
     pub const RAM_SIZE: u32 = 20 * 1024; // 20KB
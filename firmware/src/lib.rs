@@ -18,7 +18,7 @@ pub use crate::tasks::*;
 
 // Mock hardware module
 pub mod mock_hardware {
-    use keyer_core::hal::{InputPaddle, OutputKey, HalError};
+    use keyer_core::hal::{AnalogSpeedInput, InputPaddle, OutputKey, HalError};
     
     /// Mock paddle implementation
     #[derive(Debug)]
@@ -95,12 +95,45 @@ pub mod mock_hardware {
         }
     }
     
+    /// Mock speed potentiometer (12-bit ADC)
+    #[derive(Debug)]
+    pub struct MockSpeedPot {
+        raw: u16,
+    }
+    
+    impl MockSpeedPot {
+        pub fn new() -> Self {
+            Self { raw: 2048 }
+        }
+        
+        /// Set the raw reading for testing
+        pub fn set_raw(&mut self, raw: u16) {
+            self.raw = raw;
+        }
+    }
+    
+    impl AnalogSpeedInput for MockSpeedPot {
+        type Error = HalError;
+    
+        fn read_raw(&mut self) -> Result<u16, Self::Error> {
+            Ok(self.raw)
+        }
+    
+        fn full_scale(&self) -> u16 {
+            4095
+        }
+    }
+    
     /// Mock hardware collection
     #[derive(Debug)]
     pub struct MockKeyerHal {
         pub dit_paddle: MockPaddle,
         pub dah_paddle: MockPaddle,
         pub key_output: MockKeyOutput,
+        /// Plays message memory 1
+        pub memory_button: MockPaddle,
+        /// Speed potentiometer
+        pub speed_pot: MockSpeedPot,
     }
     
     impl MockKeyerHal {
@@ -111,6 +144,8 @@ pub mod mock_hardware {
                 dit_paddle: MockPaddle::new(),
                 dah_paddle: MockPaddle::new(), 
                 key_output: MockKeyOutput::new(),
+                memory_button: MockPaddle::new(),
+                speed_pot: MockSpeedPot::new(),
             }
         }
     }
@@ -125,54 +160,187 @@ pub mod tasks {
     #[embassy_executor::task]
     pub async fn evaluator_task_wrapper(
         paddle: &'static PaddleInput,
+        feedback: &'static SenderFeedback,
         producer: Producer<'static, Element, 8>,
-        config: KeyerConfig,
+        config: &'static SharedConfig,
+        playback: &'static PlaybackControl,
     ) {
         #[cfg(feature = "defmt")]
         defmt::info!("🧠 Evaluator task started");
-        keyer_core::fsm::evaluator_task::<8>(paddle, producer, config).await;
+        keyer_core::fsm::evaluator_task::<8>(paddle, feedback, producer, config, playback).await;
+    }
+
+    /// Beacon task - replaces the evaluator in beacon mode
+    #[embassy_executor::task]
+    pub async fn beacon_task(
+        mut producer: Producer<'static, Element, 8>,
+        mut beacon: Beacon,
+    ) {
+        #[cfg(feature = "defmt")]
+        defmt::info!("📡 Beacon task started");
+        loop {
+            beacon.update(embassy_time::Instant::now(), &mut producer);
+            embassy_time::Timer::after(Duration::from_millis(10)).await;
+        }
+    }
+
+    /// Memory button task - a press plays the first message slot
+    #[embassy_executor::task]
+    pub async fn memory_button_task(
+        mut button: crate::mock_hardware::MockPaddle,
+        memory: &'static MessageMemory<4>,
+        mut macros: ContestMacros,
+        playback: &'static PlaybackControl,
+        save: &'static SaveRequest,
+    ) {
+        use keyer_core::hal::InputPaddle;
+
+        let mut was_pressed = false;
+        loop {
+            let pressed = button.is_pressed().unwrap_or(false);
+            if pressed && !was_pressed {
+                // The callsign may have been changed with `CALL`
+                macros.set_callsign(&save.callsign()).ok();
+                // An empty slot is simply ignored
+                let serial = macros.serial();
+                let _result = playback.play_slot(memory, 0, &mut macros);
+                // Save a serial sent with `{NR}` so it does not repeat after a power cycle
+                if macros.serial() != serial {
+                    save.request_serial(macros.serial());
+                }
+                #[cfg(feature = "defmt")]
+                defmt::info!("📼 Memory 1: {:?}", _result.is_ok());
+            }
+            was_pressed = pressed;
+            embassy_time::Timer::after(Duration::from_millis(20)).await;
+        }
+    }
+
+    /// Speed pot task - publishes knob changes; the keyer applies them between elements
+    #[embassy_executor::task]
+    pub async fn speed_pot_task(
+        mut pot: crate::mock_hardware::MockSpeedPot,
+        range: SpeedRange,
+        config: &'static SharedConfig,
+    ) {
+        use keyer_core::hal::AnalogSpeedInput;
+
+        let Ok(mut knob) = SpeedKnob::new(range, pot.full_scale()) else {
+            return;
+        };
+        loop {
+            if let Ok(Some(_wpm)) = knob.poll(&mut pot, config) {
+                #[cfg(feature = "defmt")]
+                defmt::debug!("🎚️ Speed: {} WPM", _wpm);
+            }
+            embassy_time::Timer::after(Duration::from_millis(20)).await;
+        }
+    }
+
+    /// Settings task - saves configuration changes to flash once they settle, or on request
+    #[embassy_executor::task]
+    pub async fn settings_task(
+        mut log: SettingsLog<crate::ch32v203_hardware::flash::Ch32v203Flash>,
+        mut settings: Settings,
+        mode_override: Option<KeyerMode>,
+        config: &'static SharedConfig,
+        save: &'static SaveRequest,
+    ) {
+        let mut autosave = AutoSave::new(config, Duration::from_secs(5));
+        if let Some(mode) = mode_override {
+            autosave = autosave.with_mode_override(mode, settings.keyer.mode);
+        }
+        loop {
+            let requested = save.take();
+            let changed = save.take_changes(&mut settings);
+            let due = match requested {
+                true => autosave.flush(config),
+                false => autosave.poll(embassy_time::Instant::now(), config),
+            };
+            if let Some(keyer) = due {
+                settings.keyer = keyer;
+            }
+            if due.is_some() || changed {
+                let _result = log.save(&settings);
+                #[cfg(feature = "defmt")]
+                defmt::info!("💾 Settings saved: {:?}", _result.is_ok());
+            }
+            embassy_time::Timer::after(Duration::from_millis(500)).await;
+        }
     }
     
+    /// Serial command line task - one response line per command line
+    #[embassy_executor::task]
+    pub async fn cli_task(
+        mut uart: crate::ch32v203_hardware::uart::Ch32v203Uart,
+        config: &'static SharedConfig,
+        playback: &'static PlaybackControl,
+        save: &'static SaveRequest,
+    ) {
+        let mut reader: LineReader = LineReader::new();
+        loop {
+            while let Some(byte) = uart.read() {
+                if let Some(line) = reader.push(byte) {
+                    let response = keyer_core::cli::handle_line(line, config, playback, save);
+                    uart.write(response.as_bytes());
+                    uart.write(b"\r\n");
+                }
+            }
+            embassy_time::Timer::after(Duration::from_millis(5)).await;
+        }
+    }
+
     /// Sender task for key output
     #[embassy_executor::task]
     pub async fn sender_task_with_mock(
         mut consumer: Consumer<'static, Element, 8>,
-        unit: Duration,
+        feedback: &'static SenderFeedback,
+        config: &'static SharedConfig,
         key_output: &'static mut crate::mock_hardware::MockKeyOutput,
     ) {
         #[cfg(feature = "defmt")]
         defmt::info!("📤 Sender task started");
     
+        // Spaces are timed from the last key-up
+        let mut last_key_up = embassy_time::Instant::now();
+
         loop {
+            // Timing follows runtime configuration changes element by element
+            let keyer_config = config.get().unwrap_or_default();
             if let Some(element) = consumer.dequeue() {
-                let (on_time, element_name) = match element {
-                    Element::Dit => (unit, "Dit"),
-                    Element::Dah => (unit * 3, "Dah"),
-                    Element::CharSpace => (Duration::from_millis(0), "Space"),
-                };
-    
-                if element.is_keyed() {
+                let timing = keyer_config.element_timing(element);
+
+                if element.is_manual() {
+                    // Bug mode straight key: follow the lever, no timing (key-up always releases)
+                    key_output.set_state(element == Element::KeyDown && keyer_config.tx_enabled).ok();
+                    if element == Element::KeyUp {
+                        last_key_up = embassy_time::Instant::now();
+                    }
+                } else if element.is_keyed() {
                     #[cfg(feature = "defmt")]
-                    defmt::debug!("📡 Sending {}", element_name);
+                    defmt::debug!("📡 Sending {}", if element == Element::Dit { "Dit" } else { "Dah" });
                     
-                    // Key down
-                    key_output.set_state(true).ok();
-                    embassy_time::Timer::after(on_time).await;
+                    // Key down; sidetone only while tx_enabled is off (command mode)
+                    key_output.set_state(keyer_config.tx_enabled).ok();
+                    feedback.element_started();
+                    embassy_time::Timer::after(timing.key_down).await;
                     
-                    // Key up
+                    // Key up - evaluator decides the next element from here
                     key_output.set_state(false).ok();
+                    feedback.element_finished();
+                    last_key_up = embassy_time::Instant::now();
                     
-                    // Inter-element space (except for CharSpace)
-                    embassy_time::Timer::after(unit).await;
+                    // Inter-element space, shortened or stretched by weighting
+                    embassy_time::Timer::after(timing.space).await;
                 } else {
-                    // Character space - just wait
+                    // Character/word space - wait out whatever remains of the gap since key-up
                     #[cfg(feature = "defmt")]
-                    defmt::debug!("⏸️ Character space");
-                    embassy_time::Timer::after(unit * 3).await;
+                    defmt::debug!("⏸️ Space");
+                    embassy_time::Timer::at(last_key_up + timing.space).await;
                 }
             } else {
                 // No elements in queue, brief pause
-                embassy_time::Timer::after(unit / 8).await;
+                embassy_time::Timer::after(keyer_config.unit / 8).await;
             }
         }
     }
@@ -20,6 +20,11 @@ use rustykeyer_firmware::*;
 
 // Static resources
 static PADDLE: PaddleInput = PaddleInput::new();
+static SENDER_FEEDBACK: SenderFeedback = SenderFeedback::new();
+static KEYER_CONFIG: SharedConfig = SharedConfig::new();
+static PLAYBACK: PlaybackControl = PlaybackControl::new();
+static SAVE_REQUEST: SaveRequest = SaveRequest::new();
+static MESSAGES: StaticCell<MessageMemory<4>> = StaticCell::new();
 static KEY_QUEUE: StaticCell<Queue<Element, 8>> = StaticCell::new();
 
 /// Main firmware entry point
@@ -29,22 +34,60 @@ async fn main(spawner: Spawner) {
     defmt::info!("🔧 Rusty Keyer Firmware Starting...");
 
     // Initialize CH32V203 hardware
-    let _hal = init_hardware().await;
+    let mut hal = init_hardware().await;
     #[cfg(feature = "defmt")]
     defmt::info!("✅ Hardware initialized");
 
-    // Initialize keyer configuration - optimized for RAM
+    // Default keyer configuration - optimized for RAM
     let config = KeyerConfig {
         mode: KeyerMode::ModeA,  // Unified to ModeA for compatibility
         char_space_enabled: true,
         unit: Duration::from_millis(60), // 20 WPM
         debounce_ms: 10, // Unified 10ms debounce for noise immunity
         queue_size: 8,  // Match actual queue size
+        squeeze_tie: SqueezeTieRule::Dit,
+        sidetone_enabled: true,
+        dah_ratio_tenths: 30,
+        weighting: 50,
+        farnsworth_wpm: None,
+        paddle_swap: false,
+        single_lever: false,
+        tx_enabled: true,
     };
+
+    // Saved settings override the defaults
+    // No default callsign: `{CALL}` reports NoCallsign until the operator sets one with `CALL`
+    // Beacon (off until enabled with `BEACON`) sends slot 2 with a 5 s carrier
+    let beacon = BeaconSettings { slot: 2, carrier_s: 5, ..BeaconSettings::default() };
+    let defaults = Settings { keyer: config, beacon, ..Settings::default() };
+    let mut log = SettingsLog::new(flash::Ch32v203Flash::new()).ok();
+    let settings = log.as_mut().map_or_else(|| defaults.clone(), |log| log.load(&defaults));
+    let mut config = settings.keyer;
+    #[cfg(feature = "defmt")]
+    defmt::info!("💾 Settings loaded");
+
+    // A mono plug shorts the ring (Dah) contact: treat it as a straight key
+    // for this session only; the stored mode is what gets saved
+    let mode_override = hal.dah_paddle.is_pressed().unwrap_or(false).then_some(KeyerMode::StraightKey);
+    if let Some(mode) = mode_override {
+        config.mode = mode;
+    }
+    KEYER_CONFIG.set(config);
     #[cfg(feature = "defmt")]
     defmt::info!("⚙️ Keyer config: {:?} WPM, Mode: {:?}", 
                 config.wpm(), config.mode);
 
+    // Message memories (slot 0 is played by the memory button)
+    let mut macros = ContestMacros::new();
+    macros.set_callsign(&settings.callsign).ok();
+    SAVE_REQUEST.set_callsign(&settings.callsign).ok();
+    macros.set_serial(settings.serial);
+    let messages = MESSAGES.init(MessageMemory::new());
+    messages.set(0, "CQ TEST {CALL} {CALL} TEST").ok();
+    messages.set(1, "TU 5NN {NR}").ok();
+    messages.set(2, "VVV DE {CALL}/B").ok();
+    let messages: &'static MessageMemory<4> = messages;
+
     // Initialize element queue
     let queue = KEY_QUEUE.init(Queue::new());
     let (producer, consumer) = queue.split();
@@ -53,8 +96,29 @@ async fn main(spawner: Spawner) {
     #[cfg(feature = "defmt")]
     defmt::info!("🚀 Spawning keyer tasks...");
     
-    spawner.spawn(evaluator_task_spawn(&PADDLE, producer, config)).unwrap();
-    spawner.spawn(sender_task(consumer, config.unit)).unwrap();
+    // An enabled beacon replaces the paddle keyer; without a usable message
+    // (e.g. `{CALL}` and no callsign) the keyer starts as usual
+    let beacon = match settings.beacon.enabled {
+        true => Beacon::from_slot(messages, &mut macros, &settings.beacon)
+            .inspect_err(|_error| {
+                #[cfg(feature = "defmt")]
+                defmt::warn!("📡 Beacon message unusable: {}", defmt::Debug2Format(_error));
+            })
+            .ok(),
+        false => None,
+    };
+    match beacon {
+        Some(beacon) => spawner.spawn(beacon_task(producer, beacon)).unwrap(),
+        None => spawner.spawn(evaluator_task_spawn(&PADDLE, &SENDER_FEEDBACK, producer, &KEYER_CONFIG, &PLAYBACK)).unwrap(),
+    }
+    spawner.spawn(sender_task(consumer, &SENDER_FEEDBACK, &KEYER_CONFIG)).unwrap();
+    spawner.spawn(memory_button_task(hal.memory_button, messages, macros, &PLAYBACK, &SAVE_REQUEST)).unwrap();
+    #[cfg(feature = "speed-pot")]
+    spawner.spawn(speed_pot_task(hal.speed_pot, SpeedRange::default(), &KEYER_CONFIG)).unwrap();
+    spawner.spawn(cli_task(uart::Ch32v203Uart::new(), &KEYER_CONFIG, &PLAYBACK, &SAVE_REQUEST)).unwrap();
+    if let Some(log) = log {
+        spawner.spawn(settings_task(log, settings, mode_override, &KEYER_CONFIG, &SAVE_REQUEST)).unwrap();
+    }
 
     #[cfg(feature = "defmt")]
     defmt::info!("✨ Keyer firmware ready!");
@@ -70,12 +134,14 @@ async fn main(spawner: Spawner) {
 #[embassy_executor::task]
 async fn evaluator_task_spawn(
     paddle: &'static PaddleInput,
+    feedback: &'static SenderFeedback,
     producer: heapless::spsc::Producer<'static, Element, 8>,
-    config: KeyerConfig,
+    config: &'static SharedConfig,
+    playback: &'static PlaybackControl,
 ) {
     #[cfg(feature = "defmt")]
     defmt::info!("🧠 Evaluator task started");
-    evaluator_task::<8>(paddle, producer, config).await;
+    evaluator_task::<8>(paddle, feedback, producer, config, playback).await;
 }
 
 /// Initialize hardware abstraction layer
@@ -93,43 +159,55 @@ async fn init_hardware() -> MockKeyerHal {
 #[embassy_executor::task]
 async fn sender_task(
     mut consumer: heapless::spsc::Consumer<'static, Element, 8>,
-    unit: Duration,
+    feedback: &'static SenderFeedback,
+    config: &'static SharedConfig,
 ) {
     #[cfg(feature = "defmt")]
     defmt::info!("📤 Sender task started");
     // Use actual CH32V203 key output (through HAL)
     // Note: KeyOutput will be handled by HAL instance
 
+    // Spaces are timed from the last key-up
+    let mut last_key_up = embassy_time::Instant::now();
+
     loop {
+        // Timing follows runtime configuration changes element by element
+        let keyer_config = config.get().unwrap_or_default();
         if let Some(element) = consumer.dequeue() {
-            let (on_time, element_name) = match element {
-                Element::Dit => (unit, "Dit"),
-                Element::Dah => (unit * 3, "Dah"),
-                Element::CharSpace => (Duration::from_millis(0), "Space"),
-            };
-
-            if element.is_keyed() {
+            let timing = keyer_config.element_timing(element);
+
+            if element.is_manual() {
+                // Bug mode straight key: follow the lever, no timing (key-up always releases)
+                // hal.set_key_output(element == Element::KeyDown && keyer_config.tx_enabled);
+                if element == Element::KeyUp {
+                    last_key_up = embassy_time::Instant::now();
+                }
+            } else if element.is_keyed() {
                 #[cfg(feature = "defmt")]
-                defmt::debug!("📡 Sending {}", element_name);
+                defmt::debug!("📡 Sending {}", if element == Element::Dit { "Dit" } else { "Dah" });
                 
                 // Key down - TODO: Access HAL instance for actual output
-                // hal.set_key_output(true);
-                embassy_time::Timer::after(on_time).await;
+                // Sidetone only while tx_enabled is off (command mode)
+                // hal.set_key_output(keyer_config.tx_enabled);
+                feedback.element_started();
+                embassy_time::Timer::after(timing.key_down).await;
                 
-                // Key up
+                // Key up - evaluator decides the next element from here
                 // hal.set_key_output(false);
+                feedback.element_finished();
+                last_key_up = embassy_time::Instant::now();
                 
-                // Inter-element space (except for CharSpace)
-                embassy_time::Timer::after(unit).await;
+                // Inter-element space, shortened or stretched by weighting
+                embassy_time::Timer::after(timing.space).await;
             } else {
-                // Character space - just wait
+                // Character/word space - wait out whatever remains of the gap since key-up
                 #[cfg(feature = "defmt")]
-                defmt::debug!("⏸️ Character space");
-                embassy_time::Timer::after(unit * 3).await;
+                defmt::debug!("⏸️ Space");
+                embassy_time::Timer::at(last_key_up + timing.space).await;
             }
         } else {
             // No elements in queue, brief pause
-            embassy_time::Timer::after(unit / 8).await;
+            embassy_time::Timer::after(keyer_config.unit / 8).await;
         }
     }
 }
\ No newline at end of file
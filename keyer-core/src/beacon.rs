@@ -0,0 +1,301 @@
+//! Beacon / repeater-ID mode
+//!
+//! A [`Beacon`] repeats a message on a fixed interval, optionally followed by a
+//! long carrier (fox hunts). It replaces the FSM as the producer of the element
+//! queue, so any sender that handles `KeyDown`/`KeyUp` can key it.
+//!
+//! The firmware starts a beacon at power-up when the stored [`BeaconSettings`]
+//! enable it; the text comes from a message memory slot, macros expanded.
+
+use heapless::spsc::Producer;
+use crate::encoder::EncoderState;
+use crate::hal::{Duration, Instant};
+use crate::macros::ContestMacros;
+use crate::message::{self, MemoryError, Message, MessageMemory, PLAYBACK_LOOKAHEAD};
+use crate::types::Element;
+
+/// Beacon schedule
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BeaconConfig {
+    /// Time from the start of one transmission to the start of the next
+    pub interval: Duration,
+    /// Carrier keyed after the message
+    pub carrier: Option<Duration>,
+}
+
+impl Default for BeaconConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_millis(60_000),
+            carrier: None,
+        }
+    }
+}
+
+/// Beacon settings kept across power cycles
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BeaconSettings {
+    /// Run the beacon instead of the paddle keyer from power-up
+    pub enabled: bool,
+    /// Message memory slot with the beacon text
+    pub slot: u8,
+    /// Seconds from the start of one transmission to the start of the next
+    pub interval_s: u16,
+    /// Carrier after the message in seconds, 0 = none
+    pub carrier_s: u8,
+}
+
+impl BeaconSettings {
+    /// Beacon schedule for these settings
+    pub fn schedule(&self) -> BeaconConfig {
+        BeaconConfig {
+            interval: Duration::from_millis(u64::from(self.interval_s) * 1000),
+            carrier: (self.carrier_s != 0).then(|| Duration::from_millis(u64::from(self.carrier_s) * 1000)),
+        }
+    }
+}
+
+impl Default for BeaconSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            slot: 0,
+            interval_s: 60,
+            carrier_s: 0,
+        }
+    }
+}
+
+/// What the beacon is currently doing
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BeaconPhase {
+    /// Waiting for the next transmission
+    Waiting,
+    /// Queueing message elements
+    Message,
+    /// Carrier queued or keyed
+    Carrier,
+}
+
+#[derive(Copy, Clone, Debug)]
+enum BeaconState {
+    Waiting,
+    Message,
+    /// `KeyDown` queued; keyed once the sender has taken it
+    CarrierQueued,
+    Carrier(Instant),
+}
+
+/// Repeats a message on a schedule
+#[derive(Clone, Debug)]
+pub struct Beacon {
+    text: Message,
+    config: BeaconConfig,
+    encoder: EncoderState,
+    state: BeaconState,
+    /// Start of the current or last transmission
+    cycle_start: Option<Instant>,
+}
+
+impl Beacon {
+    /// Create beacon; the first transmission starts at the first update
+    pub fn new(text: &str, config: BeaconConfig) -> Result<Self, MemoryError> {
+        Ok(Self {
+            text: message::to_message(text)?,
+            config,
+            encoder: EncoderState::new(),
+            state: BeaconState::Waiting,
+            cycle_start: None,
+        })
+    }
+
+    /// Create beacon for `settings`, sending their memory slot with macros such as `{CALL}` expanded
+    pub fn from_slot<const SLOTS: usize>(
+        memory: &MessageMemory<SLOTS>,
+        macros: &mut ContestMacros,
+        settings: &BeaconSettings,
+    ) -> Result<Self, MemoryError> {
+        let text = message::expand_slot(memory, usize::from(settings.slot), macros)?;
+        Self::new(&text, settings.schedule())
+    }
+
+    /// Get beacon schedule
+    pub fn config(&self) -> &BeaconConfig {
+        &self.config
+    }
+
+    /// Change beacon schedule (applies from the next transmission)
+    pub fn set_config(&mut self, config: BeaconConfig) {
+        self.config = config;
+    }
+
+    /// Current phase
+    pub fn phase(&self) -> BeaconPhase {
+        match self.state {
+            BeaconState::Waiting => BeaconPhase::Waiting,
+            BeaconState::Message => BeaconPhase::Message,
+            BeaconState::CarrierQueued | BeaconState::Carrier(_) => BeaconPhase::Carrier,
+        }
+    }
+
+    /// Time until the next transmission starts (zero if it is due or running)
+    pub fn time_to_next(&self, now: Instant) -> Duration {
+        match (self.state, self.cycle_start) {
+            (BeaconState::Waiting, Some(start)) => {
+                let elapsed = now.duration_since(start);
+                if elapsed < self.config.interval {
+                    Duration::from_millis(self.config.interval.as_millis() - elapsed.as_millis())
+                } else {
+                    Duration::from_millis(0)
+                }
+            }
+            _ => Duration::from_millis(0),
+        }
+    }
+
+    /// Advance the schedule and queue elements
+    /// Returns the number of elements enqueued
+    pub fn update<const N: usize>(&mut self, now: Instant, queue: &mut Producer<'_, Element, N>) -> usize {
+        match self.state {
+            BeaconState::Waiting => {
+                let due = match self.cycle_start {
+                    Some(start) => now.duration_since(start) >= self.config.interval,
+                    None => true,
+                };
+                if !due || !queue.ready() {
+                    return 0;
+                }
+                self.cycle_start = Some(now);
+                self.encoder = EncoderState::new();
+                self.state = BeaconState::Message;
+                // Keeps back-to-back transmissions apart
+                queue.enqueue(Element::WordSpace).ok();
+                1 + self.update(now, queue)
+            }
+            BeaconState::Message => {
+                // Validated in `new`, so encoding cannot fail
+                let count = self.encoder.fill(&self.text, queue, PLAYBACK_LOOKAHEAD).unwrap_or(0);
+                if !self.encoder.is_finished(&self.text) {
+                    return count;
+                }
+                match self.config.carrier {
+                    // Wait for the message to drain so the carrier gets its own gap
+                    Some(_) if queue.len() == 0 => {
+                        queue.enqueue(Element::WordSpace).ok();
+                        queue.enqueue(Element::KeyDown).ok();
+                        self.state = BeaconState::CarrierQueued;
+                        count + 2
+                    }
+                    Some(_) => count,
+                    None => {
+                        self.state = BeaconState::Waiting;
+                        count
+                    }
+                }
+            }
+            BeaconState::CarrierQueued => {
+                if queue.len() == 0 {
+                    self.state = BeaconState::Carrier(now);
+                }
+                0
+            }
+            BeaconState::Carrier(since) => {
+                let length = self.config.carrier.unwrap_or(Duration::from_millis(0));
+                if now.duration_since(since) < length || queue.enqueue(Element::KeyUp).is_err() {
+                    return 0;
+                }
+                self.state = BeaconState::Waiting;
+                1
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoder::EncodeError;
+    use heapless::spsc::Queue;
+    use heapless::Vec;
+
+    /// Run the beacon with a sender that takes one element per millisecond
+    fn run(beacon: &mut Beacon, from_ms: i64, to_ms: i64) -> Vec<(i64, Element), 64> {
+        let mut queue = Queue::<Element, 4>::new();
+        let (mut producer, mut consumer) = queue.split();
+        let mut sent = Vec::new();
+        for ms in from_ms..to_ms {
+            beacon.update(Instant::from_millis(ms as _), &mut producer);
+            if let Some(element) = consumer.dequeue() {
+                sent.push((ms, element)).unwrap();
+            }
+        }
+        sent
+    }
+
+    #[test]
+    fn test_beacon_repeats_on_interval() {
+        let config = BeaconConfig {
+            interval: Duration::from_millis(1000),
+            carrier: None,
+        };
+        let mut beacon = Beacon::new("E", config).unwrap();
+        assert_eq!(beacon.phase(), BeaconPhase::Waiting);
+
+        let sent = run(&mut beacon, 0, 2500);
+        let starts: Vec<i64, 4> = sent
+            .iter()
+            .filter(|(_, element)| *element == Element::Dit)
+            .map(|(ms, _)| *ms)
+            .collect();
+        assert_eq!(starts.as_slice(), &[1, 1001, 2001]);
+        assert_eq!(beacon.time_to_next(Instant::from_millis(2500)), Duration::from_millis(500));
+        assert_eq!(Beacon::new("<AR", config).err(), Some(MemoryError::Encode(EncodeError::InvalidProsign)));
+    }
+
+    #[test]
+    fn test_beacon_from_slot() {
+        let mut memory = MessageMemory::<2>::new();
+        memory.set(1, "DE {CALL}").unwrap();
+        let mut macros = ContestMacros::new();
+        let settings = BeaconSettings { enabled: true, slot: 1, interval_s: 10, carrier_s: 0 };
+
+        // The callsign comes from the configuration; without one there is no beacon
+        assert_eq!(Beacon::from_slot(&memory, &mut macros, &settings).err(), Some(MemoryError::NoCallsign));
+        macros.set_callsign("T").unwrap();
+        let mut beacon = Beacon::from_slot(&memory, &mut macros, &settings).unwrap();
+        assert_eq!(beacon.config().interval, Duration::from_millis(10_000));
+        assert_eq!(beacon.config().carrier, None);
+        let dahs = run(&mut beacon, 0, 100).iter().filter(|(_, element)| *element == Element::Dah).count();
+        assert_eq!(dahs, 2);  // "DE T": D = -.., T = -
+
+        let empty = BeaconSettings { slot: 0, ..settings };
+        assert_eq!(Beacon::from_slot(&memory, &mut macros, &empty).err(), Some(MemoryError::EmptySlot));
+        assert_eq!(BeaconSettings { carrier_s: 5, ..settings }.schedule().carrier, Some(Duration::from_millis(5000)));
+    }
+
+    #[test]
+    fn test_beacon_carrier() {
+        let config = BeaconConfig {
+            interval: Duration::from_millis(10_000),
+            carrier: Some(Duration::from_millis(3000)),
+        };
+        let mut beacon = Beacon::new("TT", config).unwrap();
+
+        let sent = run(&mut beacon, 0, 5000);
+        let elements: Vec<Element, 16> = sent.iter().map(|(_, element)| *element).collect();
+        assert_eq!(
+            elements.as_slice(),
+            &[
+                Element::WordSpace, Element::Dah, Element::CharSpace, Element::Dah,
+                Element::WordSpace, Element::KeyDown, Element::KeyUp,
+            ]
+        );
+
+        // Carrier is held for its full length once the sender has keyed it
+        let down = sent.iter().find(|(_, e)| *e == Element::KeyDown).unwrap().0;
+        let up = sent.iter().find(|(_, e)| *e == Element::KeyUp).unwrap().0;
+        assert!((3000..=3002).contains(&(up - down)));
+        assert_eq!(beacon.phase(), BeaconPhase::Waiting);
+    }
+}
@@ -0,0 +1,471 @@
+//! Line-oriented serial command protocol
+//!
+//! One command per line, keywords case-insensitive:
+//!
+//! - `WPM 25`, `WEIGHT 55`, `RATIO 33` (dah length in tenths), `FARNS 15` / `FARNS OFF`
+//! - `MODE A|B|SUPER|ULTIMATIC|BUG|STRAIGHT`
+//! - `SIDETONE ON|OFF`, `SWAP ON|OFF`, `TX ON|OFF`
+//! - `SEND CQ TEST`, `STOP`
+//! - `STATUS`, `SAVE`
+//! - `CALL JA1ABC`: own callsign for `{CALL}`, used right away and saved
+//! - `BEACON <slot> [interval_s [carrier_s]]` / `BEACON OFF`: saved, used from the next power-up
+//!
+//! Every line is answered with one response line: `OK`, `OK` followed by
+//! `KEY=VALUE` pairs for `STATUS`, or `ERR` and an error code. Settings changes
+//! go through [`SharedConfig`] like any other runtime change and are refused
+//! with `ERR BUSY` while paddle command mode owns the configuration.
+
+use core::fmt::Write;
+use heapless::{String, Vec};
+use crate::beacon::BeaconSettings;
+use crate::encoder;
+use crate::macros::CALLSIGN_LEN;
+use crate::message::PlaybackControl;
+use crate::shared::SharedConfig;
+use crate::storage::SaveRequest;
+use crate::types::{KeyerConfig, KeyerMode};
+
+/// Longest command line
+pub const LINE_LEN: usize = 80;
+/// Longest response line (without line ending)
+pub const RESPONSE_LEN: usize = 112;
+
+/// Response line
+pub type Response = String<RESPONSE_LEN>;
+
+/// Command errors, reported as `ERR <code>`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CliError {
+    /// Unknown keyword
+    UnknownCommand,
+    /// Argument missing
+    MissingArgument,
+    /// Argument not understood or out of range
+    InvalidValue,
+    /// Text cannot be sent (unsupported characters, too long)
+    InvalidText,
+    /// Line longer than [`LINE_LEN`]
+    LineTooLong,
+    /// Configuration is held by command mode or not set up yet
+    Busy,
+}
+
+impl CliError {
+    /// Code used in the `ERR` response
+    pub const fn code(&self) -> &'static str {
+        match self {
+            CliError::UnknownCommand => "UNKNOWN",
+            CliError::MissingArgument => "ARG",
+            CliError::InvalidValue => "VALUE",
+            CliError::InvalidText => "TEXT",
+            CliError::LineTooLong => "TOOLONG",
+            CliError::Busy => "BUSY",
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl core::fmt::Display for CliError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            CliError::UnknownCommand => write!(f, "Unknown command"),
+            CliError::MissingArgument => write!(f, "Missing argument"),
+            CliError::InvalidValue => write!(f, "Invalid value"),
+            CliError::InvalidText => write!(f, "Text cannot be sent"),
+            CliError::LineTooLong => write!(f, "Line too long"),
+            CliError::Busy => write!(f, "Configuration busy"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for CliError {}
+
+/// Parsed command line
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CliCommand<'a> {
+    Wpm(u32),
+    Mode(KeyerMode),
+    Weight(u8),
+    Ratio(u8),
+    /// Farnsworth speed, None = off
+    Farnsworth(Option<u32>),
+    Sidetone(bool),
+    Swap(bool),
+    Tx(bool),
+    Send(&'a str),
+    Stop,
+    Status,
+    Save,
+    /// Beacon at power-up
+    Beacon(BeaconSettings),
+    /// Own callsign
+    Call(&'a str),
+}
+
+/// Collects bytes into lines
+///
+/// CR or LF ends a line; empty lines are skipped and backspace/DEL remove the
+/// last byte, so the protocol also works from a terminal.
+#[derive(Clone, Debug, Default)]
+pub struct LineReader<const N: usize = LINE_LEN> {
+    line: Vec<u8, N>,
+    overflow: bool,
+    /// The previous call returned the line; clear before the next byte
+    complete: bool,
+}
+
+impl<const N: usize> LineReader<N> {
+    pub const fn new() -> Self {
+        Self {
+            line: Vec::new(),
+            overflow: false,
+            complete: false,
+        }
+    }
+
+    /// Feed one byte; returns the line once it ends
+    pub fn push(&mut self, byte: u8) -> Option<Result<&[u8], CliError>> {
+        if self.complete {
+            self.complete = false;
+            self.line.clear();
+        }
+        match byte {
+            b'\r' | b'\n' => {
+                if core::mem::take(&mut self.overflow) {
+                    self.line.clear();
+                    return Some(Err(CliError::LineTooLong));
+                }
+                if self.line.is_empty() {
+                    return None;
+                }
+                self.complete = true;
+                Some(Ok(&self.line))
+            }
+            0x08 | 0x7F => {
+                self.line.pop();
+                None
+            }
+            _ => {
+                // The rest of an overlong line is dropped up to its end
+                if self.line.push(byte).is_err() {
+                    self.overflow = true;
+                }
+                None
+            }
+        }
+    }
+}
+
+pub(crate) fn on_off(arg: &str) -> Result<bool, CliError> {
+    match arg {
+        _ if arg.eq_ignore_ascii_case("ON") => Ok(true),
+        _ if arg.eq_ignore_ascii_case("OFF") => Ok(false),
+        _ => Err(CliError::InvalidValue),
+    }
+}
+
+fn number<T: core::str::FromStr>(arg: &str) -> Result<T, CliError> {
+    arg.parse().map_err(|_| CliError::InvalidValue)
+}
+
+fn mode_from_name(name: &str) -> Result<KeyerMode, CliError> {
+    const NAMES: &[(&str, KeyerMode)] = &[
+        ("A", KeyerMode::ModeA),
+        ("B", KeyerMode::ModeB),
+        ("S", KeyerMode::SuperKeyer),
+        ("SUPER", KeyerMode::SuperKeyer),
+        ("U", KeyerMode::Ultimatic),
+        ("ULTIMATIC", KeyerMode::Ultimatic),
+        ("BUG", KeyerMode::Bug),
+        ("STRAIGHT", KeyerMode::StraightKey),
+    ];
+    NAMES
+        .iter()
+        .find(|(candidate, _)| candidate.eq_ignore_ascii_case(name))
+        .map(|&(_, mode)| mode)
+        .ok_or(CliError::InvalidValue)
+}
+
+const fn mode_name(mode: KeyerMode) -> &'static str {
+    match mode {
+        KeyerMode::ModeA => "A",
+        KeyerMode::ModeB => "B",
+        KeyerMode::SuperKeyer => "SUPER",
+        KeyerMode::Ultimatic => "ULTIMATIC",
+        KeyerMode::Bug => "BUG",
+        KeyerMode::StraightKey => "STRAIGHT",
+    }
+}
+
+/// Parse one command line (without line ending)
+pub fn parse(line: &[u8]) -> Result<CliCommand<'_>, CliError> {
+    let line = core::str::from_utf8(line).map_err(|_| CliError::InvalidValue)?.trim();
+    let (keyword, arg) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let arg = arg.trim();
+    let is = |name: &str| keyword.eq_ignore_ascii_case(name);
+
+    // Commands without an argument
+    if is("STATUS") {
+        return Ok(CliCommand::Status);
+    } else if is("SAVE") {
+        return Ok(CliCommand::Save);
+    } else if is("STOP") {
+        return Ok(CliCommand::Stop);
+    }
+
+    let known = ["WPM", "MODE", "WEIGHT", "RATIO", "FARNS", "SIDETONE", "SWAP", "TX", "SEND", "BEACON", "CALL"];
+    if !known.iter().any(|name| is(name)) {
+        return Err(CliError::UnknownCommand);
+    }
+    if arg.is_empty() {
+        return Err(CliError::MissingArgument);
+    }
+    Ok(match () {
+        _ if is("WPM") => CliCommand::Wpm(number(arg)?),
+        _ if is("MODE") => CliCommand::Mode(mode_from_name(arg)?),
+        _ if is("WEIGHT") => CliCommand::Weight(number(arg)?),
+        _ if is("RATIO") => CliCommand::Ratio(number(arg)?),
+        _ if is("FARNS") && arg.eq_ignore_ascii_case("OFF") => CliCommand::Farnsworth(None),
+        _ if is("FARNS") => CliCommand::Farnsworth(Some(number(arg)?)),
+        _ if is("SIDETONE") => CliCommand::Sidetone(on_off(arg)?),
+        _ if is("SWAP") => CliCommand::Swap(on_off(arg)?),
+        _ if is("TX") => CliCommand::Tx(on_off(arg)?),
+        _ if is("BEACON") => CliCommand::Beacon(beacon(arg)?),
+        _ if is("CALL") => CliCommand::Call(callsign(arg)?),
+        _ => CliCommand::Send(arg),
+    })
+}
+
+/// `OFF` or `<slot> [interval_s [carrier_s]]`
+fn beacon(arg: &str) -> Result<BeaconSettings, CliError> {
+    if arg.eq_ignore_ascii_case("OFF") {
+        return Ok(BeaconSettings::default());
+    }
+    let mut args = arg.split_whitespace();
+    let defaults = BeaconSettings::default();
+    let slot = number(args.next().unwrap_or(""))?;
+    let interval_s = args.next().map_or(Ok(defaults.interval_s), number)?;
+    let carrier_s = args.next().map_or(Ok(defaults.carrier_s), number)?;
+    if interval_s == 0 || args.next().is_some() {
+        return Err(CliError::InvalidValue);
+    }
+    Ok(BeaconSettings { enabled: true, slot, interval_s, carrier_s })
+}
+
+/// One word of at most [`CALLSIGN_LEN`] sendable characters
+fn callsign(arg: &str) -> Result<&str, CliError> {
+    if arg.len() > CALLSIGN_LEN || arg.contains(char::is_whitespace) {
+        return Err(CliError::InvalidValue);
+    }
+    encoder::validate(arg).map_err(|_| CliError::InvalidText)?;
+    Ok(arg)
+}
+
+/// Write the settings as `KEY=VALUE` pairs, as in the `STATUS` response
+pub fn write_settings(config: &KeyerConfig, out: &mut impl Write) -> core::fmt::Result {
+    let flag = |on: bool| if on { "ON" } else { "OFF" };
+    write!(
+        out,
+        "MODE={} WPM={} WEIGHT={} RATIO={} FARNS=",
+        mode_name(config.mode),
+        config.wpm(),
+        config.weighting,
+        config.dah_ratio_tenths,
+    )?;
+    match config.farnsworth_wpm {
+        Some(wpm) => write!(out, "{}", wpm)?,
+        None => out.write_str("OFF")?,
+    }
+    write!(
+        out,
+        " SIDETONE={} SWAP={} TX={}",
+        flag(config.sidetone_enabled),
+        flag(config.paddle_swap),
+        flag(config.tx_enabled),
+    )
+}
+
+/// Write the `STATUS` response for a configuration and callsign
+pub fn write_status(config: &KeyerConfig, callsign: &str, response: &mut Response) {
+    response.clear();
+    // Fits: the longest status line is below RESPONSE_LEN
+    response.push_str("OK ").ok();
+    write_settings(config, response).ok();
+    write!(response, " CALL={}", callsign).ok();
+}
+
+/// Apply a settings command to a configuration
+///
+/// Commands that are not settings give [`CliError::UnknownCommand`].
+pub fn apply_setting(config: KeyerConfig, command: CliCommand<'_>) -> Result<KeyerConfig, CliError> {
+    let changed = match command {
+        CliCommand::Wpm(wpm) => config.with_wpm(wpm),
+        CliCommand::Mode(mode) => Ok(KeyerConfig { mode, ..config }),
+        CliCommand::Weight(weighting) => config.with_timing(config.dah_ratio_tenths, weighting),
+        CliCommand::Ratio(tenths) => config.with_timing(tenths, config.weighting),
+        CliCommand::Farnsworth(wpm) => config.with_farnsworth(wpm),
+        CliCommand::Sidetone(on) => Ok(KeyerConfig { sidetone_enabled: on, ..config }),
+        CliCommand::Swap(on) => Ok(KeyerConfig { paddle_swap: on, ..config }),
+        CliCommand::Tx(on) => Ok(KeyerConfig { tx_enabled: on, ..config }),
+        CliCommand::Send(_) | CliCommand::Stop | CliCommand::Status | CliCommand::Save | CliCommand::Beacon(_)
+        | CliCommand::Call(_) => {
+            return Err(CliError::UnknownCommand)
+        }
+    };
+    changed.map_err(|_| CliError::InvalidValue)
+}
+
+/// Apply a validated change to the shared configuration
+fn change(config: &SharedConfig, command: CliCommand<'_>) -> Result<(), CliError> {
+    if config.get().is_none() || config.is_temporary() {
+        return Err(CliError::Busy);
+    }
+    let mut result = Ok(());
+    config.update(|current| match apply_setting(*current, command) {
+        Ok(changed) => *current = changed,
+        Err(error) => result = Err(error),
+    });
+    result
+}
+
+/// Carry out a command
+///
+/// `SAVE` and `BEACON` only raise `save`; the settings task writes the configuration.
+pub fn execute(
+    command: CliCommand<'_>,
+    config: &SharedConfig,
+    playback: &PlaybackControl,
+    save: &SaveRequest,
+) -> Result<Response, CliError> {
+    let mut response = Response::new();
+    match command {
+        CliCommand::Send(text) => playback.play(text).map_err(|_| CliError::InvalidText)?,
+        CliCommand::Stop => playback.abort(),
+        CliCommand::Save => {
+            if config.is_temporary() {
+                return Err(CliError::Busy);
+            }
+            save.request();
+        }
+        CliCommand::Beacon(beacon) => {
+            if config.is_temporary() {
+                return Err(CliError::Busy);
+            }
+            save.request_beacon(beacon);
+        }
+        CliCommand::Call(callsign) => {
+            if config.is_temporary() {
+                return Err(CliError::Busy);
+            }
+            let mut upper: String<CALLSIGN_LEN> = String::new();
+            callsign.chars().try_for_each(|c| upper.push(c.to_ascii_uppercase())).ok();
+            save.request_callsign(&upper).map_err(|_| CliError::InvalidText)?;
+        }
+        CliCommand::Status => {
+            write_status(&config.get().ok_or(CliError::Busy)?, &save.callsign(), &mut response);
+            return Ok(response);
+        }
+        setting => change(config, setting)?,
+    }
+    response.push_str("OK").ok();
+    Ok(response)
+}
+
+/// Parse and carry out a line, producing its response
+pub fn handle_line(
+    line: Result<&[u8], CliError>,
+    config: &SharedConfig,
+    playback: &PlaybackControl,
+    save: &SaveRequest,
+) -> Response {
+    line.and_then(parse)
+        .and_then(|command| execute(command, config, playback, save))
+        .unwrap_or_else(|error| {
+            let mut response = Response::new();
+            write!(response, "ERR {}", error.code()).ok();
+            response
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse(b"wpm 25"), Ok(CliCommand::Wpm(25)));
+        assert_eq!(parse(b"  MODE  ultimatic "), Ok(CliCommand::Mode(KeyerMode::Ultimatic)));
+        assert_eq!(parse(b"FARNS off"), Ok(CliCommand::Farnsworth(None)));
+        assert_eq!(parse(b"SWAP ON"), Ok(CliCommand::Swap(true)));
+        assert_eq!(parse(b"SEND CQ  TEST <AR>"), Ok(CliCommand::Send("CQ  TEST <AR>")));
+        assert_eq!(parse(b"status"), Ok(CliCommand::Status));
+        assert_eq!(parse(b"WPM"), Err(CliError::MissingArgument));
+        assert_eq!(parse(b"WPM fast"), Err(CliError::InvalidValue));
+        assert_eq!(parse(b"MODE Z"), Err(CliError::InvalidValue));
+        assert_eq!(parse(b"FOO 1"), Err(CliError::UnknownCommand));
+
+        let beacon = BeaconSettings { enabled: true, slot: 2, interval_s: 300, carrier_s: 0 };
+        assert_eq!(parse(b"BEACON 2 300"), Ok(CliCommand::Beacon(beacon)));
+        assert_eq!(parse(b"beacon off"), Ok(CliCommand::Beacon(BeaconSettings::default())));
+        assert_eq!(parse(b"BEACON 2 0"), Err(CliError::InvalidValue));
+        assert_eq!(parse(b"BEACON 2 60 5 1"), Err(CliError::InvalidValue));
+
+        assert_eq!(parse(b"CALL ja1abc/p"), Ok(CliCommand::Call("ja1abc/p")));
+        assert_eq!(parse(b"CALL JA1 ABC"), Err(CliError::InvalidValue));
+        assert_eq!(parse(b"CALL JA1ABC{NR}"), Err(CliError::InvalidText));
+        assert_eq!(parse(b"CALL ABCDEFGHIJKLMNOPQ"), Err(CliError::InvalidValue));
+    }
+
+    #[test]
+    fn test_line_reader() {
+        let mut reader = LineReader::<8>::new();
+        let mut lines: Vec<Result<Vec<u8, 8>, CliError>, 4> = Vec::new();
+        for &byte in b"WPX\x08M 2\r\n\r\nSEND CQ CQ CQ\nSTOP\r" {
+            if let Some(line) = reader.push(byte) {
+                lines.push(line.map(|line| Vec::from_slice(line).unwrap())).unwrap();
+            }
+        }
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0].as_deref(), Ok(&b"WPM 2"[..]));
+        assert_eq!(lines[1], Err(CliError::LineTooLong));
+        assert_eq!(lines[2].as_deref(), Ok(&b"STOP"[..]));
+    }
+
+    #[test]
+    fn test_execute() {
+        let config = SharedConfig::new();
+        let playback = PlaybackControl::new();
+        let save = SaveRequest::new();
+        let run = |line: &[u8]| handle_line(Ok(line), &config, &playback, &save);
+
+        assert_eq!(run(b"STATUS"), "ERR BUSY");
+        config.set(KeyerConfig::default());
+        assert_eq!(run(b"WPM 25"), "OK");
+        assert_eq!(run(b"MODE B"), "OK");
+        assert_eq!(run(b"FARNS 30"), "ERR VALUE");
+        assert_eq!(run(b"FARNS 18"), "OK");
+        assert_eq!(run(b"SEND CQ #"), "ERR TEXT");
+        assert_eq!(run(b"SEND CQ TEST"), "OK");
+        assert_eq!(
+            run(b"STATUS"),
+            "OK MODE=B WPM=25 WEIGHT=50 RATIO=30 FARNS=18 SIDETONE=ON SWAP=OFF TX=ON CALL="
+        );
+
+        // Saving is left to the settings task; refused while command mode is active
+        assert_eq!(run(b"SAVE"), "OK");
+        assert!(save.take() && !save.take());
+        assert_eq!(run(b"BEACON 1 30 10"), "OK");
+        assert_eq!(run(b"CALL ja1abc"), "OK");
+        assert!(save.take());
+        let mut settings = crate::storage::Settings::default();
+        assert!(save.take_changes(&mut settings));
+        assert_eq!((settings.beacon.slot, settings.beacon.carrier_s), (1, 10));
+        assert_eq!(settings.callsign, "JA1ABC");
+        assert!(run(b"STATUS").ends_with(" TX=ON CALL=JA1ABC"));
+        assert!(!save.take_changes(&mut settings));
+        config.set_temporary(KeyerConfig::default());
+        assert_eq!(run(b"SAVE"), "ERR BUSY");
+        assert_eq!(run(b"WPM 30"), "ERR BUSY");
+    }
+}
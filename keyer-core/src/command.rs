@@ -0,0 +1,481 @@
+//! Paddle-driven command mode for on-device configuration
+//!
+//! Squeezing both paddles for [`CommandMode::hold`] (or calling
+//! [`CommandMode::enter`] from a button) switches the keyer to sidetone only and
+//! answers `R`. The operator then sends single-letter commands:
+//!
+//! - `S`: speed adjust; each Dit is 1 WPM slower, each Dah 1 WPM faster, and a
+//!   pause ends the adjustment and answers the new speed
+//! - `M`: cycle Mode A → Mode B → SuperKeyer → Ultimatic, answering `A`/`B`/`S`/`U`
+//! - `T`: tune; keys the transmitter until a paddle is pressed
+//! - `X`: leave command mode
+//!
+//! Unknown characters are answered with `?`. Command mode also ends after
+//! [`COMMAND_TIMEOUT_MS`] without input.
+
+use heapless::spsc::{Producer, Queue};
+use heapless::String;
+use core::fmt::Write;
+use crate::controller::PaddleInput;
+use crate::decoder::{Decoded, MorseDecoder};
+use crate::fsm::KeyerFSM;
+use crate::hal::{Duration, Instant};
+use crate::message::{MessagePlayer, PlaybackStatus};
+use crate::types::{Element, KeyerConfig, KeyerMode};
+
+/// Default squeeze time to enter command mode
+pub const COMMAND_HOLD_MS: u64 = 2_000;
+
+/// Leave command mode after this long without paddle input
+pub const COMMAND_TIMEOUT_MS: u64 = 30_000;
+
+/// Longest tune carrier
+pub const TUNE_TIMEOUT_MS: u64 = 20_000;
+
+/// Speed range reachable with `S`
+const MIN_WPM: u32 = 5;
+const MAX_WPM: u32 = 60;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum CommandState {
+    Inactive,
+    /// Entry squeeze still held; answers `R` once released
+    Entering,
+    /// Waiting for both paddles to be released
+    Release,
+    Listening,
+    SpeedAdjust,
+    /// Waiting for the queue to drain before enabling the transmitter
+    TuneStart,
+    TuneKey,
+    Tuning(Instant),
+    /// Waiting for the last answer to be sent before restoring the configuration
+    Exiting,
+}
+
+/// Command mode state machine, run by the evaluator in place of the plain FSM update
+pub struct CommandMode {
+    state: CommandState,
+    hold: Duration,
+    squeeze_since: Option<Instant>,
+    last_input: Option<Instant>,
+    /// Configuration to restore on exit (with command changes applied)
+    saved: KeyerConfig,
+    /// Configuration in effect while in command mode
+    working: KeyerConfig,
+    decoder: MorseDecoder,
+    reply: MessagePlayer,
+}
+
+impl CommandMode {
+    /// Create inactive command mode, entered by squeezing for `hold`
+    pub fn new(hold: Duration) -> Self {
+        Self {
+            state: CommandState::Inactive,
+            hold,
+            squeeze_since: None,
+            last_input: None,
+            saved: KeyerConfig::default(),
+            working: KeyerConfig::default(),
+            decoder: MorseDecoder::new(),
+            reply: MessagePlayer::new(),
+        }
+    }
+
+    /// Squeeze time needed to enter command mode
+    pub fn hold(&self) -> Duration {
+        self.hold
+    }
+
+    /// Returns true while command mode owns the paddles
+    pub fn is_active(&self) -> bool {
+        self.state != CommandState::Inactive
+    }
+
+    /// Watch for a long squeeze while inactive
+    /// Returns true when command mode should be entered
+    pub fn watch(&mut self, now: Instant, paddle: &PaddleInput, fsm: &KeyerFSM) -> bool {
+        let squeezable = !matches!(fsm.config().mode, KeyerMode::Bug | KeyerMode::StraightKey)
+            && !fsm.config().single_lever;
+        if !squeezable || !paddle.both_pressed() {
+            self.squeeze_since = None;
+            return false;
+        }
+        let since = *self.squeeze_since.get_or_insert(now);
+        now.duration_since(since) >= self.hold
+    }
+
+    /// Enter command mode (sidetone only); returns the configuration to publish
+    pub fn enter(&mut self, now: Instant, fsm: &mut KeyerFSM) -> KeyerConfig {
+        self.saved = *fsm.config();
+        self.working = KeyerConfig {
+            tx_enabled: false,
+            char_space_enabled: true,
+            ..self.saved
+        };
+        // Commands need an iambic mode when entered by button
+        if matches!(self.working.mode, KeyerMode::Bug | KeyerMode::StraightKey) {
+            self.working.mode = KeyerMode::ModeA;
+        }
+        self.state = CommandState::Entering;
+        self.squeeze_since = None;
+        self.last_input = Some(now);
+        self.decoder.reset();
+        fsm.set_config(self.working);
+        self.working
+    }
+
+    /// Run one evaluator step while active
+    /// Returns a configuration to publish when settings change
+    pub fn update<const N: usize>(
+        &mut self,
+        now: Instant,
+        fsm: &mut KeyerFSM,
+        paddle: &PaddleInput,
+        queue: &mut Producer<'_, Element, N>,
+    ) -> Option<KeyerConfig> {
+        if self.reply.update(paddle, queue) == PlaybackStatus::Playing {
+            return None;
+        }
+
+        match self.state {
+            CommandState::Inactive => None,
+            CommandState::Entering | CommandState::Release => {
+                if paddle.both_released() {
+                    if self.state == CommandState::Entering {
+                        self.answer("R", fsm);
+                    }
+                    fsm.reset();
+                    self.state = CommandState::Listening;
+                }
+                None
+            }
+            CommandState::Listening | CommandState::SpeedAdjust => {
+                if self.timed_out(now) {
+                    return self.exit(fsm);
+                }
+                self.key_paddles(now, fsm, paddle, queue)
+            }
+            CommandState::TuneStart => {
+                if queue.len() > 0 || fsm.element_in_flight() {
+                    return None;
+                }
+                self.state = CommandState::TuneKey;
+                Some(KeyerConfig { tx_enabled: true, ..self.working })
+            }
+            CommandState::TuneKey => {
+                if queue.enqueue(Element::KeyDown).is_ok() {
+                    self.state = CommandState::Tuning(now);
+                }
+                None
+            }
+            CommandState::Tuning(since) => {
+                let pressed = paddle.dit() || paddle.dah();
+                let expired = now.duration_since(since) >= Duration::from_millis(TUNE_TIMEOUT_MS);
+                if !(pressed || expired) || queue.enqueue(Element::KeyUp).is_err() {
+                    return None;
+                }
+                // Senders always release the key, so sidetone only can follow at once
+                self.state = CommandState::Release;
+                self.last_input = Some(now);
+                Some(self.working)
+            }
+            CommandState::Exiting => {
+                if queue.len() > 0 || fsm.element_in_flight() || self.reply.is_playing() {
+                    return None;
+                }
+                self.state = CommandState::Inactive;
+                fsm.reset();
+                fsm.set_config(self.saved);
+                Some(self.saved)
+            }
+        }
+    }
+
+    /// Pass paddle keying through to the sender and decode it
+    fn key_paddles<const N: usize>(
+        &mut self,
+        now: Instant,
+        fsm: &mut KeyerFSM,
+        paddle: &PaddleInput,
+        queue: &mut Producer<'_, Element, N>,
+    ) -> Option<KeyerConfig> {
+        // Elements produced by the FSM on their way to the sender
+        let mut staging = Queue::<Element, 4>::new();
+        let (mut producer, mut consumer) = staging.split();
+        fsm.update_at(now, paddle, &mut producer);
+
+        let mut change = None;
+        while let Some(element) = consumer.dequeue() {
+            // Cannot fail: the FSM only queues an element after the previous one finished
+            queue.enqueue(element).ok();
+            self.last_input = Some(now);
+            change = self.handle_element(element, fsm).or(change);
+        }
+        change
+    }
+
+    fn handle_element(&mut self, element: Element, fsm: &mut KeyerFSM) -> Option<KeyerConfig> {
+        if self.state == CommandState::SpeedAdjust {
+            let wpm = self.working.wpm();
+            let wpm = match element {
+                Element::Dit => wpm.saturating_sub(1).max(MIN_WPM),
+                Element::Dah => (wpm + 1).min(MAX_WPM),
+                // The pause after `S` itself may run on into a word space
+                Element::CharSpace => {
+                    self.state = CommandState::Listening;
+                    let mut digits = String::<4>::new();
+                    write!(digits, "{}", wpm).ok();
+                    self.answer(&digits, fsm);
+                    return None;
+                }
+                _ => return None,
+            };
+            return self.apply(fsm, |config| config.with_wpm(wpm));
+        }
+
+        let mut change = None;
+        for decoded in self.decoder.push(element) {
+            if let Decoded::Char(c) = decoded {
+                change = self.execute(c, fsm).or(change);
+            }
+        }
+        change
+    }
+
+    fn execute(&mut self, command: char, fsm: &mut KeyerFSM) -> Option<KeyerConfig> {
+        match command {
+            'S' => {
+                self.state = CommandState::SpeedAdjust;
+                None
+            }
+            'M' => {
+                let (mode, answer) = match self.working.mode {
+                    KeyerMode::ModeA => (KeyerMode::ModeB, "B"),
+                    KeyerMode::ModeB => (KeyerMode::SuperKeyer, "S"),
+                    KeyerMode::SuperKeyer => (KeyerMode::Ultimatic, "U"),
+                    _ => (KeyerMode::ModeA, "A"),
+                };
+                self.answer(answer, fsm);
+                self.apply(fsm, |config| Ok(KeyerConfig { mode, ..config }))
+            }
+            'T' => {
+                self.state = CommandState::TuneStart;
+                None
+            }
+            'X' => self.exit(fsm),
+            _ => {
+                self.answer("?", fsm);
+                None
+            }
+        }
+    }
+
+    /// Apply a setting change to both the working and the saved configuration
+    fn apply(
+        &mut self,
+        fsm: &mut KeyerFSM,
+        change: impl Fn(KeyerConfig) -> Result<KeyerConfig, &'static str>,
+    ) -> Option<KeyerConfig> {
+        let working = change(self.working).ok()?;
+        let saved = change(self.saved).ok()?;
+        self.working = working;
+        self.saved = saved;
+        fsm.set_config(working);
+        Some(working)
+    }
+
+    fn exit(&mut self, fsm: &mut KeyerFSM) -> Option<KeyerConfig> {
+        self.answer("X", fsm);
+        self.state = CommandState::Exiting;
+        None
+    }
+
+    fn timed_out(&self, now: Instant) -> bool {
+        self.last_input
+            .is_some_and(|last| now.duration_since(last) >= Duration::from_millis(COMMAND_TIMEOUT_MS))
+    }
+
+    /// Queue an answer on the sidetone
+    fn answer(&mut self, text: &str, fsm: &mut KeyerFSM) {
+        // Answers are fixed strings and speeds, always encodable
+        self.reply.start(text).ok();
+        self.decoder.reset();
+        fsm.reset();
+    }
+}
+
+impl Default for CommandMode {
+    fn default() -> Self {
+        Self::new(Duration::from_millis(COMMAND_HOLD_MS))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::PaddleSide;
+    use heapless::Vec;
+
+    struct Bench {
+        paddle: PaddleInput,
+        fsm: KeyerFSM,
+        command: CommandMode,
+        queue: Queue<Element, 8>,
+        now_ms: i64,
+        /// Sender: key-up time of the element being keyed, and when it may dequeue again
+        key_up_at: Option<i64>,
+        last_key_up: i64,
+        busy_until: i64,
+    }
+
+    impl Bench {
+        fn new() -> Self {
+            let config = KeyerConfig { unit: Duration::from_millis(50), ..KeyerConfig::default() };
+            Self {
+                paddle: PaddleInput::new(),
+                fsm: KeyerFSM::new(config),
+                command: CommandMode::default(),
+                queue: Queue::new(),
+                // Paddle debounce counts from the last edge at 0 ms
+                now_ms: 1000,
+                key_up_at: None,
+                last_key_up: 0,
+                busy_until: 0,
+            }
+        }
+
+        /// Run for `ms` with a sender that keys elements in real time
+        fn run(&mut self, ms: i64) -> Vec<Element, 64> {
+            let mut sent = Vec::new();
+            let (mut producer, mut consumer) = self.queue.split();
+            for _ in 0..ms {
+                let now = Instant::from_millis(self.now_ms as _);
+                if self.key_up_at == Some(self.now_ms) {
+                    self.key_up_at = None;
+                    self.last_key_up = self.now_ms;
+                    self.fsm.element_finished(now);
+                }
+                if self.command.is_active() {
+                    self.command.update(now, &mut self.fsm, &self.paddle, &mut producer);
+                } else if self.command.watch(now, &self.paddle, &self.fsm) {
+                    self.command.enter(now, &mut self.fsm);
+                } else {
+                    self.fsm.update_at(now, &self.paddle, &mut producer);
+                }
+                if self.now_ms >= self.busy_until {
+                    if let Some(element) = consumer.dequeue() {
+                        let timing = self.fsm.config().element_timing(element);
+                        let key_down = timing.key_down.as_millis() as i64;
+                        let space = timing.space.as_millis() as i64;
+                        if element.is_keyed() && !element.is_manual() {
+                            self.fsm.element_started(now);
+                            self.key_up_at = Some(self.now_ms + key_down);
+                            self.busy_until = self.now_ms + key_down + space;
+                        } else if element.is_space() {
+                            self.busy_until = self.last_key_up + space;
+                        }
+                        sent.push(element).ok();
+                    }
+                }
+                self.now_ms += 1;
+            }
+            sent
+        }
+
+        fn press(&mut self, side: PaddleSide, pressed: bool) {
+            self.paddle.update(side, pressed, self.now_ms as u32);
+        }
+
+        /// Key one character on the paddles
+        ///
+        /// Each lever is tapped until its element starts; the next tap is latched
+        /// while that element is keyed, so the character is sent without gaps.
+        fn key(&mut self, pattern: &str) -> Vec<Element, 64> {
+            let mut sent = Vec::new();
+            for symbol in pattern.chars() {
+                let (side, element) = match symbol {
+                    '.' => (PaddleSide::Dit, Element::Dit),
+                    _ => (PaddleSide::Dah, Element::Dah),
+                };
+                self.press(side, true);
+                while !sent.contains(&element) {
+                    sent.extend(self.run(1));
+                }
+                sent.clear();
+                sent.extend(self.run(12));
+                self.press(side, false);
+                sent.extend(self.run(12));
+            }
+            sent.extend(self.run(1000));
+            sent
+        }
+
+        fn enter(&mut self) {
+            self.press(PaddleSide::Dit, true);
+            self.press(PaddleSide::Dah, true);
+            self.run(2100);
+            self.press(PaddleSide::Dit, false);
+            self.press(PaddleSide::Dah, false);
+            self.run(500);
+            assert!(self.command.is_active());
+        }
+    }
+
+    #[test]
+    fn test_enter_by_squeeze() {
+        let mut bench = Bench::new();
+        bench.press(PaddleSide::Dit, true);
+        bench.press(PaddleSide::Dah, true);
+        bench.run(1900);
+        assert!(!bench.command.is_active());
+        bench.run(200);
+        assert!(bench.command.is_active());
+        assert!(!bench.fsm.config().tx_enabled);
+    }
+
+    #[test]
+    fn test_mode_and_speed_commands() {
+        let mut bench = Bench::new();
+        bench.enter();
+        assert_eq!(bench.fsm.config().wpm(), 24);
+
+        // M: ModeA -> ModeB, answered with B (-...)
+        let sent = bench.key("--");
+        assert_eq!(bench.fsm.config().mode, KeyerMode::ModeB);
+        assert!(sent.ends_with(&[Element::Dah, Element::Dit, Element::Dit, Element::Dit]));
+
+        // S, then two Dahs and a Dit: one WPM faster
+        bench.key("...");
+        bench.key("--.");
+        assert_eq!(bench.fsm.config().wpm(), 25);
+
+        // X restores transmit with the new settings (after the speed answer)
+        bench.run(1500);
+        bench.key("-..-");
+        bench.run(2000);
+        assert!(!bench.command.is_active());
+        let config = bench.fsm.config();
+        assert!(config.tx_enabled);
+        assert_eq!((config.mode, config.wpm()), (KeyerMode::ModeB, 25));
+    }
+
+    #[test]
+    fn test_tune_and_timeout() {
+        let mut bench = Bench::new();
+        bench.enter();
+
+        // T keys the carrier until a paddle is pressed
+        let sent = bench.key("-");
+        assert_eq!(sent.last(), Some(&Element::KeyDown));
+        bench.press(PaddleSide::Dit, true);
+        assert_eq!(bench.run(10).as_slice(), &[Element::KeyUp]);
+        bench.press(PaddleSide::Dit, false);
+        bench.run(100);
+        assert!(bench.command.is_active());
+
+        bench.run(COMMAND_TIMEOUT_MS as i64 + 1000);
+        assert!(!bench.command.is_active());
+        assert!(bench.fsm.config().tx_enabled);
+    }
+}
@@ -25,6 +25,9 @@ impl PaddleInput {
     }
 
     /// Update paddle state (called from interrupt handler)
+    ///
+    /// `side` is the physical contact; [`KeyerFSM`](crate::fsm::KeyerFSM) applies
+    /// the configured paddle swap to its own view of it.
     /// 
     /// # Safety
     /// This function is safe to call from interrupt context
@@ -35,14 +38,15 @@ impl PaddleInput {
         match side {
             PaddleSide::Dit => {
                 let last = self.dit_last_edge.load(Ordering::Relaxed);
-                if now.saturating_sub(last) >= debounce_ms {
+                // Only real state changes count as edges
+                if self.dit_pressed.load(Ordering::Relaxed) != state && now.saturating_sub(last) >= debounce_ms {
                     self.dit_pressed.store(state, Ordering::Relaxed);
                     self.dit_last_edge.store(now, Ordering::Relaxed);
                 }
             }
             PaddleSide::Dah => {
                 let last = self.dah_last_edge.load(Ordering::Relaxed);
-                if now.saturating_sub(last) >= debounce_ms {
+                if self.dah_pressed.load(Ordering::Relaxed) != state && now.saturating_sub(last) >= debounce_ms {
                     self.dah_pressed.store(state, Ordering::Relaxed);
                     self.dah_last_edge.store(now, Ordering::Relaxed);
                 }
@@ -132,8 +136,11 @@ impl SuperKeyerController {
 
     /// Record paddle press events with timestamps
     pub fn record_press(&mut self, dit_pressed: bool, dah_pressed: bool) {
-        let now = Instant::now();
-        
+        self.record_press_at(dit_pressed, dah_pressed, Instant::now());
+    }
+
+    /// Record paddle press events at an explicit time
+    pub fn record_press_at(&mut self, dit_pressed: bool, dah_pressed: bool, now: Instant) {
         if dit_pressed && self.dit_time.is_none() {
             self.dit_time = Some(now);
         }
@@ -192,7 +199,12 @@ impl SuperKeyerController {
 
     /// Update controller state based on current paddle input
     pub fn update(&mut self, paddle_input: &PaddleInput) {
-        self.record_press(paddle_input.dit(), paddle_input.dah());
+        self.update_at(paddle_input, Instant::now());
+    }
+
+    /// Update controller state at an explicit time
+    pub fn update_at(&mut self, paddle_input: &PaddleInput, now: Instant) {
+        self.record_press_at(paddle_input.dit(), paddle_input.dah(), now);
     }
 
     /// Get next element to send based on current state and mode logic
@@ -205,11 +217,7 @@ impl SuperKeyerController {
             Some(memory)
         } else {
             // Standard single paddle logic
-            if let Some(priority) = self.determine_priority() {
-                Some(priority)
-            } else {
-                None
-            }
+            self.determine_priority()
         }
     }
 
@@ -276,6 +284,19 @@ mod tests {
         assert_eq!(paddle.current_single_element(), None);
     }
 
+    #[test]
+    fn test_paddle_input_repeated_state_keeps_edge_time() {
+        let paddle = PaddleInput::new();
+        
+        paddle.update(PaddleSide::Dit, true, 100);
+        paddle.update(PaddleSide::Dah, true, 150);
+        
+        // Re-reporting an unchanged state must not move the edge time
+        paddle.update(PaddleSide::Dit, true, 200);
+        paddle.update(PaddleSide::Dah, true, 200);
+        assert_eq!(paddle.get_press_times(), (Some(100), Some(150)));
+    }
+
     #[test]
     fn test_superkeyer_priority() {
         let mut controller = SuperKeyerController::new();
@@ -294,6 +315,22 @@ mod tests {
         assert_eq!(controller.determine_priority(), Some(Element::Dah));
     }
 
+    #[test]
+    fn test_superkeyer_priority_with_explicit_time() {
+        let mut controller = SuperKeyerController::new();
+        
+        // Dit pressed first, Dah joins later: Dit keeps priority
+        controller.record_press_at(true, false, Instant::from_millis(100));
+        controller.record_press_at(true, true, Instant::from_millis(130));
+        assert_eq!(controller.determine_priority(), Some(Element::Dit));
+        
+        // Dah pressed first wins as well
+        controller.clear_history();
+        controller.record_press_at(false, true, Instant::from_millis(200));
+        controller.record_press_at(true, true, Instant::from_millis(220));
+        assert_eq!(controller.determine_priority(), Some(Element::Dah));
+    }
+
     #[test]
     fn test_superkeyer_memory() {
         let mut controller = SuperKeyerController::new();
@@ -0,0 +1,516 @@
+//! Morse decoder converting keyed elements back to text
+//!
+//! [`MorseDecoder`] consumes the [`Element`] stream produced by the FSM or the
+//! encoder. [`TimedDecoder`] works from raw key-down/key-up times using a fixed
+//! unit length; [`AdaptiveDecoder`] measures the sender's speed as it goes, for
+//! straight keys, bugs and received signals.
+
+use core::fmt;
+use heapless::Vec;
+use crate::encoder::MORSE_TABLE;
+use crate::types::Element;
+
+/// Prosigns without a single-character equivalent
+///
+/// AR, BT, KN and AS share their patterns with `+`, `=`, `(` and `&` and decode as those.
+const PROSIGN_TABLE: &[(&str, &str)] = &[
+    ("SK", "...-.-"),
+    ("KA", "-.-.-"),
+    ("SN", "...-."),
+    ("BK", "-...-.-"),
+    ("SOS", "...---..."),
+    ("HH", "........"),
+];
+
+/// Longest pattern the decoder can hold
+const MAX_PATTERN_LEN: u8 = 16;
+
+/// Decoder output
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Decoded {
+    /// Character from the ITU table (letters uppercase)
+    Char(char),
+    /// Prosign name, e.g. "SK"
+    Prosign(&'static str),
+    /// Gap between words
+    WordBreak,
+    /// Pattern not in any table
+    Unknown,
+}
+
+impl fmt::Display for Decoded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Decoded::Char(c) => write!(f, "{}", c),
+            Decoded::Prosign(name) => write!(f, "<{}>", name),
+            Decoded::WordBreak => write!(f, " "),
+            Decoded::Unknown => write!(f, "*"),
+        }
+    }
+}
+
+/// Output of one decoder step (a character may be followed by a word break)
+pub type DecodeOutput = Vec<Decoded, 2>;
+
+/// Dit/Dah pattern being collected (bit set = Dah, first element in bit 0)
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+struct Pattern {
+    bits: u16,
+    len: u8,
+}
+
+impl Pattern {
+    fn push(&mut self, dah: bool) {
+        // Overlong patterns keep counting so they decode as Unknown
+        if self.len < MAX_PATTERN_LEN {
+            self.bits |= (dah as u16) << self.len;
+        }
+        self.len = self.len.saturating_add(1);
+    }
+
+    fn matches(&self, pattern: &str) -> bool {
+        pattern.len() == self.len as usize
+            && pattern
+                .bytes()
+                .enumerate()
+                .all(|(i, symbol)| (symbol == b'-') == (self.bits & (1 << i) != 0))
+    }
+
+    fn decode(&self) -> Decoded {
+        if let Some((c, _)) = MORSE_TABLE.iter().find(|(_, pattern)| self.matches(pattern)) {
+            return Decoded::Char(*c as char);
+        }
+        match PROSIGN_TABLE.iter().find(|(_, pattern)| self.matches(pattern)) {
+            Some((name, _)) => Decoded::Prosign(name),
+            None => Decoded::Unknown,
+        }
+    }
+}
+
+/// Decoder for `Element` streams
+#[derive(Clone, Debug, Default)]
+pub struct MorseDecoder {
+    pattern: Pattern,
+}
+
+impl MorseDecoder {
+    /// Create new decoder
+    pub const fn new() -> Self {
+        Self { pattern: Pattern { bits: 0, len: 0 } }
+    }
+
+    /// Feed one element
+    ///
+    /// Characters are emitted at `CharSpace`/`WordSpace`; manual key-down/up is ignored.
+    pub fn push(&mut self, element: Element) -> DecodeOutput {
+        let mut output = DecodeOutput::new();
+        match element {
+            Element::Dit => self.pattern.push(false),
+            Element::Dah => self.pattern.push(true),
+            Element::CharSpace => {
+                if let Some(decoded) = self.flush() {
+                    output.push(decoded).ok();
+                }
+            }
+            Element::WordSpace => {
+                if let Some(decoded) = self.flush() {
+                    output.push(decoded).ok();
+                }
+                output.push(Decoded::WordBreak).ok();
+            }
+            Element::KeyDown | Element::KeyUp => {}
+        }
+        output
+    }
+
+    /// Decode the pending pattern, if any (end of transmission)
+    pub fn flush(&mut self) -> Option<Decoded> {
+        if self.pattern.len == 0 {
+            return None;
+        }
+        let decoded = self.pattern.decode();
+        self.pattern = Pattern::default();
+        Some(decoded)
+    }
+
+    /// Returns true if elements are waiting for a character boundary
+    pub fn has_pending(&self) -> bool {
+        self.pattern.len > 0
+    }
+
+    /// Discard the pending pattern
+    pub fn reset(&mut self) {
+        self.pattern = Pattern::default();
+    }
+}
+
+/// Decoder for timestamped key-down/key-up events
+///
+/// Marks of 2 units or more are Dahs; gaps of 2 units end a character and
+/// gaps of 5 units end a word (midpoints of the standard 1/3/7 spacing).
+#[derive(Clone, Debug)]
+pub struct TimedDecoder {
+    decoder: MorseDecoder,
+    unit_ms: u32,
+    key_down_at: Option<u32>,
+    last_key_up: Option<u32>,
+    /// Word break already reported for the current gap
+    word_reported: bool,
+}
+
+impl TimedDecoder {
+    /// Create decoder for the given unit length
+    pub const fn new(unit_ms: u32) -> Self {
+        Self {
+            decoder: MorseDecoder::new(),
+            unit_ms: if unit_ms == 0 { 1 } else { unit_ms },
+            key_down_at: None,
+            last_key_up: None,
+            word_reported: true,
+        }
+    }
+
+    /// Current unit length in milliseconds
+    pub fn unit_ms(&self) -> u32 {
+        self.unit_ms
+    }
+
+    /// Change the unit length (e.g. after a speed change)
+    pub fn set_unit_ms(&mut self, unit_ms: u32) {
+        self.unit_ms = unit_ms.max(1);
+    }
+
+    /// Key pressed: classify the gap since the last key-up
+    pub fn key_down(&mut self, now_ms: u32) -> DecodeOutput {
+        let output = self.poll(now_ms);
+        self.key_down_at = Some(now_ms);
+        self.last_key_up = None;
+        self.word_reported = false;
+        output
+    }
+
+    /// Key released: classify the mark
+    pub fn key_up(&mut self, now_ms: u32) {
+        if let Some(start) = self.key_down_at.take() {
+            let mark = now_ms.wrapping_sub(start);
+            let element = if mark >= self.unit_ms.saturating_mul(2) { Element::Dah } else { Element::Dit };
+            self.decoder.push(element);
+            self.last_key_up = Some(now_ms);
+        }
+    }
+
+    /// Check for character and word boundaries during silence
+    pub fn poll(&mut self, now_ms: u32) -> DecodeOutput {
+        let mut output = DecodeOutput::new();
+        let Some(key_up) = self.last_key_up else {
+            return output;
+        };
+
+        let gap = now_ms.wrapping_sub(key_up);
+        if gap >= self.unit_ms.saturating_mul(2) {
+            if let Some(decoded) = self.decoder.flush() {
+                output.push(decoded).ok();
+            }
+        }
+        if gap >= self.unit_ms.saturating_mul(5) && !self.word_reported {
+            self.word_reported = true;
+            output.push(Decoded::WordBreak).ok();
+        }
+        output
+    }
+}
+
+/// Dit/Dah length tracker clustering marks into two running averages
+///
+/// Each mark is classified against the midpoint of the two averages and then
+/// pulls its cluster's average towards it (EMA, 1/4 weight).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SpeedTracker {
+    dit_ms: u32,
+    dah_ms: u32,
+}
+
+impl SpeedTracker {
+    /// Create tracker starting from an initial speed guess (3:1 ratio)
+    pub const fn new(initial_wpm: u32) -> Self {
+        // Above 1200 WPM the unit would round down to 0 ms
+        let unit = match initial_wpm {
+            0 => 1200,
+            1..=1200 => 1200 / initial_wpm,
+            _ => 1,
+        };
+        Self { dit_ms: unit, dah_ms: unit * 3 }
+    }
+
+    /// Classify a mark and update the averages
+    pub fn classify(&mut self, mark_ms: u32) -> Element {
+        let threshold = (self.dit_ms + self.dah_ms) / 2;
+        let element = if mark_ms >= threshold { Element::Dah } else { Element::Dit };
+
+        let avg = if element == Element::Dah { &mut self.dah_ms } else { &mut self.dit_ms };
+        let diff = mark_ms as i64 - *avg as i64;
+        // Round the step so the average settles on the mark length
+        *avg = (*avg as i64 + (diff + 2 * diff.signum()) / 4).max(1) as u32;
+
+        // Keep the clusters apart so one-sided text cannot merge them
+        if element == Element::Dah {
+            self.dit_ms = self.dit_ms.min(self.dah_ms / 2).max(1);
+        } else {
+            self.dah_ms = self.dah_ms.max(self.dit_ms * 2);
+        }
+        element
+    }
+
+    /// Estimated unit (Dit) length in milliseconds
+    pub fn unit_ms(&self) -> u32 {
+        self.dit_ms
+    }
+
+    /// Estimated average Dah length in milliseconds
+    pub fn dah_ms(&self) -> u32 {
+        self.dah_ms
+    }
+
+    /// Measured speed in WPM (PARIS)
+    pub fn wpm(&self) -> u32 {
+        (1200 / self.dit_ms).max(1)
+    }
+
+    /// Measured dah:dit ratio in tenths (30 = 3:1)
+    pub fn dah_ratio_tenths(&self) -> u32 {
+        self.dah_ms * 10 / self.dit_ms
+    }
+}
+
+/// Decoder that follows the sender's speed, for straight keys, bugs and external signals
+///
+/// Feed it raw mark/space durations, or poll it with the key state via [`AdaptiveDecoder::update`].
+/// Spaces are judged against the measured Dit length.
+#[derive(Clone, Debug)]
+pub struct AdaptiveDecoder {
+    decoder: MorseDecoder,
+    speed: SpeedTracker,
+    key_down: bool,
+    /// Time of the last key edge (None until the first edge)
+    last_edge: Option<u32>,
+    /// Word break already reported for the current gap
+    word_reported: bool,
+}
+
+impl AdaptiveDecoder {
+    /// Create decoder starting from an initial speed guess
+    pub const fn new(initial_wpm: u32) -> Self {
+        Self {
+            decoder: MorseDecoder::new(),
+            speed: SpeedTracker::new(initial_wpm),
+            key_down: false,
+            last_edge: None,
+            word_reported: true,
+        }
+    }
+
+    /// Current speed estimate
+    pub fn speed(&self) -> &SpeedTracker {
+        &self.speed
+    }
+
+    /// Feed a mark (key-down) duration
+    pub fn push_mark(&mut self, mark_ms: u32) {
+        let element = self.speed.classify(mark_ms);
+        self.decoder.push(element);
+        self.word_reported = false;
+    }
+
+    /// Feed a space (key-up) duration, complete or still growing
+    pub fn push_space(&mut self, space_ms: u32) -> DecodeOutput {
+        let mut output = DecodeOutput::new();
+        let unit = self.speed.unit_ms();
+        if space_ms >= unit.saturating_mul(2) {
+            if let Some(decoded) = self.decoder.flush() {
+                output.push(decoded).ok();
+            }
+        }
+        if space_ms >= unit.saturating_mul(5) && !self.word_reported {
+            self.word_reported = true;
+            output.push(Decoded::WordBreak).ok();
+        }
+        output
+    }
+
+    /// Poll with the current key state (e.g. `PaddleInput::dit()` or a GPIO pin)
+    pub fn update(&mut self, key_down: bool, now_ms: u32) -> DecodeOutput {
+        let Some(edge) = self.last_edge else {
+            // Start timing from the first key-down
+            if key_down {
+                self.key_down = true;
+                self.last_edge = Some(now_ms);
+            }
+            return DecodeOutput::new();
+        };
+
+        let elapsed = now_ms.wrapping_sub(edge);
+        if key_down == self.key_down {
+            // Report boundaries while the silence grows
+            return if key_down { DecodeOutput::new() } else { self.push_space(elapsed) };
+        }
+
+        self.key_down = key_down;
+        self.last_edge = Some(now_ms);
+        if key_down {
+            self.push_space(elapsed)
+        } else {
+            self.push_mark(elapsed);
+            DecodeOutput::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoder::MorseEncoder;
+
+    fn decode_elements(text: &str) -> heapless::String<32> {
+        let mut decoder = MorseDecoder::new();
+        let mut result = heapless::String::new();
+        for element in MorseEncoder::new(text) {
+            for decoded in decoder.push(element.unwrap()) {
+                fmt::write(&mut result, format_args!("{}", decoded)).unwrap();
+            }
+        }
+        if let Some(decoded) = decoder.flush() {
+            fmt::write(&mut result, format_args!("{}", decoded)).unwrap();
+        }
+        result
+    }
+
+    #[test]
+    fn test_decode_round_trip() {
+        assert_eq!(decode_elements("CQ DE JA1ABC 5NN?").as_str(), "CQ DE JA1ABC 5NN?");
+        assert_eq!(decode_elements("73 <SK>").as_str(), "73 <SK>");
+        // AR shares its pattern with '+'
+        assert_eq!(decode_elements("<AR>").as_str(), "+");
+    }
+
+    #[test]
+    fn test_decode_unknown_and_overlong() {
+        let mut decoder = MorseDecoder::new();
+        for _ in 0..7 {
+            decoder.push(Element::Dah);
+        }
+        assert_eq!(decoder.flush(), Some(Decoded::Unknown));
+
+        for _ in 0..20 {
+            decoder.push(Element::Dit);
+        }
+        assert_eq!(decoder.flush(), Some(Decoded::Unknown));
+        assert!(decoder.flush().is_none());
+    }
+
+    /// Key a text with the given unit and dah length, returning (mark, space) pairs
+    fn keyed_timing(text: &str, unit: u32, dah: u32) -> heapless::Vec<(u32, u32), 64> {
+        let mut timing = heapless::Vec::new();
+        for element in MorseEncoder::new(text) {
+            match element.unwrap() {
+                Element::Dit => timing.push((unit, unit)).unwrap(),
+                Element::Dah => timing.push((dah, unit)).unwrap(),
+                // Stretch the gap after the last mark to 3 or 7 units
+                Element::CharSpace => timing.last_mut().unwrap().1 = unit * 3,
+                Element::WordSpace => timing.last_mut().unwrap().1 = unit * 7,
+                _ => {}
+            }
+        }
+        timing
+    }
+
+    fn decode_adaptive(decoder: &mut AdaptiveDecoder, text: &str, unit: u32, dah: u32) -> heapless::String<64> {
+        let mut result = heapless::String::new();
+        let mut now = 1000u32;
+        for (mark, space) in keyed_timing(text, unit, dah) {
+            for decoded in decoder.update(true, now) {
+                fmt::write(&mut result, format_args!("{}", decoded)).unwrap();
+            }
+            now += mark;
+            decoder.update(false, now);
+            now += space;
+        }
+        for decoded in decoder.update(false, now + unit * 7) {
+            fmt::write(&mut result, format_args!("{}", decoded)).unwrap();
+        }
+        result
+    }
+
+    #[test]
+    fn test_adaptive_decoder_follows_slower_sender() {
+        // Expecting 20 WPM, operator sends 13 WPM (unit 92ms) with a heavy 3.5:1 dah
+        let mut decoder = AdaptiveDecoder::new(20);
+        let text = decode_adaptive(&mut decoder, "PARIS PARIS TEST", 92, 322);
+        assert!(text.ends_with("PARIS TEST "), "decoded {:?}", text.as_str());
+
+        let speed = decoder.speed();
+        assert!((12..=14).contains(&speed.wpm()), "wpm {}", speed.wpm());
+        assert!((33..=37).contains(&speed.dah_ratio_tenths()), "ratio {}", speed.dah_ratio_tenths());
+    }
+
+    #[test]
+    fn test_adaptive_decoder_raw_durations() {
+        let mut decoder = AdaptiveDecoder::new(20);
+        for mark in [60, 180] {
+            decoder.push_mark(mark);
+            assert!(decoder.push_space(60).is_empty());
+        }
+        assert_eq!(decoder.push_space(180).as_slice(), &[Decoded::Char('A')]);
+        // Growing silence reports the word break once
+        assert_eq!(decoder.push_space(300).as_slice(), &[Decoded::WordBreak]);
+        assert!(decoder.push_space(600).is_empty());
+    }
+
+    #[test]
+    fn test_speed_tracker_one_sided_text() {
+        // Only dits: the dah estimate stays well above them
+        let mut speed = SpeedTracker::new(20);
+        for _ in 0..20 {
+            assert_eq!(speed.classify(50), Element::Dit);
+        }
+        assert!((50..=51).contains(&speed.unit_ms()));
+        assert!(speed.dah_ms() >= 100);
+        assert_eq!(speed.classify(150), Element::Dah);
+    }
+
+    #[test]
+    fn test_extreme_speeds_keep_a_unit() {
+        let speed = SpeedTracker::new(5000);
+        assert_eq!((speed.unit_ms(), speed.wpm(), speed.dah_ratio_tenths()), (1, 1200, 30));
+        assert_eq!(SpeedTracker::new(0).unit_ms(), 1200);
+        assert_eq!(TimedDecoder::new(0).unit_ms(), 1);
+
+        // Boundaries saturate instead of overflowing at the top end
+        let mut slow = TimedDecoder::new(u32::MAX);
+        slow.key_down(0);
+        slow.key_up(u32::MAX - 1);
+        assert!(slow.poll(u32::MAX - 1).is_empty());
+    }
+
+    #[test]
+    fn test_timed_decoder() {
+        let mut decoder = TimedDecoder::new(60);
+        let mut text = heapless::Vec::<Decoded, 8>::new();
+
+        // "A" then "N" after a character gap, then a word gap before "E"
+        for (down, up) in [(0, 60), (120, 300), (480, 660), (720, 780), (1200, 1260)] {
+            text.extend(decoder.key_down(down));
+            decoder.key_up(up);
+        }
+
+        // Silence ends the last character once, and the word once
+        assert_eq!(decoder.poll(1300).as_slice(), &[]);
+        assert_eq!(decoder.poll(1380).as_slice(), &[Decoded::Char('E')]);
+        assert_eq!(decoder.poll(1560).as_slice(), &[Decoded::WordBreak]);
+        assert_eq!(decoder.poll(2000).as_slice(), &[]);
+
+        assert_eq!(
+            text.as_slice(),
+            &[Decoded::Char('A'), Decoded::Char('N'), Decoded::WordBreak]
+        );
+    }
+}
@@ -0,0 +1,297 @@
+//! Text-to-Morse encoder producing [`Element`] streams
+//!
+//! Text is ASCII, case-insensitive. Letters are separated by `CharSpace`, words by
+//! `WordSpace`. Prosigns are written in angle brackets (`<AR>`, `<SK>`, `<BT>`, `<KN>`)
+//! and sent as their letters run together without character spaces.
+
+use heapless::spsc::Producer;
+use heapless::Vec;
+use crate::types::Element;
+
+/// Morse patterns for supported characters ('.' = Dit, '-' = Dah)
+pub(crate) const MORSE_TABLE: &[(u8, &str)] = &[
+    (b'A', ".-"), (b'B', "-..."), (b'C', "-.-."), (b'D', "-.."), (b'E', "."),
+    (b'F', "..-."), (b'G', "--."), (b'H', "...."), (b'I', ".."), (b'J', ".---"),
+    (b'K', "-.-"), (b'L', ".-.."), (b'M', "--"), (b'N', "-."), (b'O', "---"),
+    (b'P', ".--."), (b'Q', "--.-"), (b'R', ".-."), (b'S', "..."), (b'T', "-"),
+    (b'U', "..-"), (b'V', "...-"), (b'W', ".--"), (b'X', "-..-"), (b'Y', "-.--"),
+    (b'Z', "--.."),
+    (b'0', "-----"), (b'1', ".----"), (b'2', "..---"), (b'3', "...--"), (b'4', "....-"),
+    (b'5', "....."), (b'6', "-...."), (b'7', "--..."), (b'8', "---.."), (b'9', "----."),
+    (b'.', ".-.-.-"), (b',', "--..--"), (b'?', "..--.."), (b'\'', ".----."), (b'!', "-.-.--"),
+    (b'/', "-..-."), (b'(', "-.--."), (b')', "-.--.-"), (b'&', ".-..."), (b':', "---..."),
+    (b';', "-.-.-."), (b'=', "-...-"), (b'+', ".-.-."), (b'-', "-....-"), (b'_', "..--.-"),
+    (b'"', ".-..-."), (b'$', "...-..-"), (b'@', ".--.-."),
+];
+
+/// Look up the Morse pattern for a character
+pub fn char_pattern(c: char) -> Option<&'static str> {
+    if !c.is_ascii() {
+        return None;
+    }
+    let c = c.to_ascii_uppercase() as u8;
+    MORSE_TABLE
+        .iter()
+        .find(|(ch, _)| *ch == c)
+        .map(|(_, pattern)| *pattern)
+}
+
+/// Encoder errors
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum EncodeError {
+    /// Character has no Morse representation
+    UnsupportedChar(char),
+    /// Prosign is not closed, nested, or contains a space
+    InvalidProsign,
+    /// Output buffer is full
+    BufferFull,
+}
+
+#[cfg(feature = "std")]
+impl core::fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            EncodeError::UnsupportedChar(c) => write!(f, "Unsupported character {:?}", c),
+            EncodeError::InvalidProsign => write!(f, "Invalid prosign"),
+            EncodeError::BufferFull => write!(f, "Output buffer full"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for EncodeError {}
+
+/// Encoding position within a text, kept apart from the text itself
+///
+/// Lets owners of a text buffer (e.g. a message player) resume encoding later
+/// without borrowing the text in between. Always pass the same text.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct EncoderState {
+    pos: usize,
+    /// Remaining elements of the current character
+    pattern: &'static [u8],
+    /// A character was sent since the last word space
+    after_char: bool,
+    /// Inside `<...>`, and whether a letter of it has been sent
+    in_prosign: bool,
+    prosign_started: bool,
+    failed: bool,
+}
+
+impl EncoderState {
+    /// Start at the beginning of a text
+    pub const fn new() -> Self {
+        Self {
+            pos: 0,
+            pattern: &[],
+            after_char: false,
+            in_prosign: false,
+            prosign_started: false,
+            failed: false,
+        }
+    }
+
+    /// Returns true once all of `text` has been encoded (or encoding failed)
+    pub fn is_finished(&self, text: &str) -> bool {
+        self.failed || (self.pattern.is_empty() && self.pos >= text.len())
+    }
+
+    /// Enqueue elements of `text` while the queue holds fewer than `limit`
+    /// Returns the number of elements enqueued
+    pub fn fill<const N: usize>(
+        &mut self,
+        text: &str,
+        queue: &mut Producer<'_, Element, N>,
+        limit: usize,
+    ) -> Result<usize, EncodeError> {
+        let mut count = 0;
+        while queue.ready() && queue.len() < limit {
+            match self.next_element(text) {
+                Some(Ok(element)) => {
+                    // Cannot fail: the queue reported free space
+                    queue.enqueue(element).ok();
+                    count += 1;
+                }
+                Some(Err(error)) => return Err(error),
+                None => break,
+            }
+        }
+        Ok(count)
+    }
+
+    fn fail(&mut self, error: EncodeError) -> Option<Result<Element, EncodeError>> {
+        self.failed = true;
+        Some(Err(error))
+    }
+
+    /// Produce the next element of `text`; stops after the first error
+    pub fn next_element(&mut self, text: &str) -> Option<Result<Element, EncodeError>> {
+        if self.failed {
+            return None;
+        }
+        let text = text.as_bytes();
+
+        loop {
+            if let Some((&symbol, rest)) = self.pattern.split_first() {
+                self.pattern = rest;
+                return Some(Ok(if symbol == b'.' { Element::Dit } else { Element::Dah }));
+            }
+
+            let Some(&byte) = text.get(self.pos) else {
+                if self.in_prosign {
+                    return self.fail(EncodeError::InvalidProsign);
+                }
+                return None;
+            };
+            self.pos += 1;
+
+            match byte {
+                b'<' if !self.in_prosign => {
+                    self.in_prosign = true;
+                    self.prosign_started = false;
+                }
+                b'>' if self.in_prosign => self.in_prosign = false,
+                b'<' | b'>' => return self.fail(EncodeError::InvalidProsign),
+                _ if byte.is_ascii_whitespace() => {
+                    if self.in_prosign {
+                        return self.fail(EncodeError::InvalidProsign);
+                    }
+                    // Runs of whitespace collapse into one word space
+                    if self.after_char {
+                        self.after_char = false;
+                        return Some(Ok(Element::WordSpace));
+                    }
+                }
+                _ => {
+                    let Some(pattern) = char_pattern(byte as char) else {
+                        return self.fail(EncodeError::UnsupportedChar(byte as char));
+                    };
+                    self.pattern = pattern.as_bytes();
+
+                    // Letters inside a prosign run together
+                    let joined = self.in_prosign && self.prosign_started;
+                    self.prosign_started |= self.in_prosign;
+                    let char_space = self.after_char && !joined;
+                    self.after_char = true;
+                    if char_space {
+                        return Some(Ok(Element::CharSpace));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Resumable text-to-Morse encoder
+///
+/// Iterates over `Result<Element, EncodeError>` and stops after the first error.
+/// [`MorseEncoder::fill`] feeds a keyer queue and can be called again once the
+/// sender has made room.
+#[derive(Clone, Debug)]
+pub struct MorseEncoder<'a> {
+    text: &'a str,
+    state: EncoderState,
+}
+
+impl<'a> MorseEncoder<'a> {
+    /// Create encoder for ASCII text
+    pub fn new(text: &'a str) -> Self {
+        Self { text, state: EncoderState::new() }
+    }
+
+    /// Returns true once all text has been encoded (or encoding failed)
+    pub fn is_finished(&self) -> bool {
+        self.state.is_finished(self.text)
+    }
+
+    /// Enqueue as many elements as the queue accepts
+    /// Returns the number of elements enqueued
+    pub fn fill<const N: usize>(&mut self, queue: &mut Producer<'_, Element, N>) -> Result<usize, EncodeError> {
+        self.state.fill(self.text, queue, usize::MAX)
+    }
+}
+
+impl Iterator for MorseEncoder<'_> {
+    type Item = Result<Element, EncodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.state.next_element(self.text)
+    }
+}
+
+/// Check that text can be encoded
+pub fn validate(text: &str) -> Result<(), EncodeError> {
+    MorseEncoder::new(text).try_for_each(|element| element.map(|_| ()))
+}
+
+/// Encode text into a fixed-capacity element buffer
+pub fn encode<const N: usize>(text: &str) -> Result<Vec<Element, N>, EncodeError> {
+    let mut elements = Vec::new();
+    for element in MorseEncoder::new(text) {
+        elements.push(element?).map_err(|_| EncodeError::BufferFull)?;
+    }
+    Ok(elements)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use heapless::spsc::Queue;
+    use Element::{CharSpace as C, Dah as A, Dit as I, WordSpace as W};
+
+    #[test]
+    fn test_encode_words() {
+        let elements = encode::<32>("Hi  5").unwrap();
+        assert_eq!(elements.as_slice(), &[I, I, I, I, C, I, I, W, I, I, I, I, I]);
+
+        // Leading whitespace is dropped, trailing whitespace ends the word
+        let elements = encode::<8>(" e ").unwrap();
+        assert_eq!(elements.as_slice(), &[I, W]);
+    }
+
+    #[test]
+    fn test_encode_prosigns() {
+        // <AR> = .-.-. with no character space between A and R
+        assert_eq!(encode::<8>("<AR>").unwrap().as_slice(), &[I, A, I, A, I]);
+        assert_eq!(encode::<8>("<sk>").unwrap().as_slice(), &[I, I, I, A, I, A]);
+        assert_eq!(encode::<8>("<BT>").unwrap().as_slice(), &[A, I, I, I, A]);
+
+        // Prosigns are spaced like any other character
+        let elements = encode::<16>("E<KN>T").unwrap();
+        assert_eq!(elements.as_slice(), &[I, C, A, I, A, A, I, C, A]);
+    }
+
+    #[test]
+    fn test_encode_errors() {
+        assert_eq!(validate("CQ DE JA1ABC"), Ok(()));
+        assert_eq!(validate("CQ#"), Err(EncodeError::UnsupportedChar('#')));
+        assert_eq!(validate("<AR"), Err(EncodeError::InvalidProsign));
+        assert_eq!(validate("<A R>"), Err(EncodeError::InvalidProsign));
+        assert_eq!(validate("<<AR>>"), Err(EncodeError::InvalidProsign));
+        assert_eq!(validate("AR>"), Err(EncodeError::InvalidProsign));
+        assert_eq!(encode::<4>("HI"), Err(EncodeError::BufferFull));
+
+        // The iterator stops after an error
+        let mut encoder = MorseEncoder::new("#E");
+        assert!(matches!(encoder.next(), Some(Err(_))));
+        assert!(encoder.next().is_none());
+        assert!(encoder.is_finished());
+    }
+
+    #[test]
+    fn test_fill_resumes_when_queue_drains() {
+        let mut queue = Queue::<Element, 4>::new();
+        let (mut producer, mut consumer) = queue.split();
+        let mut encoder = MorseEncoder::new("SOS");
+
+        let mut sent = Vec::<Element, 16>::new();
+        while !encoder.is_finished() {
+            encoder.fill(&mut producer).unwrap();
+            while let Some(element) = consumer.dequeue() {
+                sent.push(element).unwrap();
+            }
+        }
+        assert_eq!(sent, encode::<16>("SOS").unwrap());
+        assert_eq!(sent.len(), 11);
+    }
+}
@@ -1,15 +1,43 @@
 //! Finite State Machine implementation for iambic keyer
+//!
+//! The FSM is element-synchronous: after queueing a keyed element it waits for the
+//! sender to report key-up before deciding the next one. Paddle presses that begin
+//! while an element is in flight are latched and evaluated at the element boundary.
 
 use crate::hal::Instant;
 use heapless::spsc::Producer;
-use crate::types::{Element, FSMState, KeyerConfig, KeyerMode};
+use crate::types::{Element, FSMState, KeyerConfig, KeyerMode, SqueezeTieRule};
 use crate::controller::{PaddleInput, SuperKeyerController};
+use crate::sender::SenderFeedback;
+#[cfg(feature = "embassy-time")]
+use crate::shared::SharedConfig;
+#[cfg(feature = "embassy-time")]
+use crate::message::{MessagePlayer, PlaybackControl, PlaybackStatus};
+#[cfg(feature = "embassy-time")]
+use crate::command::CommandMode;
 
 /// Main keyer FSM implementation
 pub struct KeyerFSM {
     state: FSMState,
     config: KeyerConfig,
     superkeyer: SuperKeyerController,
+    last_element: Option<Element>,
+    /// A keyed element is queued or being keyed
+    in_flight: bool,
+    /// Key-down time of the element being keyed
+    keying_since: Option<Instant>,
+    /// Key-up time of the last finished element
+    last_key_up: Option<Instant>,
+    /// Paddle presses latched while an element was in flight
+    dit_latch: bool,
+    dah_latch: bool,
+    /// Paddle state seen on the previous update (for press edge detection)
+    dit_prev: bool,
+    dah_prev: bool,
+    /// Sender is keying an element, queued by the FSM or by another producer
+    sender_keying: bool,
+    /// Waiting for another producer's elements to be sent
+    draining: bool,
 }
 
 impl KeyerFSM {
@@ -19,6 +47,16 @@ impl KeyerFSM {
             state: FSMState::Idle,
             config,
             superkeyer: SuperKeyerController::new(),
+            last_element: None,
+            in_flight: false,
+            keying_since: None,
+            last_key_up: None,
+            dit_latch: false,
+            dah_latch: false,
+            dit_prev: false,
+            dah_prev: false,
+            sender_keying: false,
+            draining: false,
         }
     }
 
@@ -30,15 +68,60 @@ impl KeyerFSM {
     /// Update FSM state and generate output elements
     /// Returns the number of elements enqueued
     pub fn update<const N: usize>(&mut self, paddle: &PaddleInput, queue: &mut Producer<'_, Element, N>) -> usize {
-        let dit_now = paddle.dit();
-        let dah_now = paddle.dah();
-        let both_pressed = dit_now && dah_now;
-        let both_released = !dit_now && !dah_now;
-        let now = Instant::now();
+        self.update_at(Instant::now(), paddle, queue)
+    }
+
+    /// Update FSM state using an externally supplied time
+    ///
+    /// Use this with a [`Clock`](crate::hal::Clock) implementation when
+    /// `Instant::now()` is not meaningful (host tests, simulators, bare-metal tick counters).
+    /// Returns the number of elements enqueued
+    pub fn update_at<const N: usize>(&mut self, now: Instant, paddle: &PaddleInput, queue: &mut Producer<'_, Element, N>) -> usize {
+        let (dit_now, dah_now) = self.swap(paddle.dit(), paddle.dah());
         
         // Update SuperKeyer controller if in SuperKeyer mode
         if self.config.mode == KeyerMode::SuperKeyer {
-            self.superkeyer.update(paddle);
+            self.superkeyer.record_press_at(dit_now, dah_now, now);
+        }
+
+        // Latch fresh presses while the sender is busy; evaluate at the element boundary
+        self.dit_latch |= dit_now && !self.dit_prev;
+        self.dah_latch |= dah_now && !self.dah_prev;
+        self.dit_prev = dit_now;
+        self.dah_prev = dah_now;
+
+        if self.draining {
+            if queue.len() > 0 || self.sender_keying {
+                return 0;
+            }
+            self.draining = false;
+        }
+        if self.in_flight {
+            return 0;
+        }
+
+        let mut dit = dit_now || self.dit_latch;
+        let mut dah = dah_now || self.dah_latch;
+        self.dit_latch = false;
+        self.dah_latch = false;
+
+        // A single lever cannot squeeze: the lever pressed last wins
+        if self.config.single_lever && dit && dah {
+            let last = match (dit_now, dah_now) {
+                (true, false) => Element::Dit,
+                (false, true) => Element::Dah,
+                _ => self.squeeze_tie_element(),
+            };
+            dit = last == Element::Dit;
+            dah = last == Element::Dah;
+        }
+
+        match self.config.mode {
+            // Manual keying follows the raw (debounced) paddle, never a latched press
+            KeyerMode::Bug => return self.handle_bug_mode(dit, dah_now, queue),
+            // Dah is ignored: a mono plug shorts the ring contact
+            KeyerMode::StraightKey => return self.handle_straight_key(dit_now, queue),
+            _ => {}
         }
 
         let mut elements_sent = 0;
@@ -46,19 +129,19 @@ impl KeyerFSM {
         // State machine transitions
         match self.state {
             FSMState::Idle => {
-                elements_sent += self.handle_idle_state(dit_now, dah_now, both_pressed, queue);
+                elements_sent += self.handle_idle_state(paddle, dit, dah, queue);
             }
 
             FSMState::DitHold => {
-                elements_sent += self.handle_dit_hold_state(dit_now, dah_now, both_pressed, queue);
+                elements_sent += self.handle_dit_hold_state(paddle, dit, dah, now, queue);
             }
 
             FSMState::DahHold => {
-                elements_sent += self.handle_dah_hold_state(dit_now, dah_now, both_pressed, queue);
+                elements_sent += self.handle_dah_hold_state(paddle, dit, dah, now, queue);
             }
 
             FSMState::Squeeze(last_element) => {
-                elements_sent += self.handle_squeeze_state(dit_now, dah_now, both_pressed, both_released, last_element, now, queue);
+                elements_sent += self.handle_squeeze_state(paddle, dit, dah, last_element, now, queue);
             }
 
             FSMState::MemoryPending(memory_element) => {
@@ -66,46 +149,112 @@ impl KeyerFSM {
             }
 
             FSMState::CharSpacePending(start_time) => {
-                elements_sent += self.handle_char_space_pending_state(dit_now, dah_now, both_pressed, start_time, now, queue);
+                elements_sent += self.handle_char_space_pending_state(paddle, dit, dah, start_time, now, queue);
+            }
+
+            FSMState::WordSpacePending(start_time) => {
+                elements_sent += self.handle_word_space_pending_state(paddle, dit, dah, start_time, now, queue);
+            }
+
+            FSMState::KeyDown => {
+                // Left over from a manual keying mode: release the key
+                elements_sent += self.handle_key_down_state(false, queue);
             }
         }
 
         elements_sent
     }
 
+    /// Apply progress reported by the sender
+    pub fn sync_with_sender(&mut self, feedback: &SenderFeedback, now: Instant) {
+        let (started, finished) = feedback.take_events();
+        if started {
+            self.element_started(now);
+        }
+        if finished {
+            self.element_finished(now);
+        }
+    }
+
+    /// Sender began keying the queued element
+    pub fn element_started(&mut self, now: Instant) {
+        self.sender_keying = true;
+        if self.in_flight {
+            self.keying_since = Some(now);
+        }
+    }
+
+    /// Sender released the key at the end of the element
+    pub fn element_finished(&mut self, now: Instant) {
+        self.sender_keying = false;
+        if !self.in_flight {
+            return;
+        }
+        self.in_flight = false;
+        self.keying_since = None;
+        self.last_key_up = Some(now);
+
+        // Character space counts from key-up, not from when the release was decided
+        if let FSMState::CharSpacePending(_) = self.state {
+            self.state = FSMState::CharSpacePending(now);
+        }
+    }
+
+    /// Returns true while a keyed element is queued or being keyed
+    pub fn element_in_flight(&self) -> bool {
+        self.in_flight
+    }
+
+    /// Hold off until elements queued by another producer have been sent
+    ///
+    /// Call when message playback ends or is aborted: the FSM must not count
+    /// the sender's progress on those elements as its own. Presses in the
+    /// meantime are latched as usual.
+    pub fn wait_for_sender(&mut self) {
+        self.draining = true;
+    }
+
+    /// Key-down time of the element currently being keyed
+    pub fn keying_since(&self) -> Option<Instant> {
+        self.keying_since
+    }
+
     /// Handle Idle state transitions
-    fn handle_idle_state<const N: usize>(&mut self, dit_now: bool, dah_now: bool, both_pressed: bool, queue: &mut Producer<'_, Element, N>) -> usize {
-        if both_pressed {
-            let start_element = self.determine_squeeze_start();
-            if queue.enqueue(start_element).is_ok() {
+    fn handle_idle_state<const N: usize>(&mut self, paddle: &PaddleInput, dit_now: bool, dah_now: bool, queue: &mut Producer<'_, Element, N>) -> usize {
+        if dit_now && dah_now {
+            let start_element = self.determine_squeeze_start(paddle);
+            if self.enqueue_element(start_element, queue) {
                 self.state = FSMState::Squeeze(start_element);
                 return 1;
             }
         } else if dit_now {
-            if queue.enqueue(Element::Dit).is_ok() {
+            if self.enqueue_element(Element::Dit, queue) {
                 self.state = FSMState::DitHold;
                 return 1;
             }
-        } else if dah_now {
-            if queue.enqueue(Element::Dah).is_ok() {
-                self.state = FSMState::DahHold;
-                return 1;
-            }
+        } else if dah_now && self.enqueue_element(Element::Dah, queue) {
+            self.state = FSMState::DahHold;
+            return 1;
         }
         0
     }
 
     /// Handle DitHold state transitions
-    fn handle_dit_hold_state<const N: usize>(&mut self, dit_now: bool, _dah_now: bool, both_pressed: bool, queue: &mut Producer<'_, Element, N>) -> usize {
-        if both_pressed {
-            self.state = FSMState::Squeeze(Element::Dit);
-            0
+    fn handle_dit_hold_state<const N: usize>(&mut self, paddle: &PaddleInput, dit_now: bool, dah_now: bool, now: Instant, queue: &mut Producer<'_, Element, N>) -> usize {
+        if dit_now && dah_now {
+            // Squeeze joined during Dit - continue with the squeeze sequence right away
+            self.handle_squeeze_state(paddle, dit_now, dah_now, Element::Dit, now, queue)
         } else if !dit_now {
-            self.transition_to_idle_or_char_space();
-            0
+            if dah_now {
+                // Moved straight to the other lever (e.g. single-lever paddle): same character
+                self.handle_idle_state(paddle, false, true, queue)
+            } else {
+                self.transition_to_idle_or_char_space(now);
+                0
+            }
         } else {
             // Continue holding Dit - send another Dit element
-            if queue.enqueue(Element::Dit).is_ok() {
+            if self.enqueue_element(Element::Dit, queue) {
                 1
             } else {
                 0
@@ -114,16 +263,21 @@ impl KeyerFSM {
     }
 
     /// Handle DahHold state transitions
-    fn handle_dah_hold_state<const N: usize>(&mut self, _dit_now: bool, dah_now: bool, both_pressed: bool, queue: &mut Producer<'_, Element, N>) -> usize {
-        if both_pressed {
-            self.state = FSMState::Squeeze(Element::Dah);
-            0
+    fn handle_dah_hold_state<const N: usize>(&mut self, paddle: &PaddleInput, dit_now: bool, dah_now: bool, now: Instant, queue: &mut Producer<'_, Element, N>) -> usize {
+        if dit_now && dah_now {
+            // Squeeze joined during Dah - continue with the squeeze sequence right away
+            self.handle_squeeze_state(paddle, dit_now, dah_now, Element::Dah, now, queue)
         } else if !dah_now {
-            self.transition_to_idle_or_char_space();
-            0
+            if dit_now {
+                // Moved straight to the other lever (e.g. single-lever paddle): same character
+                self.handle_idle_state(paddle, true, false, queue)
+            } else {
+                self.transition_to_idle_or_char_space(now);
+                0
+            }
         } else {
             // Continue holding Dah - send another Dah element
-            if queue.enqueue(Element::Dah).is_ok() {
+            if self.enqueue_element(Element::Dah, queue) {
                 1
             } else {
                 0
@@ -134,43 +288,46 @@ impl KeyerFSM {
     /// Handle Squeeze state transitions
     fn handle_squeeze_state<const N: usize>(
         &mut self,
+        paddle: &PaddleInput,
         dit_now: bool,
         dah_now: bool,
-        both_pressed: bool,
-        both_released: bool,
         last_element: Element,
         now: Instant,
         queue: &mut Producer<'_, Element, N>
     ) -> usize {
-        if both_pressed {
-            // Continue squeeze - send alternating element
-            let next_element = self.determine_next_squeeze_element(last_element);
-            if queue.enqueue(next_element).is_ok() {
+        if dit_now && dah_now {
+            // Continue squeeze - send next element for this mode
+            let next_element = self.determine_next_squeeze_element(paddle, last_element);
+            if self.enqueue_element(next_element, queue) {
                 self.state = FSMState::Squeeze(next_element);
                 return 1;
             }
         } else if dit_now {
             // Only Dit pressed - transition to DitHold
-            if queue.enqueue(Element::Dit).is_ok() {
+            if self.enqueue_element(Element::Dit, queue) {
                 self.state = FSMState::DitHold;
                 return 1;
             }
         } else if dah_now {
             // Only Dah pressed - transition to DahHold
-            if queue.enqueue(Element::Dah).is_ok() {
+            if self.enqueue_element(Element::Dah, queue) {
                 self.state = FSMState::DahHold;
                 return 1;
             }
-        } else if both_released {
+        } else {
             // Squeeze released - handle memory based on mode
             self.handle_squeeze_release(last_element, now);
+            if let FSMState::MemoryPending(memory_element) = self.state {
+                // Memory element follows directly at this element boundary
+                return self.handle_memory_pending_state(memory_element, now, queue);
+            }
         }
         0
     }
 
     /// Handle MemoryPending state
     fn handle_memory_pending_state<const N: usize>(&mut self, memory_element: Element, now: Instant, queue: &mut Producer<'_, Element, N>) -> usize {
-        if queue.enqueue(memory_element).is_ok() {
+        if self.enqueue_element(memory_element, queue) {
             // Memory element sent, clear SuperKeyer history and transition
             if self.config.mode == KeyerMode::SuperKeyer {
                 self.superkeyer.clear_history();
@@ -183,65 +340,219 @@ impl KeyerFSM {
     }
 
     /// Handle CharSpacePending state
+    ///
+    /// The gap follows [`KeyerConfig::char_space_duration`], so Farnsworth spacing stretches it.
     fn handle_char_space_pending_state<const N: usize>(
         &mut self,
+        paddle: &PaddleInput,
         dit_now: bool,
         dah_now: bool,
-        both_pressed: bool,
         start_time: Instant,
         now: Instant,
         queue: &mut Producer<'_, Element, N>
     ) -> usize {
         let elapsed = now.duration_since(start_time);
-        let char_space_duration = self.config.char_space_duration();
+
+        if elapsed < self.config.char_space_duration() {
+            // Input too early, keep it latched and remain in CharSpacePending
+            self.dit_latch |= dit_now;
+            self.dah_latch |= dah_now;
+            return 0;
+        }
+
+        // Character space complete: mark the boundary (the silence has already elapsed)
+        let mut elements_sent = 0;
+        if self.enqueue_element(Element::CharSpace, queue) {
+            elements_sent += 1;
+        }
 
         if dit_now || dah_now {
-            if elapsed >= char_space_duration {
-                // Character space complete, start new transmission
-                return self.handle_idle_state(dit_now, dah_now, both_pressed, queue);
+            // Start new transmission
+            elements_sent += self.handle_idle_state(paddle, dit_now, dah_now, queue);
+        } else {
+            // Keep timing from the same key-up towards a word space
+            self.state = FSMState::WordSpacePending(start_time);
+        }
+        elements_sent
+    }
+
+    /// Handle WordSpacePending state - character space sent, waiting for a word gap
+    fn handle_word_space_pending_state<const N: usize>(
+        &mut self,
+        paddle: &PaddleInput,
+        dit_now: bool,
+        dah_now: bool,
+        start_time: Instant,
+        now: Instant,
+        queue: &mut Producer<'_, Element, N>
+    ) -> usize {
+        if dit_now || dah_now {
+            // Next character of the same word
+            return self.handle_idle_state(paddle, dit_now, dah_now, queue);
+        }
+
+        if now.duration_since(start_time) >= self.config.word_space_duration() {
+            self.state = FSMState::Idle;
+            if self.enqueue_element(Element::WordSpace, queue) {
+                return 1;
+            }
+        }
+        0
+    }
+
+    /// Handle Bug mode: automatic dits on the Dit lever, manual keying on the Dah lever
+    ///
+    /// Spacing is left to the operator's hand, so no character space is enforced.
+    fn handle_bug_mode<const N: usize>(&mut self, dit_now: bool, manual_now: bool, queue: &mut Producer<'_, Element, N>) -> usize {
+        match self.state {
+            FSMState::KeyDown => self.handle_key_down_state(manual_now, queue),
+            _ if manual_now => self.start_key_down(queue),
+            _ if dit_now => {
+                if self.enqueue_element(Element::Dit, queue) {
+                    self.state = FSMState::DitHold;
+                    1
+                } else {
+                    0
+                }
+            }
+            _ => {
+                self.state = FSMState::Idle;
+                0
             }
-            // Input too early, remain in CharSpacePending
-        } else if elapsed >= char_space_duration {
-            // Character space complete, return to Idle
+        }
+    }
+
+    /// Handle straight key passthrough: key follows the input with no timing
+    fn handle_straight_key<const N: usize>(&mut self, key_now: bool, queue: &mut Producer<'_, Element, N>) -> usize {
+        match self.state {
+            FSMState::KeyDown => self.handle_key_down_state(key_now, queue),
+            _ if key_now => self.start_key_down(queue),
+            _ => {
+                self.state = FSMState::Idle;
+                0
+            }
+        }
+    }
+
+    /// Start manual keying
+    fn start_key_down<const N: usize>(&mut self, queue: &mut Producer<'_, Element, N>) -> usize {
+        if self.enqueue_element(Element::KeyDown, queue) {
+            self.state = FSMState::KeyDown;
+            1
+        } else {
+            0
+        }
+    }
+
+    /// Handle manual KeyDown state - key stays down until the lever is released
+    fn handle_key_down_state<const N: usize>(&mut self, manual_now: bool, queue: &mut Producer<'_, Element, N>) -> usize {
+        if !manual_now && self.enqueue_element(Element::KeyUp, queue) {
             self.state = FSMState::Idle;
+            return 1;
         }
         0
     }
 
+    /// Enqueue an element and remember it as the last element sent
+    fn enqueue_element<const N: usize>(&mut self, element: Element, queue: &mut Producer<'_, Element, N>) -> bool {
+        if queue.enqueue(element).is_ok() {
+            if matches!(element, Element::Dit | Element::Dah) {
+                self.last_element = Some(element);
+            }
+            // Manual keying has no end the sender could report, so never wait on it
+            self.in_flight = element.is_keyed() && !element.is_manual();
+            true
+        } else {
+            false
+        }
+    }
+
     /// Determine which element to start with in squeeze mode
-    fn determine_squeeze_start(&mut self) -> Element {
+    fn determine_squeeze_start(&mut self, paddle: &PaddleInput) -> Element {
         match self.config.mode {
             KeyerMode::SuperKeyer => {
                 self.superkeyer.determine_priority().unwrap_or(Element::Dit)
             }
             // For Mode A and B, use first-pressed priority (timestamp-based)
-            KeyerMode::ModeA | KeyerMode::ModeB => {
-                // This should be determined by the PaddleInput based on edge times
-                // For now, default to Dit (will be enhanced with proper timestamp logic)
-                Element::Dit
+            // Manual keying modes never squeeze; they share the Mode A/B rule for completeness
+            KeyerMode::ModeA | KeyerMode::ModeB | KeyerMode::Bug | KeyerMode::StraightKey => {
+                self.first_pressed(paddle).unwrap_or_else(|| self.squeeze_tie_element())
+            }
+            // Ultimatic: last-pressed paddle wins
+            KeyerMode::Ultimatic => {
+                self.last_pressed(paddle).unwrap_or_else(|| self.squeeze_tie_element())
             }
         }
     }
 
+    /// Dit and Dah as seen by the keyer: the shared input reports physical
+    /// contacts, and a straight key is always on the jack tip whatever the wiring
+    fn swap<T>(&self, dit: T, dah: T) -> (T, T) {
+        if self.config.paddle_swap && self.config.mode != KeyerMode::StraightKey {
+            (dah, dit)
+        } else {
+            (dit, dah)
+        }
+    }
+
+    /// Paddle pressed first, if the edge times tell them apart
+    fn first_pressed(&self, paddle: &PaddleInput) -> Option<Element> {
+        let (dit, dah) = paddle.get_press_times();
+        match self.swap(dit, dah) {
+            // Wrapping difference keeps the comparison valid across tick counter overflow
+            (Some(dit), Some(dah)) if dit != dah => {
+                if (dah.wrapping_sub(dit) as i32) > 0 {
+                    Some(Element::Dit)
+                } else {
+                    Some(Element::Dah)
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Paddle pressed last; a latched press that is already released counts as the latest
+    fn last_pressed(&self, paddle: &PaddleInput) -> Option<Element> {
+        let (dit, dah) = paddle.get_press_times();
+        match self.swap(dit, dah) {
+            (Some(_), None) => Some(Element::Dah),
+            (None, Some(_)) => Some(Element::Dit),
+            _ => self.first_pressed(paddle).map(|first| first.opposite()),
+        }
+    }
+
+    /// Resolve a simultaneous squeeze using the configured tie rule
+    fn squeeze_tie_element(&self) -> Element {
+        match self.config.squeeze_tie {
+            SqueezeTieRule::Dit => Element::Dit,
+            SqueezeTieRule::Dah => Element::Dah,
+            SqueezeTieRule::Alternate => self.last_element.map_or(Element::Dit, |last| last.opposite()),
+        }
+    }
+
     /// Determine next element in squeeze sequence
-    fn determine_next_squeeze_element(&mut self, last_element: Element) -> Element {
+    fn determine_next_squeeze_element(&mut self, paddle: &PaddleInput, last_element: Element) -> Element {
         match self.config.mode {
             KeyerMode::SuperKeyer => {
                 self.superkeyer.next_element(true, Some(last_element)).unwrap_or_else(|| last_element.opposite())
             }
-            KeyerMode::ModeA | KeyerMode::ModeB => {
+            KeyerMode::ModeA | KeyerMode::ModeB | KeyerMode::Bug | KeyerMode::StraightKey => {
                 // Standard alternating behavior
                 last_element.opposite()
             }
+            KeyerMode::Ultimatic => {
+                // Last-pressed paddle repeats instead of alternating
+                self.last_pressed(paddle).unwrap_or(last_element)
+            }
         }
     }
 
     /// Handle squeeze release based on keyer mode
     fn handle_squeeze_release(&mut self, last_element: Element, now: Instant) {
         match self.config.mode {
-            KeyerMode::ModeA => {
-                // Mode A: immediate return to Idle/CharSpace
-                self.transition_to_idle_or_char_space_at_time(now);
+            KeyerMode::ModeA | KeyerMode::Ultimatic | KeyerMode::Bug | KeyerMode::StraightKey => {
+                // Mode A / Ultimatic: no memory, immediate return to Idle/CharSpace
+                self.transition_to_idle_or_char_space(now);
             }
             KeyerMode::ModeB => {
                 // Mode B: send opposite element once
@@ -254,15 +565,15 @@ impl KeyerFSM {
                 if let Some(memory) = self.superkeyer.take_memory() {
                     self.state = FSMState::MemoryPending(memory);
                 } else {
-                    self.transition_to_idle_or_char_space_at_time(now);
+                    self.transition_to_idle_or_char_space(now);
                 }
             }
         }
     }
 
-    /// Transition to Idle or CharSpacePending based on configuration
-    fn transition_to_idle_or_char_space(&mut self) {
-        self.transition_to_idle_or_char_space_at_time(Instant::now());
+    /// Transition to Idle or CharSpacePending, counting from the last key-up
+    fn transition_to_idle_or_char_space(&mut self, now: Instant) {
+        self.transition_to_idle_or_char_space_at_time(self.last_key_up.unwrap_or(now));
     }
 
     /// Transition to Idle or CharSpacePending at specific time
@@ -277,6 +588,13 @@ impl KeyerFSM {
     /// Reset FSM to initial state
     pub fn reset(&mut self) {
         self.state = FSMState::Idle;
+        self.last_element = None;
+        self.in_flight = false;
+        self.keying_since = None;
+        self.last_key_up = None;
+        self.dit_latch = false;
+        self.dah_latch = false;
+        self.draining = false;
         self.superkeyer.clear_history();
     }
 
@@ -295,24 +613,78 @@ impl KeyerFSM {
 }
 
 /// Async task for running the FSM evaluator
+///
+/// The sender must report key-down/key-up through `feedback`; the next element
+/// is only queued once the previous one has finished. Changes published to
+/// `config` are applied at the next element boundary.
+///
+/// Messages requested through `playback` take over the queue until they finish
+/// or a paddle is pressed; the press is then evaluated by the FSM right away.
+/// A long squeeze enters [`CommandMode`], whose settings are published to `config`.
 #[cfg(feature = "embassy-time")]
 pub async fn evaluator_task<const N: usize>(
     paddle: &PaddleInput,
+    feedback: &SenderFeedback,
     mut queue_producer: Producer<'_, Element, N>,
-    config: KeyerConfig,
+    config: &SharedConfig,
+    playback: &PlaybackControl,
 ) {
-    use embassy_time::Timer;
+    use embassy_time::{Duration, Timer};
     
-    let mut fsm = KeyerFSM::new(config);
-    let update_interval = config.unit / 4; // Update FSM at unit/4 intervals
+    let mut seen = config.generation();
+    let mut fsm = KeyerFSM::new(config.get().unwrap_or_default());
+    let mut player = MessagePlayer::new();
+    let mut command = CommandMode::default();
 
     loop {
-        let _elements_sent = fsm.update(paddle, &mut queue_producer);
+        let now = Instant::now();
+        fsm.sync_with_sender(feedback, now);
+
+        if command.is_active() {
+            if let Some(new_config) = command.update(now, &mut fsm, paddle, &mut queue_producer) {
+                // Settings only become permanent once command mode exits
+                if command.is_active() {
+                    config.set_temporary(new_config);
+                } else {
+                    config.set(new_config);
+                }
+                seen = config.generation();
+            }
+        } else if command.watch(now, paddle, &fsm) {
+            player.abort();
+            config.set_temporary(command.enter(now, &mut fsm));
+            seen = config.generation();
+        } else {
+            if !fsm.element_in_flight() {
+                if let Some(new_config) = config.changed_since(&mut seen) {
+                    fsm.set_config(new_config);
+                }
+                let was_playing = player.is_playing();
+                playback.apply(&mut player);
+                if player.is_playing() && !was_playing {
+                    // Drop any pending character space from manual keying
+                    fsm.reset();
+                }
+            }
+
+            let status = player.update(paddle, &mut queue_producer);
+            if matches!(status, PlaybackStatus::Finished | PlaybackStatus::Aborted) {
+                fsm.wait_for_sender();
+            }
+            if matches!(status, PlaybackStatus::Idle | PlaybackStatus::Aborted) {
+                let _elements_sent = fsm.update_at(now, paddle, &mut queue_producer);
+            }
+        }
         
         // Optional: Log state transitions for debugging
         #[cfg(feature = "defmt")]
         defmt::trace!("FSM State: {:?}", fsm.current_state());
 
+        // Update FSM at unit/4 intervals; a straight key needs no timing, only low latency
+        let update_interval = match fsm.config().mode {
+            KeyerMode::StraightKey => Duration::from_millis(1),
+            _ => fsm.config().unit / 4,
+        };
         Timer::after(update_interval).await;
     }
 }
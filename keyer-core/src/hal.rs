@@ -64,6 +64,25 @@ mod mock_time {
 use embedded_hal::digital::{InputPin, OutputPin};
 use crate::types::PaddleSide;
 
+/// Time source for keyer timing
+///
+/// Lets host tests, simulators and bare-metal ports supply their own notion of
+/// "now" instead of relying on `Instant::now()`.
+pub trait Clock {
+    /// Get the current time
+    fn now(&self) -> Instant;
+}
+
+/// Clock backed by `Instant::now()`
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
 /// Error types for HAL operations
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum HalError {
@@ -132,6 +151,17 @@ pub trait OutputKey {
     }
 }
 
+/// Trait for an analog speed control (potentiometer on an ADC channel)
+pub trait AnalogSpeedInput {
+    type Error: From<HalError>;
+
+    /// Read a raw conversion result in `0..=full_scale()`
+    fn read_raw(&mut self) -> Result<u16, Self::Error>;
+
+    /// Raw value at the end of the travel (e.g. 1023 for a 10-bit ADC)
+    fn full_scale(&self) -> u16;
+}
+
 /// Trait for interrupt configuration
 pub trait InterruptConfig {
     type Error: From<HalError>;
@@ -305,7 +335,35 @@ pub mod mock {
     //! Mock implementations for testing
     
     use super::*;
-    use core::cell::RefCell;
+    use core::cell::{Cell, RefCell};
+    
+    /// Manually advanced clock for deterministic tests
+    #[derive(Default)]
+    pub struct MockClock {
+        now_ms: Cell<u64>,
+    }
+    
+    impl MockClock {
+        pub fn new() -> Self {
+            Self::default()
+        }
+        
+        /// Set absolute time in milliseconds
+        pub fn set_millis(&self, ms: u64) {
+            self.now_ms.set(ms);
+        }
+        
+        /// Advance time by given milliseconds
+        pub fn advance_millis(&self, ms: u64) {
+            self.now_ms.set(self.now_ms.get() + ms);
+        }
+    }
+    
+    impl Clock for MockClock {
+        fn now(&self) -> Instant {
+            Instant::from_millis(self.now_ms.get() as _)
+        }
+    }
     
     #[derive(Default)]
     pub struct MockPaddle {
@@ -379,4 +437,33 @@ pub mod mock {
             Ok(*self.state.borrow())
         }
     }
-}
\ No newline at end of file
+    
+    /// Speed potentiometer with a settable reading
+    #[derive(Default)]
+    pub struct MockSpeedInput {
+        raw: Cell<u16>,
+    }
+    
+    impl MockSpeedInput {
+        pub fn new() -> Self {
+            Self::default()
+        }
+        
+        /// Set the raw reading (10-bit)
+        pub fn set_raw(&self, raw: u16) {
+            self.raw.set(raw);
+        }
+    }
+    
+    impl AnalogSpeedInput for MockSpeedInput {
+        type Error = HalError;
+        
+        fn read_raw(&mut self) -> Result<u16, Self::Error> {
+            Ok(self.raw.get())
+        }
+        
+        fn full_scale(&self) -> u16 {
+            1023
+        }
+    }
+}
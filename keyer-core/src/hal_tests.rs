@@ -11,6 +11,8 @@ use crate::controller::{PaddleInput};
 #[cfg(test)]
 use crate::fsm::KeyerFSM;
 #[cfg(test)]
+use crate::sender::SenderFeedback;
+#[cfg(test)]
 use heapless::spsc::Queue;
 // Embassy-time removed for test compatibility
 
@@ -299,6 +301,7 @@ fn test_fsm_squeeze_mode_a() {
         unit: crate::hal::Duration::from_millis(60), // 20 WPM
         debounce_ms: 5,
         queue_size: 8,
+        ..KeyerConfig::default()
     });
     
     let paddle = PaddleInput::new();
@@ -326,6 +329,7 @@ fn test_fsm_squeeze_mode_b() {
         unit: crate::hal::Duration::from_millis(60), // 20 WPM
         debounce_ms: 5,
         queue_size: 8,
+        ..KeyerConfig::default()
     });
     
     let paddle = PaddleInput::new();
@@ -366,6 +370,7 @@ fn test_fsm_squeeze_superkeyer_dah_priority() {
         unit: crate::hal::Duration::from_millis(60), // 20 WPM
         debounce_ms: 5,
         queue_size: 8,
+        ..KeyerConfig::default()
     });
     
     let paddle = PaddleInput::new();
@@ -426,6 +431,7 @@ fn test_squeeze_timing_boundaries() {
         unit: crate::hal::Duration::from_millis(60), // 20 WPM
         debounce_ms: 5,
         queue_size: 8,
+        ..KeyerConfig::default()
     });
     
     let paddle = PaddleInput::new();
@@ -463,6 +469,369 @@ fn test_squeeze_timing_boundaries() {
     }
 }
 
+#[test]
+fn test_fsm_squeeze_start_dah_first() {
+    // Mode A/B: first-pressed paddle starts the squeeze
+    for mode in [KeyerMode::ModeA, KeyerMode::ModeB] {
+        let mut fsm = KeyerFSM::new(KeyerConfig {
+            mode,
+            char_space_enabled: false,
+            ..KeyerConfig::default()
+        });
+        
+        let paddle = PaddleInput::new();
+        let mut queue = Queue::<Element, 8>::new();
+        let (mut producer, mut consumer) = queue.split();
+        
+        // Dah leads, Dit follows before the FSM sees either
+        paddle.update(PaddleSide::Dah, true, 100);
+        paddle.update(PaddleSide::Dit, true, 104);
+        
+        assert_eq!(fsm.update_at(Instant::from_millis(105), &paddle, &mut producer), 1);
+        assert_eq!(fsm.current_state(), FSMState::Squeeze(Element::Dah));
+        assert_eq!(consumer.dequeue(), Some(Element::Dah));
+    }
+}
+
+#[test]
+fn test_fsm_squeeze_tie_rules() {
+    let run = |rule: SqueezeTieRule, previous: Option<PaddleSide>| {
+        let mut fsm = KeyerFSM::new(KeyerConfig {
+            mode: KeyerMode::ModeA,
+            char_space_enabled: false,
+            squeeze_tie: rule,
+            ..KeyerConfig::default()
+        });
+        
+        let paddle = PaddleInput::new();
+        let mut queue = Queue::<Element, 8>::new();
+        let (mut producer, mut consumer) = queue.split();
+        
+        // Optionally send a single element first
+        if let Some(side) = previous {
+            paddle.update(side, true, 100);
+            fsm.update_at(Instant::from_millis(100), &paddle, &mut producer);
+            paddle.update(side, false, 200);
+            fsm.element_finished(Instant::from_millis(200));
+            fsm.update_at(Instant::from_millis(200), &paddle, &mut producer);
+            consumer.dequeue();
+        }
+        
+        // Simultaneous press
+        paddle.update(PaddleSide::Dit, true, 300);
+        paddle.update(PaddleSide::Dah, true, 300);
+        fsm.update_at(Instant::from_millis(300), &paddle, &mut producer);
+        consumer.dequeue()
+    };
+    
+    assert_eq!(run(SqueezeTieRule::Dit, None), Some(Element::Dit));
+    assert_eq!(run(SqueezeTieRule::Dah, None), Some(Element::Dah));
+    assert_eq!(run(SqueezeTieRule::Alternate, None), Some(Element::Dit));
+    assert_eq!(run(SqueezeTieRule::Alternate, Some(PaddleSide::Dit)), Some(Element::Dah));
+    assert_eq!(run(SqueezeTieRule::Alternate, Some(PaddleSide::Dah)), Some(Element::Dit));
+}
+
+#[test]
+fn test_fsm_held_paddle_does_not_run_ahead() {
+    let mut fsm = KeyerFSM::new(KeyerConfig {
+        mode: KeyerMode::ModeA,
+        char_space_enabled: false,
+        ..KeyerConfig::default()
+    });
+    
+    let paddle = PaddleInput::new();
+    let mut queue = Queue::<Element, 8>::new();
+    let (mut producer, consumer) = queue.split();
+    
+    paddle.update(PaddleSide::Dit, true, 100);
+    
+    // Many polls while the first Dit is keyed queue nothing further
+    for t in (100..160).step_by(15) {
+        fsm.update_at(Instant::from_millis(t), &paddle, &mut producer);
+    }
+    assert_eq!(consumer.len(), 1);
+    assert!(fsm.element_in_flight());
+    
+    // Key-up releases the next element
+    fsm.element_finished(Instant::from_millis(160));
+    assert_eq!(fsm.update_at(Instant::from_millis(165), &paddle, &mut producer), 1);
+    assert_eq!(consumer.len(), 2);
+}
+
+#[test]
+fn test_fsm_squeeze_release_judged_at_element_end() {
+    let run = |mode: KeyerMode| {
+        let mut fsm = KeyerFSM::new(KeyerConfig {
+            mode,
+            char_space_enabled: false,
+            ..KeyerConfig::default()
+        });
+        
+        let paddle = PaddleInput::new();
+        let feedback = SenderFeedback::new();
+        let mut queue = Queue::<Element, 8>::new();
+        let (mut producer, mut consumer) = queue.split();
+        
+        // Squeeze starts with Dit
+        paddle.update(PaddleSide::Dit, true, 100);
+        paddle.update(PaddleSide::Dah, true, 105);
+        fsm.update_at(Instant::from_millis(106), &paddle, &mut producer);
+        assert_eq!(consumer.dequeue(), Some(Element::Dit));
+        feedback.element_started();
+        
+        // Both released while the Dit is still keyed
+        paddle.update(PaddleSide::Dit, false, 130);
+        paddle.update(PaddleSide::Dah, false, 130);
+        fsm.sync_with_sender(&feedback, Instant::from_millis(130));
+        assert_eq!(fsm.update_at(Instant::from_millis(130), &paddle, &mut producer), 0);
+        
+        // Decision happens at key-up
+        feedback.element_finished();
+        fsm.sync_with_sender(&feedback, Instant::from_millis(166));
+        fsm.update_at(Instant::from_millis(166), &paddle, &mut producer);
+        consumer.dequeue()
+    };
+    
+    assert_eq!(run(KeyerMode::ModeA), None);
+    assert_eq!(run(KeyerMode::ModeB), Some(Element::Dah));
+}
+
+#[test]
+fn test_fsm_latches_tap_during_element() {
+    let mut fsm = KeyerFSM::new(KeyerConfig {
+        mode: KeyerMode::ModeA,
+        char_space_enabled: false,
+        ..KeyerConfig::default()
+    });
+    
+    let paddle = PaddleInput::new();
+    let mut queue = Queue::<Element, 8>::new();
+    let (mut producer, mut consumer) = queue.split();
+    
+    paddle.update(PaddleSide::Dit, true, 100);
+    fsm.update_at(Instant::from_millis(100), &paddle, &mut producer);
+    assert_eq!(consumer.dequeue(), Some(Element::Dit));
+    
+    // Dah tapped and released while the Dit is keyed
+    paddle.update(PaddleSide::Dah, true, 120);
+    fsm.update_at(Instant::from_millis(120), &paddle, &mut producer);
+    paddle.update(PaddleSide::Dah, false, 140);
+    fsm.update_at(Instant::from_millis(140), &paddle, &mut producer);
+    
+    // The tap is not lost at the element boundary
+    fsm.element_finished(Instant::from_millis(160));
+    assert_eq!(fsm.update_at(Instant::from_millis(160), &paddle, &mut producer), 1);
+    assert_eq!(consumer.dequeue(), Some(Element::Dah));
+}
+
+#[test]
+fn test_fsm_waits_for_aborted_playback() {
+    use crate::message::{MessagePlayer, PlaybackStatus};
+
+    let mut fsm = KeyerFSM::new(KeyerConfig {
+        char_space_enabled: false,
+        ..KeyerConfig::default()
+    });
+    let paddle = PaddleInput::new();
+    let feedback = SenderFeedback::new();
+    let mut queue = Queue::<Element, 8>::new();
+    let (mut producer, mut consumer) = queue.split();
+    let mut player = MessagePlayer::new();
+
+    player.start("TT").unwrap();
+    assert_eq!(player.update(&paddle, &mut producer), PlaybackStatus::Playing);
+    assert_eq!(consumer.dequeue(), Some(Element::Dah));
+    feedback.element_started();
+
+    // Dit pressed while the message Dah is keyed: playback stops, the FSM holds
+    paddle.update(PaddleSide::Dit, true, 100);
+    assert_eq!(player.update(&paddle, &mut producer), PlaybackStatus::Aborted);
+    fsm.wait_for_sender();
+    fsm.sync_with_sender(&feedback, Instant::from_millis(100));
+    assert_eq!(fsm.update_at(Instant::from_millis(100), &paddle, &mut producer), 0);
+    assert!(!fsm.element_in_flight());
+
+    // Queued message elements go out first, then the latched press
+    while consumer.dequeue().is_some() {}
+    assert_eq!(fsm.update_at(Instant::from_millis(120), &paddle, &mut producer), 0);
+    paddle.update(PaddleSide::Dit, false, 150);
+    feedback.element_finished();
+    fsm.sync_with_sender(&feedback, Instant::from_millis(180));
+    assert_eq!(fsm.update_at(Instant::from_millis(180), &paddle, &mut producer), 1);
+    assert_eq!(consumer.dequeue(), Some(Element::Dit));
+}
+
+#[test]
+fn test_fsm_ultimatic_last_pressed_repeats() {
+    assert!(!KeyerMode::Ultimatic.has_memory());
+    assert!(KeyerMode::Ultimatic.has_priority());
+    
+    let mut fsm = KeyerFSM::new(KeyerConfig {
+        mode: KeyerMode::Ultimatic,
+        char_space_enabled: false,
+        ..KeyerConfig::default()
+    });
+    
+    let paddle = PaddleInput::new();
+    let mut queue = Queue::<Element, 8>::new();
+    let (mut producer, mut consumer) = queue.split();
+    let mut t = 100u32;
+    let mut next = |fsm: &mut KeyerFSM, paddle: &PaddleInput| {
+        fsm.element_finished(Instant::from_millis(t as _));
+        fsm.update_at(Instant::from_millis(t as _), paddle, &mut producer);
+        t += 120;
+    };
+    
+    // Dit held alone
+    paddle.update(PaddleSide::Dit, true, 100);
+    next(&mut fsm, &paddle);
+    
+    // Dah joins: last-pressed Dah repeats for as long as the squeeze lasts
+    paddle.update(PaddleSide::Dah, true, 150);
+    next(&mut fsm, &paddle);
+    next(&mut fsm, &paddle);
+    
+    // Dah released: Dit takes over again
+    paddle.update(PaddleSide::Dah, false, 400);
+    next(&mut fsm, &paddle);
+    
+    // Both released: no memory element
+    paddle.update(PaddleSide::Dit, false, 500);
+    next(&mut fsm, &paddle);
+    
+    let mut sent = heapless::Vec::<Element, 8>::new();
+    while let Some(element) = consumer.dequeue() {
+        sent.push(element).unwrap();
+    }
+    assert_eq!(sent.as_slice(), &[Element::Dit, Element::Dah, Element::Dah, Element::Dit]);
+}
+
+#[test]
+fn test_fsm_ultimatic_squeeze_start_last_pressed() {
+    let mut fsm = KeyerFSM::new(KeyerConfig {
+        mode: KeyerMode::Ultimatic,
+        char_space_enabled: false,
+        ..KeyerConfig::default()
+    });
+    
+    let paddle = PaddleInput::new();
+    let mut queue = Queue::<Element, 8>::new();
+    let (mut producer, mut consumer) = queue.split();
+    
+    // Dit first, Dah last before the FSM sees the squeeze
+    paddle.update(PaddleSide::Dit, true, 100);
+    paddle.update(PaddleSide::Dah, true, 104);
+    fsm.update_at(Instant::from_millis(105), &paddle, &mut producer);
+    assert_eq!(consumer.dequeue(), Some(Element::Dah));
+}
+
+#[test]
+fn test_mock_clock_advances() {
+    let clock = MockClock::new();
+    assert_eq!(clock.now().as_millis(), 0);
+    
+    clock.set_millis(100);
+    assert_eq!(clock.now().as_millis(), 100);
+    
+    clock.advance_millis(60);
+    assert_eq!(clock.now().as_millis(), 160);
+}
+
+#[test]
+fn test_fsm_char_space_timing_with_clock() {
+    let mut fsm = KeyerFSM::new(KeyerConfig {
+        mode: KeyerMode::ModeA,
+        char_space_enabled: true,
+        unit: crate::hal::Duration::from_millis(60), // 20 WPM
+        debounce_ms: 5,
+        queue_size: 8,
+        ..KeyerConfig::default()
+    });
+    
+    let clock = MockClock::new();
+    let paddle = PaddleInput::new();
+    let mut queue = Queue::<Element, 8>::new();
+    let (mut producer, mut consumer) = queue.split();
+    
+    // Dit pressed and released
+    clock.set_millis(100);
+    paddle.update(PaddleSide::Dit, true, 100);
+    assert_eq!(fsm.update_at(clock.now(), &paddle, &mut producer), 1);
+    assert_eq!(consumer.dequeue(), Some(Element::Dit));
+    
+    // Released mid-element: the FSM waits for the sender's key-up
+    clock.set_millis(140);
+    paddle.update(PaddleSide::Dit, false, 140);
+    fsm.element_started(Instant::from_millis(100));
+    assert_eq!(fsm.update_at(clock.now(), &paddle, &mut producer), 0);
+    assert_eq!(fsm.current_state(), FSMState::DitHold);
+    
+    clock.set_millis(160);
+    fsm.element_finished(clock.now());
+    fsm.update_at(clock.now(), &paddle, &mut producer);
+    assert_eq!(fsm.current_state(), FSMState::CharSpacePending(Instant::from_millis(160)));
+    
+    // Press again before the character space (3 units = 180ms) has elapsed
+    clock.set_millis(200);
+    paddle.update(PaddleSide::Dah, true, 200);
+    assert_eq!(fsm.update_at(clock.now(), &paddle, &mut producer), 0);
+    assert!(matches!(fsm.current_state(), FSMState::CharSpacePending(_)));
+    
+    // Once the character space is complete the held paddle starts a new character
+    clock.set_millis(340);
+    assert_eq!(fsm.update_at(clock.now(), &paddle, &mut producer), 2);
+    assert_eq!(fsm.current_state(), FSMState::DahHold);
+    assert_eq!(consumer.dequeue(), Some(Element::CharSpace));
+    assert_eq!(consumer.dequeue(), Some(Element::Dah));
+}
+
+#[test]
+fn test_fsm_char_space_returns_to_idle_with_clock() {
+    let mut fsm = KeyerFSM::new(KeyerConfig {
+        mode: KeyerMode::ModeA,
+        char_space_enabled: true,
+        unit: crate::hal::Duration::from_millis(60), // 20 WPM
+        debounce_ms: 5,
+        queue_size: 8,
+        ..KeyerConfig::default()
+    });
+    
+    let clock = MockClock::new();
+    let paddle = PaddleInput::new();
+    let mut queue = Queue::<Element, 8>::new();
+    let (mut producer, mut consumer) = queue.split();
+    
+    clock.set_millis(100);
+    paddle.update(PaddleSide::Dah, true, 100);
+    fsm.update_at(clock.now(), &paddle, &mut producer);
+    assert_eq!(consumer.dequeue(), Some(Element::Dah));
+    
+    clock.set_millis(280);
+    paddle.update(PaddleSide::Dah, false, 280);
+    fsm.element_finished(clock.now());
+    fsm.update_at(clock.now(), &paddle, &mut producer);
+    
+    // Still inside the character space
+    clock.advance_millis(179);
+    fsm.update_at(clock.now(), &paddle, &mut producer);
+    assert!(matches!(fsm.current_state(), FSMState::CharSpacePending(_)));
+    
+    // Character space complete: marked, then timing continues towards a word space
+    clock.advance_millis(1);
+    assert_eq!(fsm.update_at(clock.now(), &paddle, &mut producer), 1);
+    assert_eq!(fsm.current_state(), FSMState::WordSpacePending(Instant::from_millis(280)));
+    assert_eq!(consumer.dequeue(), Some(Element::CharSpace));
+    
+    // Word space (7 units = 420ms from key-up) complete
+    clock.set_millis(699);
+    assert_eq!(fsm.update_at(clock.now(), &paddle, &mut producer), 0);
+    clock.set_millis(700);
+    assert_eq!(fsm.update_at(clock.now(), &paddle, &mut producer), 1);
+    assert_eq!(fsm.current_state(), FSMState::Idle);
+    assert_eq!(consumer.dequeue(), Some(Element::WordSpace));
+    assert!(consumer.dequeue().is_none());
+}
+
 #[cfg(feature = "std")]
 #[test]
 fn test_hal_error_display() {
@@ -481,4 +850,288 @@ fn test_hal_error_display() {
         // Verify Error trait is implemented
         let _: &dyn Error = &error;
     }
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_fsm_bug_mode_dits_and_manual_dah() {
+    assert!(!KeyerMode::Bug.has_memory());
+    assert!(Element::KeyDown.is_keyed());
+    assert!(!Element::KeyUp.is_keyed());
+    
+    let mut fsm = KeyerFSM::new(KeyerConfig {
+        mode: KeyerMode::Bug,
+        ..KeyerConfig::default()
+    });
+    
+    let paddle = PaddleInput::new();
+    let mut queue = Queue::<Element, 8>::new();
+    let (mut producer, mut consumer) = queue.split();
+    let mut t = 100u32;
+    let mut next = |fsm: &mut KeyerFSM, paddle: &PaddleInput| {
+        fsm.element_finished(Instant::from_millis(t as _));
+        fsm.update_at(Instant::from_millis(t as _), paddle, &mut producer);
+        t += 120;
+    };
+    
+    // Dit lever: automatic dits
+    paddle.update(PaddleSide::Dit, true, 100);
+    next(&mut fsm, &paddle);
+    next(&mut fsm, &paddle);
+    paddle.update(PaddleSide::Dit, false, 300);
+    next(&mut fsm, &paddle);
+    
+    // Dah lever: key follows the lever for as long as it is held
+    paddle.update(PaddleSide::Dah, true, 400);
+    next(&mut fsm, &paddle);
+    assert_eq!(fsm.current_state(), FSMState::KeyDown);
+    assert!(!fsm.element_in_flight());
+    next(&mut fsm, &paddle);
+    next(&mut fsm, &paddle);
+    paddle.update(PaddleSide::Dah, false, 900);
+    next(&mut fsm, &paddle);
+    assert_eq!(fsm.current_state(), FSMState::Idle);
+    
+    let mut sent = heapless::Vec::<Element, 8>::new();
+    while let Some(element) = consumer.dequeue() {
+        sent.push(element).unwrap();
+    }
+    assert_eq!(sent.as_slice(), &[Element::Dit, Element::Dit, Element::KeyDown, Element::KeyUp]);
+}
+
+#[test]
+fn test_fsm_straight_key_passthrough() {
+    let mut fsm = KeyerFSM::new(KeyerConfig {
+        mode: KeyerMode::StraightKey,
+        ..KeyerConfig::default()
+    });
+    
+    let paddle = PaddleInput::new();
+    let mut queue = Queue::<Element, 8>::new();
+    let (mut producer, mut consumer) = queue.split();
+    
+    // Mono plug: Dah contact shorted for the whole session, never keys
+    paddle.update(PaddleSide::Dah, true, 50);
+    fsm.update_at(Instant::from_millis(60), &paddle, &mut producer);
+    assert!(consumer.dequeue().is_none());
+    
+    // Long hold stays a single key-down, no repeating dits
+    paddle.update(PaddleSide::Dit, true, 100);
+    for t in (100..1000).step_by(20) {
+        fsm.update_at(Instant::from_millis(t as _), &paddle, &mut producer);
+    }
+    assert_eq!(consumer.dequeue(), Some(Element::KeyDown));
+    assert!(consumer.dequeue().is_none());
+    
+    paddle.update(PaddleSide::Dit, false, 1100);
+    fsm.update_at(Instant::from_millis(1100), &paddle, &mut producer);
+    assert_eq!(consumer.dequeue(), Some(Element::KeyUp));
+    assert_eq!(fsm.current_state(), FSMState::Idle);
+    
+    // Contact bounce inside the debounce window is ignored
+    paddle.update(PaddleSide::Dit, true, 1105);
+    fsm.update_at(Instant::from_millis(1105), &paddle, &mut producer);
+    assert!(consumer.dequeue().is_none());
+}
+
+#[test]
+fn test_fsm_mode_switch_releases_manual_key() {
+    let mut fsm = KeyerFSM::new(KeyerConfig {
+        mode: KeyerMode::StraightKey,
+        ..KeyerConfig::default()
+    });
+    
+    let paddle = PaddleInput::new();
+    let mut queue = Queue::<Element, 8>::new();
+    let (mut producer, mut consumer) = queue.split();
+    
+    paddle.update(PaddleSide::Dit, true, 100);
+    fsm.update_at(Instant::from_millis(100), &paddle, &mut producer);
+    assert_eq!(consumer.dequeue(), Some(Element::KeyDown));
+    
+    // Switching to an iambic mode never leaves the key stuck down
+    fsm.set_config(KeyerConfig::default());
+    fsm.update_at(Instant::from_millis(120), &paddle, &mut producer);
+    assert_eq!(consumer.dequeue(), Some(Element::KeyUp));
+    assert_eq!(fsm.current_state(), FSMState::Idle);
+}
+
+#[test]
+fn test_element_timing_ratio_and_weighting() {
+    let config = KeyerConfig::default(); // 60ms unit
+    let dit = config.element_timing(Element::Dit);
+    let dah = config.element_timing(Element::Dah);
+    assert_eq!((dit.key_down.as_millis(), dit.space.as_millis()), (60, 60));
+    assert_eq!((dah.key_down.as_millis(), dah.space.as_millis()), (180, 60));
+    assert_eq!(config.element_timing(Element::CharSpace).space.as_millis(), 180);
+    assert_eq!(config.element_timing(Element::WordSpace).space.as_millis(), 420);
+    
+    // Heavier weighting lengthens the mark at the expense of the gap
+    let heavy = config.with_timing(35, 60).unwrap();
+    let dit = heavy.element_timing(Element::Dit);
+    let dah = heavy.element_timing(Element::Dah);
+    assert_eq!((dit.key_down.as_millis(), dit.space.as_millis()), (72, 48));
+    assert_eq!((dah.key_down.as_millis(), dah.space.as_millis()), (222, 48));
+    assert_eq!(heavy.element_timing(Element::CharSpace).space.as_millis(), 180);
+    
+    // Light weighting and a short dah
+    let light = config.with_timing(25, 40).unwrap();
+    let dah = light.element_timing(Element::Dah);
+    assert_eq!((dah.key_down.as_millis(), dah.space.as_millis()), (138, 72));
+    
+    assert!(config.with_timing(24, 50).is_err());
+    assert!(config.with_timing(46, 50).is_err());
+    assert!(config.with_timing(30, 9).is_err());
+    assert!(config.with_timing(30, 91).is_err());
+}
+
+#[test]
+fn test_farnsworth_spacing() {
+    // 20 WPM characters at 10 WPM effective: ta = (60*20 - 37.2*10) / (10*20) s = 4140ms
+    let config = KeyerConfig::default().with_farnsworth(Some(10)).unwrap();
+    assert_eq!(config.char_space_duration().as_millis(), 653); // 3 * ta / 19
+    assert_eq!(config.word_space_duration().as_millis(), 1525); // 7 * ta / 19
+    assert_eq!(config.element_timing(Element::Dit).key_down.as_millis(), 60);
+    assert_eq!(config.element_timing(Element::CharSpace).space.as_millis(), 653);
+    assert_eq!(config.element_timing(Element::WordSpace).space.as_millis(), 1525);
+    
+    // Disabled or invalid settings keep standard spacing
+    let standard = KeyerConfig::default();
+    assert_eq!(standard.char_space_duration().as_millis(), 180);
+    assert_eq!(standard.word_space_duration().as_millis(), 420);
+    assert!(standard.with_farnsworth(Some(20)).is_err());
+    assert!(standard.with_farnsworth(Some(0)).is_err());
+    assert!(standard.with_farnsworth(None).is_ok());
+}
+
+#[test]
+fn test_fsm_farnsworth_stretches_char_space() {
+    let mut fsm = KeyerFSM::new(KeyerConfig::default().with_farnsworth(Some(10)).unwrap());
+    
+    let clock = MockClock::new();
+    let paddle = PaddleInput::new();
+    let mut queue = Queue::<Element, 8>::new();
+    let (mut producer, mut consumer) = queue.split();
+    
+    clock.set_millis(100);
+    paddle.update(PaddleSide::Dit, true, 100);
+    fsm.update_at(clock.now(), &paddle, &mut producer);
+    assert_eq!(consumer.dequeue(), Some(Element::Dit));
+    paddle.update(PaddleSide::Dit, false, 140);
+    
+    clock.set_millis(160);
+    fsm.element_finished(clock.now());
+    fsm.update_at(clock.now(), &paddle, &mut producer);
+    
+    // Standard character space (180ms) has passed, Farnsworth space (653ms) has not
+    clock.set_millis(400);
+    paddle.update(PaddleSide::Dah, true, 400);
+    assert_eq!(fsm.update_at(clock.now(), &paddle, &mut producer), 0);
+    assert!(matches!(fsm.current_state(), FSMState::CharSpacePending(_)));
+    
+    clock.set_millis(813);
+    assert_eq!(fsm.update_at(clock.now(), &paddle, &mut producer), 2);
+    assert_eq!(consumer.dequeue(), Some(Element::CharSpace));
+    assert_eq!(consumer.dequeue(), Some(Element::Dah));
+}
+
+#[test]
+fn test_fsm_word_space_interrupted_by_next_character() {
+    let mut fsm = KeyerFSM::new(KeyerConfig::default()); // 60ms unit
+    
+    let paddle = PaddleInput::new();
+    let mut queue = Queue::<Element, 8>::new();
+    let (mut producer, mut consumer) = queue.split();
+    
+    paddle.update(PaddleSide::Dit, true, 100);
+    fsm.update_at(Instant::from_millis(100), &paddle, &mut producer);
+    paddle.update(PaddleSide::Dit, false, 130);
+    fsm.element_finished(Instant::from_millis(160));
+    fsm.update_at(Instant::from_millis(160), &paddle, &mut producer);
+    
+    // Character space marked, word space not yet reached
+    fsm.update_at(Instant::from_millis(340), &paddle, &mut producer);
+    assert!(matches!(fsm.current_state(), FSMState::WordSpacePending(_)));
+    
+    // Next character starts without a second space marker
+    paddle.update(PaddleSide::Dah, true, 400);
+    fsm.update_at(Instant::from_millis(400), &paddle, &mut producer);
+    assert_eq!(fsm.current_state(), FSMState::DahHold);
+    
+    let mut sent = heapless::Vec::<Element, 8>::new();
+    while let Some(element) = consumer.dequeue() {
+        sent.push(element).unwrap();
+    }
+    assert_eq!(sent.as_slice(), &[Element::Dit, Element::CharSpace, Element::Dah]);
+}
+
+#[test]
+fn test_paddle_swap() {
+    let paddle = PaddleInput::new();
+    paddle.update(PaddleSide::Dit, true, 100); // physical Dit contact
+    
+    // The FSM applies the configured swap to its own view only
+    let mut fsm = KeyerFSM::new(KeyerConfig {
+        paddle_swap: true,
+        ..KeyerConfig::default()
+    });
+    let mut queue = Queue::<Element, 8>::new();
+    let (mut producer, mut consumer) = queue.split();
+    fsm.update_at(Instant::from_millis(100), &paddle, &mut producer);
+    assert_eq!(consumer.dequeue(), Some(Element::Dah));
+    assert!(paddle.dit() && !paddle.dah());
+    assert_eq!(paddle.get_press_times(), (Some(100), None));
+    assert_eq!(paddle.current_single_element(), Some(Element::Dit));
+    
+    // Press order is swapped too: physical Dah first reads as Dit first
+    let mut fsm = KeyerFSM::new(KeyerConfig {
+        mode: KeyerMode::ModeB,
+        paddle_swap: true,
+        ..KeyerConfig::default()
+    });
+    let paddle = PaddleInput::new();
+    paddle.update(PaddleSide::Dah, true, 200);
+    paddle.update(PaddleSide::Dit, true, 205);
+    let mut queue = Queue::<Element, 8>::new();
+    let (mut producer, mut consumer) = queue.split();
+    fsm.update_at(Instant::from_millis(205), &paddle, &mut producer);
+    assert_eq!(consumer.dequeue(), Some(Element::Dit));
+}
+
+#[test]
+fn test_fsm_single_lever_last_lever_wins() {
+    let mut fsm = KeyerFSM::new(KeyerConfig {
+        single_lever: true,
+        char_space_enabled: false,
+        ..KeyerConfig::default()
+    });
+    
+    let paddle = PaddleInput::new();
+    let mut queue = Queue::<Element, 8>::new();
+    let (mut producer, mut consumer) = queue.split();
+    
+    // Dit, then the lever swings over to Dah while the Dit is still being keyed
+    paddle.update(PaddleSide::Dit, true, 100);
+    fsm.update_at(Instant::from_millis(100), &paddle, &mut producer);
+    paddle.update(PaddleSide::Dit, false, 130);
+    paddle.update(PaddleSide::Dah, true, 140);
+    fsm.update_at(Instant::from_millis(140), &paddle, &mut producer);
+    
+    // Both presses were seen, but no squeeze: the Dah lever wins
+    fsm.element_finished(Instant::from_millis(160));
+    fsm.update_at(Instant::from_millis(160), &paddle, &mut producer);
+    assert_eq!(fsm.current_state(), FSMState::DahHold);
+    
+    // Lever back to Dit while the Dah is being keyed
+    paddle.update(PaddleSide::Dah, false, 300);
+    paddle.update(PaddleSide::Dit, true, 320);
+    fsm.update_at(Instant::from_millis(320), &paddle, &mut producer);
+    fsm.element_finished(Instant::from_millis(400));
+    fsm.update_at(Instant::from_millis(400), &paddle, &mut producer);
+    assert_eq!(fsm.current_state(), FSMState::DitHold);
+    
+    let mut sent = heapless::Vec::<Element, 8>::new();
+    while let Some(element) = consumer.dequeue() {
+        sent.push(element).unwrap();
+    }
+    assert_eq!(sent.as_slice(), &[Element::Dit, Element::Dah, Element::Dit]);
+}
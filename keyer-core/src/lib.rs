@@ -3,11 +3,24 @@
 //! # Keyer Core
 //! 
 //! Iambic keyer core logic library for embedded systems.
-//! Supports Mode A, Mode B, and SuperKeyer modes with high-precision timing.
+//! Supports Mode A, Mode B, SuperKeyer, Ultimatic, Bug and straight key modes with high-precision timing.
 
 pub mod types;
 pub mod fsm;
 pub mod controller;
+pub mod sender;
+pub mod shared;
+pub mod encoder;
+pub mod decoder;
+pub mod message;
+pub mod macros;
+pub mod beacon;
+pub mod command;
+pub mod storage;
+pub mod speed;
+pub mod winkeyer;
+pub mod cli;
+pub mod trace;
 pub mod hal;
 
 #[cfg(feature = "test-utils")]
@@ -19,6 +32,19 @@ mod hal_tests;
 pub use types::*;
 pub use fsm::*;
 pub use controller::*;
+pub use sender::*;
+pub use shared::*;
+pub use encoder::{EncodeError, MorseEncoder};
+pub use decoder::{AdaptiveDecoder, Decoded, MorseDecoder, SpeedTracker, TimedDecoder};
+pub use message::{MemoryError, MessageMemory, MessagePlayer, PlaybackControl, PlaybackStatus};
+pub use macros::ContestMacros;
+pub use beacon::{Beacon, BeaconConfig, BeaconPhase, BeaconSettings};
+pub use command::CommandMode;
+pub use speed::{SpeedKnob, SpeedRange};
+pub use winkeyer::WinKeyer;
+pub use cli::{CliCommand, CliError, LineReader};
+pub use trace::{TraceError, TraceEvent, TraceReader, TraceRecorder};
+pub use storage::{AutoSave, ConfigStore, RamStore, SaveRequest, Settings, SettingsLog, SimFlash, StorageError};
 pub use hal::{*, Instant, Duration};
 
 /// Keyer library version
@@ -32,5 +58,13 @@ pub fn default_config() -> KeyerConfig {
         unit: Duration::from_millis(60), // 20 WPM
         debounce_ms: 10,  // 10ms debounce for practical noise immunity
         queue_size: 64,
+        squeeze_tie: SqueezeTieRule::Dit,
+        sidetone_enabled: true,
+        dah_ratio_tenths: 30,
+        weighting: 50,
+        farnsworth_wpm: None,
+        paddle_swap: false,
+        single_lever: false,
+        tx_enabled: true,
     }
 }
\ No newline at end of file
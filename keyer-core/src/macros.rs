@@ -0,0 +1,266 @@
+//! Contest macros for message text
+//!
+//! Messages may contain tokens that are expanded right before playback:
+//!
+//! - `{NR}`: serial number, incremented after each message that sends it
+//! - `{CALL}`: own callsign
+//! - `{LAST}`: the last expanded message, sent again unchanged
+//!
+//! Serial numbers are zero-padded to [`ContestMacros::serial_width`] digits and
+//! may use cut numbers (0 → T, 9 → N).
+
+use core::fmt::Write;
+use heapless::String;
+use crate::encoder;
+use crate::message::{MemoryError, Message};
+
+/// Maximum callsign length in bytes
+pub const CALLSIGN_LEN: usize = 16;
+
+/// Macro token
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Macro {
+    /// Serial number
+    Serial,
+    /// Own callsign
+    Callsign,
+    /// Last expanded message
+    Last,
+}
+
+impl Macro {
+    /// Parse a token name (without braces), case-insensitive
+    pub fn parse(name: &str) -> Option<Self> {
+        if name.eq_ignore_ascii_case("NR") {
+            Some(Macro::Serial)
+        } else if name.eq_ignore_ascii_case("CALL") {
+            Some(Macro::Callsign)
+        } else if name.eq_ignore_ascii_case("LAST") {
+            Some(Macro::Last)
+        } else {
+            None
+        }
+    }
+}
+
+/// Piece of message text: literal text or a macro token
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Segment<'a> {
+    Text(&'a str),
+    Token(Macro),
+}
+
+/// Split text into literal and token segments
+fn segments(text: &str) -> impl Iterator<Item = Result<Segment<'_>, MemoryError>> {
+    let mut rest = text;
+    core::iter::from_fn(move || {
+        if rest.is_empty() {
+            return None;
+        }
+        let Some(body) = rest.strip_prefix('{') else {
+            let end = rest.find('{').unwrap_or(rest.len());
+            let (text, tail) = rest.split_at(end);
+            rest = tail;
+            return Some(Ok(Segment::Text(text)));
+        };
+        let Some(end) = body.find('}') else {
+            rest = "";
+            return Some(Err(MemoryError::UnterminatedMacro));
+        };
+        let name = &body[..end];
+        rest = &body[end + 1..];
+        Some(Macro::parse(name).map(Segment::Token).ok_or(MemoryError::UnknownMacro))
+    })
+}
+
+/// Check message text that may contain macros
+/// Literal text must be encodable and every token known
+pub fn validate_template(text: &str) -> Result<(), MemoryError> {
+    for segment in segments(text) {
+        if let Segment::Text(text) = segment? {
+            encoder::validate(text)?;
+        }
+    }
+    Ok(())
+}
+
+/// Macro state for a contest: serial number, callsign and last message
+#[derive(Clone, Debug)]
+pub struct ContestMacros {
+    serial: u16,
+    callsign: String<CALLSIGN_LEN>,
+    last: Message,
+    /// Minimum number of serial digits (zero-padded)
+    pub serial_width: u8,
+    /// Send 0 as T and 9 as N in serial numbers
+    pub cut_numbers: bool,
+}
+
+impl ContestMacros {
+    /// Create macros starting at serial number 1
+    pub const fn new() -> Self {
+        Self {
+            serial: 1,
+            callsign: String::new(),
+            last: Message::new(),
+            serial_width: 3,
+            cut_numbers: true,
+        }
+    }
+
+    /// Set own callsign
+    pub fn set_callsign(&mut self, callsign: &str) -> Result<(), MemoryError> {
+        encoder::validate(callsign)?;
+        let mut value = String::new();
+        value.push_str(callsign).map_err(|_| MemoryError::TooLong)?;
+        self.callsign = value;
+        Ok(())
+    }
+
+    /// Own callsign (empty if not set)
+    pub fn callsign(&self) -> &str {
+        &self.callsign
+    }
+
+    /// Serial number sent by the next `{NR}`
+    pub fn serial(&self) -> u16 {
+        self.serial
+    }
+
+    /// Set the next serial number (at least 1)
+    pub fn set_serial(&mut self, serial: u16) {
+        self.serial = serial.max(1);
+    }
+
+    /// Advance the serial number
+    pub fn increment(&mut self) {
+        self.serial = self.serial.saturating_add(1);
+    }
+
+    /// Step the serial number back, e.g. after a QSO that did not count
+    pub fn decrement(&mut self) {
+        self.set_serial(self.serial - 1);
+    }
+
+    /// Last expanded message (empty if nothing was sent yet)
+    pub fn last(&self) -> &str {
+        &self.last
+    }
+
+    /// Expand macros into sendable text
+    ///
+    /// A message containing `{NR}` advances the serial number. Messages that do
+    /// not repeat `{LAST}` become the new last message.
+    pub fn expand(&mut self, template: &str) -> Result<Message, MemoryError> {
+        let mut out = Message::new();
+        let mut sends_serial = false;
+        let mut repeats_last = false;
+
+        for segment in segments(template) {
+            match segment? {
+                Segment::Text(text) => out.push_str(text).map_err(|_| MemoryError::TooLong)?,
+                Segment::Token(Macro::Serial) => {
+                    sends_serial = true;
+                    self.push_serial(&mut out)?;
+                }
+                Segment::Token(Macro::Callsign) => {
+                    if self.callsign.is_empty() {
+                        return Err(MemoryError::NoCallsign);
+                    }
+                    out.push_str(&self.callsign).map_err(|_| MemoryError::TooLong)?;
+                }
+                Segment::Token(Macro::Last) => {
+                    repeats_last = true;
+                    out.push_str(&self.last).map_err(|_| MemoryError::TooLong)?;
+                }
+            }
+        }
+        encoder::validate(&out)?;
+
+        if sends_serial {
+            self.increment();
+        }
+        if !repeats_last {
+            self.last = out.clone();
+        }
+        Ok(out)
+    }
+
+    fn push_serial(&self, out: &mut Message) -> Result<(), MemoryError> {
+        let mut digits = String::<8>::new();
+        // Cannot fail: at most five digits fit
+        write!(digits, "{:0width$}", self.serial, width = usize::from(self.serial_width).min(5)).ok();
+        for digit in digits.chars() {
+            let c = match digit {
+                '0' if self.cut_numbers => 'T',
+                '9' if self.cut_numbers => 'N',
+                _ => digit,
+            };
+            out.push(c).map_err(|_| MemoryError::TooLong)?;
+        }
+        Ok(())
+    }
+}
+
+impl Default for ContestMacros {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serial_and_cut_numbers() {
+        let mut macros = ContestMacros::new();
+        macros.set_serial(9);
+        assert_eq!(macros.expand("5NN {NR}").unwrap(), "5NN TTN");
+        assert_eq!(macros.serial(), 10);
+        assert_eq!(macros.expand("{nr}").unwrap(), "T1T");
+
+        macros.cut_numbers = false;
+        macros.serial_width = 1;
+        assert_eq!(macros.expand("{NR}").unwrap(), "11");
+
+        // Counter API
+        macros.decrement();
+        macros.decrement();
+        assert_eq!(macros.serial(), 10);
+        macros.increment();
+        assert_eq!(macros.serial(), 11);
+        macros.set_serial(0);
+        macros.decrement();
+        assert_eq!(macros.serial(), 1);
+    }
+
+    #[test]
+    fn test_callsign_and_last() {
+        let mut macros = ContestMacros::new();
+        assert_eq!(macros.expand("DE {CALL}"), Err(MemoryError::NoCallsign));
+        macros.set_callsign("JA1ABC").unwrap();
+        assert_eq!(macros.expand("CQ {CALL} {CALL}").unwrap(), "CQ JA1ABC JA1ABC");
+
+        // Repeating the exchange neither changes the number nor the last message
+        assert_eq!(macros.expand("TU 5NN {NR}").unwrap(), "TU 5NN TT1");
+        assert_eq!(macros.expand("{LAST}").unwrap(), "TU 5NN TT1");
+        assert_eq!(macros.expand("AGN {LAST}").unwrap(), "AGN TU 5NN TT1");
+        assert_eq!(macros.last(), "TU 5NN TT1");
+        assert_eq!(macros.serial(), 2);
+    }
+
+    #[test]
+    fn test_template_errors() {
+        assert_eq!(validate_template("CQ {CALL} {NR} {LAST}"), Ok(()));
+        assert_eq!(validate_template("{FOO}"), Err(MemoryError::UnknownMacro));
+        assert_eq!(validate_template("5NN {NR"), Err(MemoryError::UnterminatedMacro));
+        assert!(validate_template("TU #").is_err());
+
+        let mut macros = ContestMacros::new();
+        assert_eq!(macros.set_callsign("JA1ABC/1234567890"), Err(MemoryError::TooLong));
+        // A failed expansion leaves the counter alone
+        assert_eq!(macros.expand("{NR} {FOO}"), Err(MemoryError::UnknownMacro));
+        assert_eq!(macros.serial(), 1);
+    }
+}
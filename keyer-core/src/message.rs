@@ -0,0 +1,381 @@
+//! Message memories played back through the keyer queue
+//!
+//! [`MessageMemory`] holds a fixed number of text slots. A message is encoded
+//! element by element while it plays, so a queue of a few elements is enough.
+//! Any paddle press aborts playback and hands the paddles back to the FSM.
+//! Stored messages may contain contest macros (see [`crate::macros`]), which are
+//! expanded when playback starts.
+
+use core::cell::RefCell;
+use critical_section::Mutex;
+use heapless::spsc::Producer;
+use heapless::String;
+use portable_atomic::{AtomicBool, Ordering};
+use crate::controller::PaddleInput;
+use crate::encoder::{self, EncodeError, EncoderState};
+use crate::macros::{self, ContestMacros};
+use crate::types::Element;
+
+/// Maximum length of a stored message in bytes
+pub const MESSAGE_LEN: usize = 64;
+
+/// Elements kept queued ahead of the sender during playback
+/// Bounds how much of a message is still sent after an abort
+pub const PLAYBACK_LOOKAHEAD: usize = 2;
+
+/// Message text as stored in a slot
+pub type Message = String<MESSAGE_LEN>;
+
+/// Message memory errors
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MemoryError {
+    /// Slot index out of range
+    InvalidSlot,
+    /// Slot is empty
+    EmptySlot,
+    /// Text longer than [`MESSAGE_LEN`]
+    TooLong,
+    /// Text cannot be sent in Morse
+    Encode(EncodeError),
+    /// Unknown `{...}` macro
+    UnknownMacro,
+    /// `{` without closing `}`
+    UnterminatedMacro,
+    /// `{CALL}` used before a callsign was set
+    NoCallsign,
+}
+
+impl From<EncodeError> for MemoryError {
+    fn from(error: EncodeError) -> Self {
+        MemoryError::Encode(error)
+    }
+}
+
+#[cfg(feature = "std")]
+impl core::fmt::Display for MemoryError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            MemoryError::InvalidSlot => write!(f, "Invalid memory slot"),
+            MemoryError::EmptySlot => write!(f, "Memory slot is empty"),
+            MemoryError::TooLong => write!(f, "Message longer than {} characters", MESSAGE_LEN),
+            MemoryError::Encode(error) => write!(f, "{}", error),
+            MemoryError::UnknownMacro => write!(f, "Unknown macro"),
+            MemoryError::UnterminatedMacro => write!(f, "Unterminated macro"),
+            MemoryError::NoCallsign => write!(f, "Callsign not set"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for MemoryError {}
+
+/// Validate text and copy it into a message buffer
+pub(crate) fn to_message(text: &str) -> Result<Message, MemoryError> {
+    encoder::validate(text)?;
+    let mut message = Message::new();
+    message.push_str(text).map_err(|_| MemoryError::TooLong)?;
+    Ok(message)
+}
+
+/// Expand the macros of a stored message
+pub(crate) fn expand_slot<const SLOTS: usize>(
+    memory: &MessageMemory<SLOTS>,
+    slot: usize,
+    macros: &mut ContestMacros,
+) -> Result<Message, MemoryError> {
+    if slot >= SLOTS {
+        return Err(MemoryError::InvalidSlot);
+    }
+    macros.expand(memory.get(slot).ok_or(MemoryError::EmptySlot)?)
+}
+
+/// Fixed set of message slots
+#[derive(Clone, Debug)]
+pub struct MessageMemory<const SLOTS: usize> {
+    slots: [Message; SLOTS],
+}
+
+impl<const SLOTS: usize> MessageMemory<SLOTS> {
+    /// Create memory with all slots empty
+    pub const fn new() -> Self {
+        const EMPTY: Message = Message::new();
+        Self { slots: [EMPTY; SLOTS] }
+    }
+
+    /// Store a message, replacing the slot contents
+    /// Text outside macros must be encodable (see [`MorseEncoder`](crate::encoder::MorseEncoder))
+    pub fn set(&mut self, slot: usize, text: &str) -> Result<(), MemoryError> {
+        let entry = self.slots.get_mut(slot).ok_or(MemoryError::InvalidSlot)?;
+        macros::validate_template(text)?;
+        let mut message = Message::new();
+        message.push_str(text).map_err(|_| MemoryError::TooLong)?;
+        *entry = message;
+        Ok(())
+    }
+
+    /// Get a stored message (`None` if the slot is out of range or empty)
+    pub fn get(&self, slot: usize) -> Option<&str> {
+        self.slots
+            .get(slot)
+            .map(|message| message.as_str())
+            .filter(|text| !text.is_empty())
+    }
+
+    /// Empty a slot
+    pub fn clear(&mut self, slot: usize) -> Result<(), MemoryError> {
+        self.slots.get_mut(slot).ok_or(MemoryError::InvalidSlot)?.clear();
+        Ok(())
+    }
+
+    /// Number of slots
+    pub const fn slots(&self) -> usize {
+        SLOTS
+    }
+}
+
+impl<const SLOTS: usize> Default for MessageMemory<SLOTS> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Result of a playback step
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PlaybackStatus {
+    /// Nothing is playing
+    Idle,
+    /// Message elements are still being queued
+    Playing,
+    /// Last element of the message was queued
+    Finished,
+    /// Stopped by a paddle press or an abort request
+    Aborted,
+}
+
+/// Plays one message into the keyer queue
+#[derive(Clone, Debug, Default)]
+pub struct MessagePlayer {
+    text: Message,
+    state: EncoderState,
+    playing: bool,
+    /// Paddle state seen on the previous update, `None` before the first one
+    paddles: Option<(bool, bool)>,
+}
+
+impl MessagePlayer {
+    /// Create idle player
+    pub const fn new() -> Self {
+        Self {
+            text: Message::new(),
+            state: EncoderState::new(),
+            playing: false,
+            paddles: None,
+        }
+    }
+
+    /// Start playing text, replacing any message in progress
+    pub fn start(&mut self, text: &str) -> Result<(), MemoryError> {
+        self.text = to_message(text)?;
+        self.state = EncoderState::new();
+        self.playing = true;
+        self.paddles = None;
+        Ok(())
+    }
+
+    /// Start playing a memory slot, expanding its macros
+    pub fn play_slot<const SLOTS: usize>(
+        &mut self,
+        memory: &MessageMemory<SLOTS>,
+        slot: usize,
+        macros: &mut ContestMacros,
+    ) -> Result<(), MemoryError> {
+        self.start(&expand_slot(memory, slot, macros)?)
+    }
+
+    /// Stop playback (elements already queued are still sent)
+    pub fn abort(&mut self) {
+        self.playing = false;
+    }
+
+    /// Returns true while a message is playing
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    /// Queue the next elements of the message
+    ///
+    /// Keeps at most [`PLAYBACK_LOOKAHEAD`] elements queued. A paddle press
+    /// aborts playback so the caller can hand the press to the FSM; a contact
+    /// already closed when playback started (the ring of a mono plug) does not.
+    pub fn update<const N: usize>(&mut self, paddle: &PaddleInput, queue: &mut Producer<'_, Element, N>) -> PlaybackStatus {
+        if !self.playing {
+            return PlaybackStatus::Idle;
+        }
+        let (dit, dah) = (paddle.dit(), paddle.dah());
+        let (dit_before, dah_before) = self.paddles.replace((dit, dah)).unwrap_or((dit, dah));
+        if (dit && !dit_before) || (dah && !dah_before) {
+            self.playing = false;
+            return PlaybackStatus::Aborted;
+        }
+
+        // Text was validated when playback started, so encoding cannot fail
+        let result = self.state.fill(&self.text, queue, PLAYBACK_LOOKAHEAD);
+        if result.is_err() || self.state.is_finished(&self.text) {
+            self.playing = false;
+            return PlaybackStatus::Finished;
+        }
+        PlaybackStatus::Playing
+    }
+}
+
+/// Playback requests from other tasks (buttons, host control)
+/// Safe for use across tasks and interrupt contexts
+pub struct PlaybackControl {
+    request: Mutex<RefCell<Option<Message>>>,
+    abort: AtomicBool,
+}
+
+impl PlaybackControl {
+    /// Create control with no pending request
+    pub const fn new() -> Self {
+        Self {
+            request: Mutex::new(RefCell::new(None)),
+            abort: AtomicBool::new(false),
+        }
+    }
+
+    /// Request playback of text
+    pub fn play(&self, text: &str) -> Result<(), MemoryError> {
+        let message = to_message(text)?;
+        critical_section::with(|cs| self.request.borrow(cs).replace(Some(message)));
+        Ok(())
+    }
+
+    /// Request playback of a memory slot, expanding its macros
+    pub fn play_slot<const SLOTS: usize>(
+        &self,
+        memory: &MessageMemory<SLOTS>,
+        slot: usize,
+        macros: &mut ContestMacros,
+    ) -> Result<(), MemoryError> {
+        self.play(&expand_slot(memory, slot, macros)?)
+    }
+
+    /// Request that the current message stops
+    pub fn abort(&self) {
+        critical_section::with(|cs| self.request.borrow(cs).replace(None));
+        self.abort.store(true, Ordering::Release);
+    }
+
+    /// Apply pending requests to a player
+    pub fn apply(&self, player: &mut MessagePlayer) {
+        if self.abort.swap(false, Ordering::AcqRel) {
+            player.abort();
+        }
+        if let Some(message) = critical_section::with(|cs| self.request.borrow(cs).take()) {
+            // Already validated by `play`
+            player.start(&message).ok();
+        }
+    }
+}
+
+impl Default for PlaybackControl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::PaddleSide;
+    use heapless::spsc::Queue;
+    use heapless::Vec;
+
+    #[test]
+    fn test_memory_slots() {
+        let mut memory = MessageMemory::<3>::new();
+        assert_eq!(memory.set(0, "CQ TEST JA1ABC"), Ok(()));
+        assert_eq!(memory.get(0), Some("CQ TEST JA1ABC"));
+        assert_eq!(memory.get(1), None);
+
+        assert_eq!(memory.set(3, "TU"), Err(MemoryError::InvalidSlot));
+        assert_eq!(memory.set(1, "5NN#"), Err(MemoryError::Encode(EncodeError::UnsupportedChar('#'))));
+        let long = [b'E'; MESSAGE_LEN + 1];
+        assert_eq!(memory.set(1, core::str::from_utf8(&long).unwrap()), Err(MemoryError::TooLong));
+
+        assert_eq!(memory.set(1, "5NN {NR"), Err(MemoryError::UnterminatedMacro));
+
+        memory.clear(0).unwrap();
+        assert_eq!(memory.get(0), None);
+    }
+
+    #[test]
+    fn test_playback_through_queue() {
+        let mut memory = MessageMemory::<2>::new();
+        memory.set(1, "TU {NR} <SK>").unwrap();
+        let mut macros = ContestMacros::new();
+        let paddle = PaddleInput::new();
+        let mut queue = Queue::<Element, 8>::new();
+        let (mut producer, mut consumer) = queue.split();
+
+        let mut player = MessagePlayer::new();
+        assert_eq!(player.play_slot(&memory, 0, &mut macros), Err(MemoryError::EmptySlot));
+        player.play_slot(&memory, 1, &mut macros).unwrap();
+
+        let mut sent = Vec::<Element, 64>::new();
+        loop {
+            let status = player.update(&paddle, &mut producer);
+            assert!(producer.len() <= PLAYBACK_LOOKAHEAD);
+            // Sender takes one element per step
+            if let Some(element) = consumer.dequeue() {
+                sent.push(element).unwrap();
+            }
+            if status == PlaybackStatus::Finished {
+                break;
+            }
+        }
+        while let Some(element) = consumer.dequeue() {
+            sent.push(element).unwrap();
+        }
+        assert_eq!(sent, encoder::encode::<64>("TU TT1 <SK>").unwrap());
+        assert_eq!(player.update(&paddle, &mut producer), PlaybackStatus::Idle);
+    }
+
+    #[test]
+    fn test_paddle_press_aborts_playback() {
+        let paddle = PaddleInput::new();
+        let control = PlaybackControl::new();
+        let mut queue = Queue::<Element, 8>::new();
+        let (mut producer, mut consumer) = queue.split();
+        let mut player = MessagePlayer::new();
+
+        // A contact closed from the start (mono plug ring) does not abort
+        paddle.update(PaddleSide::Dah, true, 0);
+        control.play("CQ CQ").unwrap();
+        control.apply(&mut player);
+        assert_eq!(player.update(&paddle, &mut producer), PlaybackStatus::Playing);
+        assert_eq!(producer.len(), PLAYBACK_LOOKAHEAD);
+        paddle.update(PaddleSide::Dah, false, 50);
+        assert_eq!(player.update(&paddle, &mut producer), PlaybackStatus::Playing);
+
+        paddle.update(PaddleSide::Dah, true, 100);
+        assert_eq!(player.update(&paddle, &mut producer), PlaybackStatus::Aborted);
+        assert!(!player.is_playing());
+
+        // Nothing beyond the lookahead is sent
+        while consumer.dequeue().is_some() {}
+        assert_eq!(player.update(&paddle, &mut producer), PlaybackStatus::Idle);
+        assert_eq!(producer.len(), 0);
+
+        // Abort requests from other tasks
+        paddle.update(PaddleSide::Dah, false, 200);
+        control.play("CQ").unwrap();
+        control.apply(&mut player);
+        control.abort();
+        control.apply(&mut player);
+        assert!(!player.is_playing());
+        assert_eq!(control.play("<AR"), Err(MemoryError::Encode(EncodeError::InvalidProsign)));
+    }
+}
@@ -0,0 +1,48 @@
+//! Sender feedback for element-synchronous evaluation
+//!
+//! The evaluator only queues the next element once the sender has finished
+//! keying the previous one. Senders report progress through [`SenderFeedback`],
+//! which the evaluator hands to [`KeyerFSM::sync_with_sender`](crate::fsm::KeyerFSM::sync_with_sender).
+
+use portable_atomic::{AtomicBool, Ordering};
+
+/// Element progress reported by the sender
+/// Safe for use across tasks and interrupt contexts
+pub struct SenderFeedback {
+    started: AtomicBool,
+    finished: AtomicBool,
+}
+
+impl SenderFeedback {
+    /// Create new sender feedback channel
+    pub const fn new() -> Self {
+        Self {
+            started: AtomicBool::new(false),
+            finished: AtomicBool::new(false),
+        }
+    }
+
+    /// Report key-down of a keyed element
+    pub fn element_started(&self) {
+        self.started.store(true, Ordering::Release);
+    }
+
+    /// Report key-up of a keyed element
+    pub fn element_finished(&self) {
+        self.finished.store(true, Ordering::Release);
+    }
+
+    /// Take pending events as `(started, finished)`
+    pub fn take_events(&self) -> (bool, bool) {
+        (
+            self.started.swap(false, Ordering::AcqRel),
+            self.finished.swap(false, Ordering::AcqRel),
+        )
+    }
+}
+
+impl Default for SenderFeedback {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,125 @@
+//! Runtime configuration shared between tasks
+//!
+//! Whatever changes settings at runtime (mode switch, command mode, host control)
+//! publishes a new [`KeyerConfig`] through [`SharedConfig`]. The evaluator picks it
+//! up at the next element boundary; senders read it once per element.
+
+use core::cell::Cell;
+use critical_section::Mutex;
+use crate::types::{KeyerConfig, KeyerMode};
+
+/// Keyer configuration that can be replaced at runtime
+/// Safe for use across tasks and interrupt contexts
+pub struct SharedConfig {
+    /// Change counter, current configuration and whether it is temporary
+    inner: Mutex<Cell<(u32, Option<KeyerConfig>, bool)>>,
+}
+
+impl SharedConfig {
+    /// Create empty shared configuration (set it before starting the keyer)
+    pub const fn new() -> Self {
+        Self {
+            inner: Mutex::new(Cell::new((0, None, false))),
+        }
+    }
+
+    /// Publish a new configuration
+    pub fn set(&self, config: KeyerConfig) {
+        self.publish(config, false);
+    }
+
+    /// Publish a configuration that must not be persisted (e.g. command mode)
+    /// The next [`set`](Self::set) or [`update`](Self::update) makes it permanent again
+    pub fn set_temporary(&self, config: KeyerConfig) {
+        self.publish(config, true);
+    }
+
+    fn publish(&self, config: KeyerConfig, temporary: bool) {
+        critical_section::with(|cs| {
+            let cell = self.inner.borrow(cs);
+            let (generation, _, _) = cell.get();
+            cell.set((generation.wrapping_add(1), Some(config), temporary));
+        });
+    }
+
+    /// Whether the current configuration is temporary
+    pub fn is_temporary(&self) -> bool {
+        critical_section::with(|cs| self.inner.borrow(cs).get().2)
+    }
+
+    /// Get the current configuration, if one has been set
+    pub fn get(&self) -> Option<KeyerConfig> {
+        critical_section::with(|cs| self.inner.borrow(cs).get().1)
+    }
+
+    /// Modify the current configuration in place
+    /// Does nothing if no configuration has been set yet
+    pub fn update(&self, f: impl FnOnce(&mut KeyerConfig)) {
+        critical_section::with(|cs| {
+            let cell = self.inner.borrow(cs);
+            if let (generation, Some(mut config), _) = cell.get() {
+                f(&mut config);
+                cell.set((generation.wrapping_add(1), Some(config), false));
+            }
+        });
+    }
+
+    /// Switch the operating mode
+    pub fn set_mode(&self, mode: KeyerMode) {
+        self.update(|config| config.mode = mode);
+    }
+
+    /// Number of changes published so far
+    pub fn generation(&self) -> u32 {
+        critical_section::with(|cs| self.inner.borrow(cs).get().0)
+    }
+
+    /// Return the configuration if it changed since `seen`, updating `seen`
+    pub fn changed_since(&self, seen: &mut u32) -> Option<KeyerConfig> {
+        let (generation, config, _) = critical_section::with(|cs| self.inner.borrow(cs).get());
+        if generation == *seen {
+            return None;
+        }
+        *seen = generation;
+        config
+    }
+}
+
+impl Default for SharedConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shared_config_change_tracking() {
+        let shared = SharedConfig::new();
+        let mut seen = shared.generation();
+        assert!(shared.get().is_none());
+        assert!(shared.changed_since(&mut seen).is_none());
+
+        // Updates before the first set are ignored
+        shared.set_mode(KeyerMode::ModeB);
+        assert!(shared.changed_since(&mut seen).is_none());
+
+        shared.set(KeyerConfig::default());
+        assert_eq!(shared.changed_since(&mut seen).map(|c| c.mode), Some(KeyerMode::ModeA));
+        assert!(shared.changed_since(&mut seen).is_none());
+
+        shared.set_mode(KeyerMode::StraightKey);
+        assert_eq!(shared.changed_since(&mut seen).map(|c| c.mode), Some(KeyerMode::StraightKey));
+        assert_eq!(shared.get().map(|c| c.mode), Some(KeyerMode::StraightKey));
+
+        // Temporary settings are tracked like any other change
+        assert!(!shared.is_temporary());
+        shared.set_temporary(KeyerConfig::default());
+        assert!(shared.is_temporary());
+        assert!(shared.changed_since(&mut seen).is_some());
+        shared.set_mode(KeyerMode::ModeB);
+        assert!(!shared.is_temporary());
+    }
+}
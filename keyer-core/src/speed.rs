@@ -0,0 +1,231 @@
+//! Speed potentiometer handling
+//!
+//! A raw ADC reading is mapped linearly onto a WPM range. [`SpeedKnob`] smooths
+//! the readings and only moves to a new WPM once the knob is clearly past the
+//! middle between two steps, so ADC noise cannot make the speed flicker. New
+//! speeds go out through [`SharedConfig`] and the keyer picks them up at the
+//! next element boundary.
+
+use crate::hal::{AnalogSpeedInput, Duration};
+use crate::shared::SharedConfig;
+
+/// Fixed-point scale for fractional WPM
+const WPM_SCALE: u32 = 256;
+/// Extra travel (1/256 WPM) needed beyond the midpoint before the speed changes
+const HYSTERESIS: u32 = 64;
+/// Smoothing: each reading moves the filter 1/2^SHIFT of the way
+const SMOOTHING_SHIFT: u32 = 3;
+
+/// Speed range covered by the knob travel
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SpeedRange {
+    /// Speed at raw value 0
+    pub min_wpm: u32,
+    /// Speed at full scale
+    pub max_wpm: u32,
+}
+
+impl Default for SpeedRange {
+    fn default() -> Self {
+        Self { min_wpm: 10, max_wpm: 40 }
+    }
+}
+
+impl SpeedRange {
+    /// Check the range the keyer can run with
+    pub fn validate(self) -> Result<Self, &'static str> {
+        if self.min_wpm == 0 || self.max_wpm > 100 || self.min_wpm >= self.max_wpm {
+            return Err("Speed range must be ascending within 1..=100 WPM");
+        }
+        Ok(self)
+    }
+
+    /// Knob position in 1/256 WPM
+    ///
+    /// An unvalidated, inverted range collapses to `min_wpm`.
+    fn position(self, raw: u32, full_scale: u32) -> u32 {
+        let raw = raw.min(full_scale);
+        let span = self.max_wpm.saturating_sub(self.min_wpm) * WPM_SCALE;
+        self.min_wpm * WPM_SCALE + (u64::from(span) * u64::from(raw) / u64::from(full_scale)) as u32
+    }
+}
+
+/// Map a raw reading onto the range, in whole WPM (no smoothing)
+pub fn raw_to_wpm(raw: u16, full_scale: u16, range: SpeedRange) -> u32 {
+    (range.position(u32::from(raw), u32::from(full_scale.max(1))) + WPM_SCALE / 2) / WPM_SCALE
+}
+
+/// Map a raw reading onto the range as a unit duration (PARIS: 1200 ms / WPM)
+pub fn raw_to_unit(raw: u16, full_scale: u16, range: SpeedRange) -> Duration {
+    Duration::from_millis(1200 / u64::from(raw_to_wpm(raw, full_scale, range).max(1)))
+}
+
+/// Smoothed speed knob with hysteresis
+#[derive(Copy, Clone, Debug)]
+pub struct SpeedKnob {
+    range: SpeedRange,
+    full_scale: u16,
+    /// Filtered reading, raw × 16
+    filtered: Option<u32>,
+    wpm: Option<u32>,
+}
+
+impl SpeedKnob {
+    /// Create knob for an input with the given full-scale raw value
+    pub fn new(range: SpeedRange, full_scale: u16) -> Result<Self, &'static str> {
+        if full_scale == 0 {
+            return Err("Full scale must be above zero");
+        }
+        Ok(Self {
+            range: range.validate()?,
+            full_scale,
+            filtered: None,
+            wpm: None,
+        })
+    }
+
+    /// Speed range
+    pub fn range(&self) -> SpeedRange {
+        self.range
+    }
+
+    /// Current speed (None before the first reading)
+    pub fn wpm(&self) -> Option<u32> {
+        self.wpm
+    }
+
+    /// Current unit duration (None before the first reading)
+    pub fn unit(&self) -> Option<Duration> {
+        self.wpm.map(|wpm| Duration::from_millis(1200 / u64::from(wpm)))
+    }
+
+    /// Feed a reading; returns the new WPM if the speed changed
+    pub fn update(&mut self, raw: u16) -> Option<u32> {
+        let wpm = self.filter(raw);
+        if self.wpm == Some(wpm) {
+            return None;
+        }
+        self.wpm = Some(wpm);
+        Some(wpm)
+    }
+
+    /// Feed a reading and publish a changed speed
+    ///
+    /// While the configuration is temporary (command mode) the change is held
+    /// back and published after it ends.
+    pub fn apply(&mut self, raw: u16, config: &SharedConfig) -> Option<u32> {
+        if config.is_temporary() {
+            self.filter(raw);
+            return None;
+        }
+        let wpm = self.update(raw)?;
+        config.update(|c| {
+            // A Farnsworth speed at or above the new speed is dropped
+            let changed = c.with_wpm(wpm).or_else(|_| c.with_farnsworth(None).and_then(|c| c.with_wpm(wpm)));
+            if let Ok(changed) = changed {
+                *c = changed;
+            }
+        });
+        Some(wpm)
+    }
+
+    /// Read the input and publish a changed speed
+    pub fn poll<A: AnalogSpeedInput>(&mut self, input: &mut A, config: &SharedConfig) -> Result<Option<u32>, A::Error> {
+        let raw = input.read_raw()?;
+        Ok(self.apply(raw, config))
+    }
+
+    /// Smooth the reading and return the WPM it selects
+    fn filter(&mut self, raw: u16) -> u32 {
+        let sample = u32::from(raw.min(self.full_scale)) << 4;
+        let filtered = match self.filtered {
+            Some(previous) => {
+                let delta = (sample as i32 - previous as i32) >> SMOOTHING_SHIFT;
+                (previous as i32 + delta) as u32
+            }
+            None => sample,
+        };
+        self.filtered = Some(filtered);
+
+        let position = self.range.position(filtered, u32::from(self.full_scale) << 4);
+        if let Some(wpm) = self.wpm {
+            // Stay until the knob is clearly in the next step
+            let centre = wpm * WPM_SCALE;
+            let reach = WPM_SCALE / 2 + HYSTERESIS;
+            if position + reach >= centre && position <= centre + reach {
+                return wpm;
+            }
+        }
+        ((position + WPM_SCALE / 2) / WPM_SCALE).clamp(self.range.min_wpm, self.range.max_wpm)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::KeyerConfig;
+
+    #[test]
+    fn test_mapping() {
+        let range = SpeedRange { min_wpm: 10, max_wpm: 40 };
+        assert_eq!(raw_to_wpm(0, 1023, range), 10);
+        assert_eq!(raw_to_wpm(1023, 1023, range), 40);
+        assert_eq!(raw_to_wpm(512, 1023, range), 25);
+        assert_eq!(raw_to_wpm(4000, 1023, range), 40);
+        assert_eq!(raw_to_unit(1023, 1023, range), Duration::from_millis(30));
+
+        assert!(SpeedKnob::new(SpeedRange { min_wpm: 30, max_wpm: 20 }, 1023).is_err());
+        assert!(SpeedKnob::new(range, 0).is_err());
+
+        // The free functions skip validation: an inverted range must not underflow
+        let inverted = SpeedRange { min_wpm: 30, max_wpm: 20 };
+        assert_eq!(raw_to_wpm(1023, 1023, inverted), 30);
+        assert_eq!(raw_to_unit(0, 1023, inverted), Duration::from_millis(40));
+    }
+
+    #[test]
+    fn test_hysteresis_and_smoothing() {
+        // 1 WPM per 10 counts makes the step boundaries easy to hit
+        let mut knob = SpeedKnob::new(SpeedRange { min_wpm: 10, max_wpm: 20 }, 100).unwrap();
+        assert_eq!(knob.update(50), Some(15));
+        assert_eq!(knob.unit(), Some(Duration::from_millis(80)));
+
+        // Noise around the 15/16 midpoint (55) does not toggle the speed
+        for raw in [55, 56, 54, 57, 55, 56].iter().cycle().take(30) {
+            assert_eq!(knob.update(*raw), None);
+        }
+        assert_eq!(knob.wpm(), Some(15));
+
+        // A single glitch is smoothed away
+        assert_eq!(knob.update(70), None);
+        for _ in 0..10 {
+            knob.update(55);
+        }
+        assert_eq!(knob.wpm(), Some(15));
+
+        // A real move settles on the new speed within a few readings
+        let changes = (0..30).filter_map(|_| knob.update(80)).last();
+        assert_eq!(changes, Some(18));
+        assert_eq!(knob.wpm(), Some(18));
+    }
+
+    #[test]
+    fn test_apply_publishes_speed() {
+        let config = SharedConfig::new();
+        config.set(KeyerConfig::default().with_wpm(20).unwrap().with_farnsworth(Some(15)).unwrap());
+        let mut knob = SpeedKnob::new(SpeedRange::default(), 1023).unwrap();
+
+        // Down to 10 WPM: the Farnsworth speed no longer fits and is dropped
+        assert_eq!(knob.apply(0, &config), Some(10));
+        assert_eq!(config.get().map(|c| (c.wpm(), c.farnsworth_wpm)), Some((10, None)));
+
+        // Held back while command mode owns the configuration
+        config.set_temporary(KeyerConfig::default());
+        for _ in 0..10 {
+            assert_eq!(knob.apply(1023, &config), None);
+        }
+        config.set(config.get().unwrap());
+        assert_eq!((0..30).filter_map(|_| knob.apply(1023, &config)).last(), Some(40));
+        assert_eq!(config.get().map(|c| c.wpm()), Some(40));
+    }
+}
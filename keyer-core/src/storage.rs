@@ -0,0 +1,1106 @@
+//! Persistent settings storage
+//!
+//! [`Settings`] are serialized into a compact record:
+//!
+//! | bytes | content                                   |
+//! |-------|-------------------------------------------|
+//! | 2     | magic `"KY"`                              |
+//! | 1     | format version ([`RECORD_VERSION`])       |
+//! | 1     | payload length                            |
+//! | n     | payload, little endian                    |
+//! | 2     | CRC-16/CCITT-FALSE over everything before |
+//!
+//! Payload fields are append-only: a record written by older firmware is simply
+//! shorter, and the missing fields keep their defaults (the load reports it as
+//! migrated so it can be rewritten). Incompatible changes bump the version, and
+//! such records are rejected instead of being loaded as garbage.
+//!
+//! [`save`]/[`load`] keep a single record at the start of a page. On flash,
+//! [`SettingsLog`] spreads saves over several pages and survives power loss at
+//! any point of a save.
+
+use core::cell::{Cell, RefCell};
+use critical_section::Mutex;
+use heapless::{String, Vec};
+use portable_atomic::{AtomicBool, Ordering};
+use crate::beacon::BeaconSettings;
+use crate::hal::{Duration, Instant};
+use crate::shared::SharedConfig;
+use crate::encoder;
+use crate::macros::CALLSIGN_LEN;
+use crate::message::MemoryError;
+use crate::types::{KeyerConfig, KeyerMode, SqueezeTieRule};
+
+/// Current record format version
+pub const RECORD_VERSION: u8 = 1;
+
+/// Largest encoded record in bytes
+pub const RECORD_MAX: usize = 64;
+
+const MAGIC: [u8; 2] = *b"KY";
+const HEADER_LEN: usize = 4;
+const CRC_LEN: usize = 2;
+
+/// Storage errors
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StorageError {
+    /// Page or offset outside the store
+    OutOfRange,
+    /// Write to flash that was not erased
+    NotErased,
+    /// Store hardware reported a failure
+    Device,
+    /// No record (erased page or wrong magic)
+    Empty,
+    /// Record CRC mismatch
+    Crc,
+    /// Record written by an incompatible format version
+    UnsupportedVersion(u8),
+    /// Field value out of range
+    InvalidValue,
+    /// Store too small for the requested layout
+    Geometry,
+}
+
+#[cfg(feature = "std")]
+impl core::fmt::Display for StorageError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            StorageError::OutOfRange => write!(f, "Address out of range"),
+            StorageError::NotErased => write!(f, "Write to non-erased flash"),
+            StorageError::Device => write!(f, "Storage device error"),
+            StorageError::Empty => write!(f, "No stored record"),
+            StorageError::Crc => write!(f, "Record CRC mismatch"),
+            StorageError::UnsupportedVersion(v) => write!(f, "Unsupported record version {}", v),
+            StorageError::InvalidValue => write!(f, "Invalid stored value"),
+            StorageError::Geometry => write!(f, "Store too small"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for StorageError {}
+
+/// Page-oriented non-volatile memory (flash, EEPROM emulation, RAM for tests)
+///
+/// Erased bytes read as `0xFF`; a write may only clear bits of erased memory.
+pub trait ConfigStore {
+    /// Page size in bytes (the erase unit)
+    fn page_size(&self) -> usize;
+
+    /// Number of pages reserved for settings
+    fn page_count(&self) -> usize;
+
+    /// Read bytes from a page
+    fn read(&mut self, page: usize, offset: usize, buf: &mut [u8]) -> Result<(), StorageError>;
+
+    /// Erase a page to `0xFF`
+    fn erase(&mut self, page: usize) -> Result<(), StorageError>;
+
+    /// Write bytes to an erased area of a page
+    fn write(&mut self, page: usize, offset: usize, data: &[u8]) -> Result<(), StorageError>;
+}
+
+/// RAM-backed store with flash semantics, for tests and simulators
+#[derive(Clone, Debug)]
+pub struct RamStore<const PAGE: usize, const PAGES: usize> {
+    pages: [[u8; PAGE]; PAGES],
+}
+
+impl<const PAGE: usize, const PAGES: usize> RamStore<PAGE, PAGES> {
+    /// Create store with all pages erased
+    pub const fn new() -> Self {
+        Self { pages: [[0xFF; PAGE]; PAGES] }
+    }
+
+    /// Raw page contents
+    pub fn page(&self, page: usize) -> &[u8] {
+        &self.pages[page]
+    }
+
+    /// Raw page contents, e.g. to simulate corruption
+    pub fn page_mut(&mut self, page: usize) -> &mut [u8] {
+        &mut self.pages[page]
+    }
+
+    fn range(&self, page: usize, offset: usize, len: usize) -> Result<core::ops::Range<usize>, StorageError> {
+        if page >= PAGES || offset.checked_add(len).is_none_or(|end| end > PAGE) {
+            return Err(StorageError::OutOfRange);
+        }
+        Ok(offset..offset + len)
+    }
+}
+
+impl<const PAGE: usize, const PAGES: usize> Default for RamStore<PAGE, PAGES> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const PAGE: usize, const PAGES: usize> ConfigStore for RamStore<PAGE, PAGES> {
+    fn page_size(&self) -> usize {
+        PAGE
+    }
+
+    fn page_count(&self) -> usize {
+        PAGES
+    }
+
+    fn read(&mut self, page: usize, offset: usize, buf: &mut [u8]) -> Result<(), StorageError> {
+        let range = self.range(page, offset, buf.len())?;
+        buf.copy_from_slice(&self.pages[page][range]);
+        Ok(())
+    }
+
+    fn erase(&mut self, page: usize) -> Result<(), StorageError> {
+        self.range(page, 0, 0)?;
+        self.pages[page] = [0xFF; PAGE];
+        Ok(())
+    }
+
+    fn write(&mut self, page: usize, offset: usize, data: &[u8]) -> Result<(), StorageError> {
+        let range = self.range(page, offset, data.len())?;
+        let target = &mut self.pages[page][range];
+        if target.iter().zip(data).any(|(old, new)| new & !old != 0) {
+            return Err(StorageError::NotErased);
+        }
+        target.copy_from_slice(data);
+        Ok(())
+    }
+}
+
+/// CRC-16/CCITT-FALSE (poly 0x1021, init 0xFFFF)
+pub fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0xFFFF, |crc, &byte| {
+        let mut crc = crc ^ (u16::from(byte) << 8);
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 };
+        }
+        crc
+    })
+}
+
+/// Settings kept across power cycles
+#[derive(Clone, Debug, PartialEq)]
+pub struct Settings {
+    /// Keyer configuration (`queue_size` is a build-time setting and not stored)
+    pub keyer: KeyerConfig,
+    /// Own callsign for `{CALL}`
+    pub callsign: String<CALLSIGN_LEN>,
+    /// Next contest serial number
+    pub serial: u16,
+    /// Beacon started at power-up
+    pub beacon: BeaconSettings,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            keyer: KeyerConfig::default(),
+            callsign: String::new(),
+            serial: 1,
+            beacon: BeaconSettings::default(),
+        }
+    }
+}
+
+/// Encoded settings record
+pub type Record = Vec<u8, RECORD_MAX>;
+
+/// Result of decoding a record
+#[derive(Clone, Debug, PartialEq)]
+pub struct Loaded {
+    /// Decoded settings
+    pub settings: Settings,
+    /// Record came from an older format and should be rewritten
+    pub migrated: bool,
+    /// Encoded record length in bytes
+    pub len: usize,
+}
+
+fn mode_to_u8(mode: KeyerMode) -> u8 {
+    match mode {
+        KeyerMode::ModeA => 0,
+        KeyerMode::ModeB => 1,
+        KeyerMode::SuperKeyer => 2,
+        KeyerMode::Ultimatic => 3,
+        KeyerMode::Bug => 4,
+        KeyerMode::StraightKey => 5,
+    }
+}
+
+fn mode_from_u8(value: u8) -> Result<KeyerMode, StorageError> {
+    Ok(match value {
+        0 => KeyerMode::ModeA,
+        1 => KeyerMode::ModeB,
+        2 => KeyerMode::SuperKeyer,
+        3 => KeyerMode::Ultimatic,
+        4 => KeyerMode::Bug,
+        5 => KeyerMode::StraightKey,
+        _ => return Err(StorageError::InvalidValue),
+    })
+}
+
+fn tie_to_u8(rule: SqueezeTieRule) -> u8 {
+    match rule {
+        SqueezeTieRule::Dit => 0,
+        SqueezeTieRule::Dah => 1,
+        SqueezeTieRule::Alternate => 2,
+    }
+}
+
+fn tie_from_u8(value: u8) -> Result<SqueezeTieRule, StorageError> {
+    Ok(match value {
+        0 => SqueezeTieRule::Dit,
+        1 => SqueezeTieRule::Dah,
+        2 => SqueezeTieRule::Alternate,
+        _ => return Err(StorageError::InvalidValue),
+    })
+}
+
+// Flag bits
+const FLAG_CHAR_SPACE: u8 = 0x01;
+const FLAG_SIDETONE: u8 = 0x02;
+const FLAG_PADDLE_SWAP: u8 = 0x04;
+const FLAG_SINGLE_LEVER: u8 = 0x08;
+const FLAG_TX: u8 = 0x10;
+
+/// Payload length written by this firmware, without the callsign text
+const PAYLOAD_LEN_V1: usize = 17;
+
+/// Serialize settings into a record
+pub fn encode(settings: &Settings) -> Record {
+    let keyer = &settings.keyer;
+    let mut flags = 0;
+    for (set, bit) in [
+        (keyer.char_space_enabled, FLAG_CHAR_SPACE),
+        (keyer.sidetone_enabled, FLAG_SIDETONE),
+        (keyer.paddle_swap, FLAG_PADDLE_SWAP),
+        (keyer.single_lever, FLAG_SINGLE_LEVER),
+        (keyer.tx_enabled, FLAG_TX),
+    ] {
+        if set {
+            flags |= bit;
+        }
+    }
+    let unit_ms = keyer.unit.as_millis().min(u16::MAX as u64) as u16;
+    let farnsworth = keyer.farnsworth_wpm.map_or(0, |wpm| wpm.min(255) as u8);
+
+    let mut record = Record::new();
+    // Cannot fail: header, payload and CRC are far below RECORD_MAX
+    let mut push = |bytes: &[u8]| record.extend_from_slice(bytes).ok();
+    push(&MAGIC);
+    push(&[RECORD_VERSION, (PAYLOAD_LEN_V1 + settings.callsign.len()) as u8]);
+    push(&[mode_to_u8(keyer.mode), flags]);
+    push(&unit_ms.to_le_bytes());
+    push(&[
+        keyer.debounce_ms.min(255) as u8,
+        tie_to_u8(keyer.squeeze_tie),
+        keyer.dah_ratio_tenths,
+        keyer.weighting,
+        farnsworth,
+    ]);
+    push(&settings.serial.to_le_bytes());
+    push(&[settings.callsign.len() as u8]);
+    push(settings.callsign.as_bytes());
+    let beacon = &settings.beacon;
+    push(&[u8::from(beacon.enabled), beacon.slot]);
+    push(&beacon.interval_s.to_le_bytes());
+    push(&[beacon.carrier_s]);
+
+    let crc = crc16(&record);
+    record.extend_from_slice(&crc.to_le_bytes()).ok();
+    record
+}
+
+/// Sequential payload reader
+///
+/// Reading past the end fails with `None`: the field is absent from an older
+/// record, as opposed to `Some(error)` for a corrupt value.
+struct Fields<'a> {
+    data: &'a [u8],
+}
+
+type FieldResult<T> = Result<T, Option<StorageError>>;
+
+impl Fields<'_> {
+    fn bytes(&mut self, len: usize) -> FieldResult<&[u8]> {
+        let bytes = self.data.get(..len).ok_or(None)?;
+        self.data = &self.data[len..];
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> FieldResult<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> FieldResult<u16> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+}
+
+/// Decode a record; fields missing from older records keep `defaults`
+pub fn decode(bytes: &[u8], defaults: &Settings) -> Result<Loaded, StorageError> {
+    if bytes.len() < HEADER_LEN || bytes[..2] != MAGIC {
+        return Err(StorageError::Empty);
+    }
+    let version = bytes[2];
+    let len = HEADER_LEN + bytes[3] as usize + CRC_LEN;
+    let record = bytes.get(..len).ok_or(StorageError::Crc)?;
+    let (body, crc) = record.split_at(len - CRC_LEN);
+    if crc16(body) != u16::from_le_bytes([crc[0], crc[1]]) {
+        return Err(StorageError::Crc);
+    }
+    if version == 0 || version > RECORD_VERSION {
+        return Err(StorageError::UnsupportedVersion(version));
+    }
+
+    let mut settings = defaults.clone();
+    let mut fields = Fields { data: &body[HEADER_LEN..] };
+    let keyer = &mut settings.keyer;
+    let complete = (|| -> FieldResult<()> {
+        keyer.mode = mode_from_u8(fields.u8()?).map_err(Some)?;
+        let flags = fields.u8()?;
+        keyer.char_space_enabled = flags & FLAG_CHAR_SPACE != 0;
+        keyer.sidetone_enabled = flags & FLAG_SIDETONE != 0;
+        keyer.paddle_swap = flags & FLAG_PADDLE_SWAP != 0;
+        keyer.single_lever = flags & FLAG_SINGLE_LEVER != 0;
+        keyer.tx_enabled = flags & FLAG_TX != 0;
+        keyer.unit = Duration::from_millis(u64::from(fields.u16()?));
+        keyer.debounce_ms = u64::from(fields.u8()?);
+        keyer.squeeze_tie = tie_from_u8(fields.u8()?).map_err(Some)?;
+        keyer.dah_ratio_tenths = fields.u8()?;
+        keyer.weighting = fields.u8()?;
+        keyer.farnsworth_wpm = Some(u32::from(fields.u8()?)).filter(|&wpm| wpm != 0);
+        settings.serial = fields.u16()?.max(1);
+        let callsign_len = fields.u8()? as usize;
+        let callsign = core::str::from_utf8(fields.bytes(callsign_len)?).ok();
+        settings.callsign.clear();
+        settings.callsign.push_str(callsign.unwrap_or_default()).ok();
+        let beacon = &mut settings.beacon;
+        beacon.enabled = fields.u8()? != 0;
+        beacon.slot = fields.u8()?;
+        beacon.interval_s = fields.u16()?;
+        beacon.carrier_s = fields.u8()?;
+        Ok(())
+    })();
+
+    // A short payload is an older format
+    let migrated = match complete {
+        Ok(()) => false,
+        Err(None) => true,
+        Err(Some(error)) => return Err(error),
+    };
+    validate(&settings.keyer)?;
+    if settings.beacon.interval_s == 0 {
+        return Err(StorageError::InvalidValue);
+    }
+    Ok(Loaded { settings, migrated, len })
+}
+
+/// Reject values the keyer could not run with
+fn validate(keyer: &KeyerConfig) -> Result<(), StorageError> {
+    // wpm() divides by the unit: check it first
+    let valid = keyer.unit.as_millis() > 0
+        && KeyerConfig::default().with_timing(keyer.dah_ratio_tenths, keyer.weighting).is_ok()
+        && keyer.farnsworth_wpm.is_none_or(|effective| effective < keyer.wpm());
+    if valid {
+        Ok(())
+    } else {
+        Err(StorageError::InvalidValue)
+    }
+}
+
+/// Write settings to a page (erasing it first)
+pub fn save<S: ConfigStore>(store: &mut S, page: usize, settings: &Settings) -> Result<(), StorageError> {
+    let record = encode(settings);
+    store.erase(page)?;
+    store.write(page, 0, &record)
+}
+
+/// Read settings from a page
+pub fn load<S: ConfigStore>(store: &mut S, page: usize, defaults: &Settings) -> Result<Loaded, StorageError> {
+    let mut buf = [0u8; RECORD_MAX];
+    let len = RECORD_MAX.min(store.page_size());
+    store.read(page, 0, &mut buf[..len])?;
+    decode(&buf[..len], defaults)
+}
+
+/// Read settings, falling back to `defaults` for missing or unusable records
+/// Migrated records are rewritten in the current format
+pub fn load_or_default<S: ConfigStore>(store: &mut S, page: usize, defaults: &Settings) -> Settings {
+    match load(store, page, defaults) {
+        Ok(loaded) => {
+            if loaded.migrated {
+                // Keep running on the loaded settings even if the rewrite fails
+                save(store, page, &loaded.settings).ok();
+            }
+            loaded.settings
+        }
+        Err(_) => defaults.clone(),
+    }
+}
+
+/// RAM store that can lose power in the middle of a write or erase
+///
+/// After [`cut_power_after`](Self::cut_power_after) the given number of bytes
+/// are still programmed; the write in progress is then torn and every access
+/// fails until [`power_cycle`](Self::power_cycle). An erase that runs out of
+/// budget leaves the page half erased.
+#[derive(Clone, Debug)]
+pub struct SimFlash<const PAGE: usize, const PAGES: usize> {
+    ram: RamStore<PAGE, PAGES>,
+    budget: Option<usize>,
+    powered: bool,
+    erase_counts: [u32; PAGES],
+}
+
+impl<const PAGE: usize, const PAGES: usize> SimFlash<PAGE, PAGES> {
+    /// Create erased flash
+    pub const fn new() -> Self {
+        Self {
+            ram: RamStore::new(),
+            budget: None,
+            powered: true,
+            erase_counts: [0; PAGES],
+        }
+    }
+
+    /// Lose power once `bytes` more bytes have been programmed
+    pub fn cut_power_after(&mut self, bytes: usize) {
+        self.budget = Some(bytes);
+    }
+
+    /// Restore power (contents are kept)
+    pub fn power_cycle(&mut self) {
+        self.budget = None;
+        self.powered = true;
+    }
+
+    /// Whether power was lost
+    pub fn is_powered(&self) -> bool {
+        self.powered
+    }
+
+    /// Number of erases of a page
+    pub fn erase_count(&self, page: usize) -> u32 {
+        self.erase_counts[page]
+    }
+
+    /// Underlying memory
+    pub fn ram(&mut self) -> &mut RamStore<PAGE, PAGES> {
+        &mut self.ram
+    }
+
+    fn check_power(&self) -> Result<(), StorageError> {
+        if self.powered {
+            Ok(())
+        } else {
+            Err(StorageError::Device)
+        }
+    }
+}
+
+impl<const PAGE: usize, const PAGES: usize> Default for SimFlash<PAGE, PAGES> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const PAGE: usize, const PAGES: usize> ConfigStore for SimFlash<PAGE, PAGES> {
+    fn page_size(&self) -> usize {
+        PAGE
+    }
+
+    fn page_count(&self) -> usize {
+        PAGES
+    }
+
+    fn read(&mut self, page: usize, offset: usize, buf: &mut [u8]) -> Result<(), StorageError> {
+        self.check_power()?;
+        self.ram.read(page, offset, buf)
+    }
+
+    fn erase(&mut self, page: usize) -> Result<(), StorageError> {
+        self.check_power()?;
+        self.ram.erase(page)?;
+        self.erase_counts[page] += 1;
+        if self.budget == Some(0) {
+            // Torn erase: only the first half was cleared, the rest is noise
+            for (i, byte) in self.ram.page_mut(page)[PAGE / 2..].iter_mut().enumerate() {
+                *byte = (i as u8).wrapping_mul(37);
+            }
+            self.powered = false;
+            return Err(StorageError::Device);
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, page: usize, offset: usize, data: &[u8]) -> Result<(), StorageError> {
+        self.check_power()?;
+        let Some(budget) = self.budget else {
+            return self.ram.write(page, offset, data);
+        };
+        if data.len() <= budget {
+            self.budget = Some(budget - data.len());
+            return self.ram.write(page, offset, data);
+        }
+        self.ram.write(page, offset, &data[..budget])?;
+        self.powered = false;
+        Err(StorageError::Device)
+    }
+}
+
+/// Slot size in [`SettingsLog`]: sequence number, record, commit marker
+pub const SLOT_LEN: usize = 4 + RECORD_MAX + COMMIT_LEN;
+
+const COMMIT_LEN: usize = 4;
+/// Written after the record; any other value marks an incomplete save
+const COMMIT: [u8; COMMIT_LEN] = [0x00, 0x00, 0x5A, 0xA5];
+
+/// Slot position in a [`SettingsLog`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct SlotPos {
+    page: usize,
+    slot: usize,
+}
+
+/// Wear-leveled settings log over two or more flash pages
+///
+/// Each save goes into the next free slot, so a page is only erased once all
+/// of its slots are used, and pages are used in rotation. A save writes the
+/// sequence number and record first and the commit marker last: a save cut
+/// short by power loss is ignored and the previous one is loaded instead.
+/// The page about to be erased never holds the newest record.
+pub struct SettingsLog<S: ConfigStore> {
+    store: S,
+    /// Newest committed slot and its sequence number
+    newest: Option<(SlotPos, u32)>,
+    /// Last record written or loaded, to skip unchanged saves
+    last: Record,
+}
+
+impl<S: ConfigStore> SettingsLog<S> {
+    /// Use all pages of `store` for the log
+    pub fn new(store: S) -> Result<Self, StorageError> {
+        if store.page_count() < 2 || store.page_size() < SLOT_LEN {
+            return Err(StorageError::Geometry);
+        }
+        Ok(Self {
+            store,
+            newest: None,
+            last: Record::new(),
+        })
+    }
+
+    /// Underlying store
+    pub fn store(&mut self) -> &mut S {
+        &mut self.store
+    }
+
+    fn slots_per_page(&self) -> usize {
+        self.store.page_size() / SLOT_LEN
+    }
+
+    /// Find the newest valid record; `defaults` fill fields it does not have
+    /// Records from an older format are rewritten in the current one
+    pub fn load(&mut self, defaults: &Settings) -> Settings {
+        self.newest = None;
+        let mut found: Option<Loaded> = None;
+        for page in 0..self.store.page_count() {
+            for slot in 0..self.slots_per_page() {
+                let pos = SlotPos { page, slot };
+                let mut buf = [0u8; SLOT_LEN];
+                if self.store.read(page, slot * SLOT_LEN, &mut buf).is_err() {
+                    continue;
+                }
+                let seq = u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]);
+                if buf[SLOT_LEN - COMMIT_LEN..] != COMMIT || self.newest.is_some_and(|(_, newest)| seq <= newest) {
+                    continue;
+                }
+                // Committed records that do not decode are skipped, not loaded
+                if let Ok(loaded) = decode(&buf[4..SLOT_LEN - COMMIT_LEN], defaults) {
+                    self.newest = Some((pos, seq));
+                    self.last = Record::from_slice(&buf[4..4 + loaded.len]).unwrap_or_default();
+                    found = Some(loaded);
+                }
+            }
+        }
+        match found {
+            Some(loaded) => {
+                if loaded.migrated {
+                    // Keep running on the loaded settings even if the rewrite fails
+                    self.save(&loaded.settings).ok();
+                }
+                loaded.settings
+            }
+            None => defaults.clone(),
+        }
+    }
+
+    /// Append settings to the log (unchanged settings are not written again)
+    pub fn save(&mut self, settings: &Settings) -> Result<(), StorageError> {
+        let record = encode(settings);
+        if record == self.last {
+            return Ok(());
+        }
+        let (pos, seq) = self.next_slot()?;
+        let offset = pos.slot * SLOT_LEN;
+
+        let mut body = Vec::<u8, { 4 + RECORD_MAX }>::new();
+        // Cannot fail: the record is at most RECORD_MAX bytes
+        body.extend_from_slice(&seq.to_le_bytes()).ok();
+        body.extend_from_slice(&record).ok();
+        self.store.write(pos.page, offset, &body)?;
+        self.store.write(pos.page, offset + SLOT_LEN - COMMIT_LEN, &COMMIT)?;
+
+        self.newest = Some((pos, seq));
+        self.last = record;
+        Ok(())
+    }
+
+    /// Next erased slot after the newest record, erasing a page when moving on
+    fn next_slot(&mut self) -> Result<(SlotPos, u32), StorageError> {
+        let Some((newest, seq)) = self.newest else {
+            // Nothing valid stored: start over
+            self.store.erase(0)?;
+            return Ok((SlotPos { page: 0, slot: 0 }, 0));
+        };
+        let seq = seq.wrapping_add(1);
+        for slot in newest.slot + 1..self.slots_per_page() {
+            // Slots written by a torn save are not reused
+            let mut buf = [0u8; SLOT_LEN];
+            self.store.read(newest.page, slot * SLOT_LEN, &mut buf)?;
+            if buf.iter().all(|&b| b == 0xFF) {
+                return Ok((SlotPos { page: newest.page, slot }, seq));
+            }
+        }
+        let page = (newest.page + 1) % self.store.page_count();
+        self.store.erase(page)?;
+        Ok((SlotPos { page, slot: 0 }, seq))
+    }
+}
+
+/// Decides when a changed runtime configuration should be saved
+///
+/// Saving waits until the configuration has been left alone for `delay`, so
+/// turning the speed knob writes flash once rather than at every step.
+/// Temporary configurations (command mode) are never saved.
+#[derive(Copy, Clone, Debug)]
+pub struct AutoSave {
+    delay: Duration,
+    seen: u32,
+    changed_at: Option<Instant>,
+    /// Runtime-only mode in use, and the mode to save in its place
+    mode_override: Option<(KeyerMode, KeyerMode)>,
+}
+
+impl AutoSave {
+    /// Track changes published after now
+    pub fn new(config: &SharedConfig, delay: Duration) -> Self {
+        Self {
+            delay,
+            seen: config.generation(),
+            changed_at: None,
+            mode_override: None,
+        }
+    }
+
+    /// Save `stored` instead of the runtime-only `mode`
+    ///
+    /// For power-up overrides such as a straight key plugged in: they must not
+    /// outlive the session. Choosing another mode ends the override.
+    pub fn with_mode_override(mut self, mode: KeyerMode, stored: KeyerMode) -> Self {
+        self.mode_override = Some((mode, stored));
+        self
+    }
+
+    fn saved(&self, config: KeyerConfig) -> KeyerConfig {
+        match self.mode_override {
+            Some((mode, stored)) if config.mode == mode => KeyerConfig { mode: stored, ..config },
+            _ => config,
+        }
+    }
+
+    /// Return the configuration to save, if it is due
+    pub fn poll(&mut self, now: Instant, config: &SharedConfig) -> Option<KeyerConfig> {
+        let generation = config.generation();
+        if generation != self.seen {
+            self.seen = generation;
+            self.changed_at = Some(now);
+        }
+        let changed_at = self.changed_at?;
+        if now.duration_since(changed_at) < self.delay || config.is_temporary() {
+            return None;
+        }
+        self.changed_at = None;
+        config.get().map(|config| self.saved(config))
+    }
+
+    /// Return the configuration to save right away (explicit save request)
+    ///
+    /// None while the configuration is temporary; a pending delayed save is
+    /// covered by this one.
+    pub fn flush(&mut self, config: &SharedConfig) -> Option<KeyerConfig> {
+        if config.is_temporary() {
+            return None;
+        }
+        self.seen = config.generation();
+        self.changed_at = None;
+        config.get().map(|config| self.saved(config))
+    }
+}
+
+/// Settings outside the keyer configuration waiting to be saved
+#[derive(Copy, Clone, Debug)]
+struct Pending {
+    beacon: Option<BeaconSettings>,
+    callsign: bool,
+    serial: Option<u16>,
+}
+
+impl Pending {
+    const NONE: Self = Self { beacon: None, callsign: false, serial: None };
+}
+
+/// Save request from another task (e.g. the serial command line)
+/// Safe for use across tasks and interrupt contexts
+///
+/// Also carries the settings that are not part of [`KeyerConfig`] to the
+/// settings task, and holds the callsign in use so every task sees changes.
+pub struct SaveRequest {
+    requested: AtomicBool,
+    pending: Mutex<Cell<Pending>>,
+    callsign: Mutex<RefCell<String<CALLSIGN_LEN>>>,
+}
+
+impl SaveRequest {
+    pub const fn new() -> Self {
+        Self {
+            requested: AtomicBool::new(false),
+            pending: Mutex::new(Cell::new(Pending::NONE)),
+            callsign: Mutex::new(RefCell::new(String::new())),
+        }
+    }
+
+    /// Ask the settings task to save now
+    pub fn request(&self) {
+        self.requested.store(true, Ordering::Release);
+    }
+
+    /// Ask the settings task to save new beacon settings (used from the next power-up)
+    pub fn request_beacon(&self, beacon: BeaconSettings) {
+        self.update_pending(|pending| pending.beacon = Some(beacon));
+        self.request();
+    }
+
+    /// Callsign in use (empty if not set)
+    pub fn callsign(&self) -> String<CALLSIGN_LEN> {
+        critical_section::with(|cs| self.callsign.borrow(cs).borrow().clone())
+    }
+
+    /// Use a callsign without saving it, e.g. the one loaded at power-up
+    pub fn set_callsign(&self, callsign: &str) -> Result<(), MemoryError> {
+        encoder::validate(callsign)?;
+        let mut value = String::new();
+        value.push_str(callsign).map_err(|_| MemoryError::TooLong)?;
+        critical_section::with(|cs| self.callsign.borrow(cs).replace(value));
+        Ok(())
+    }
+
+    /// Use a new callsign and ask the settings task to save it
+    pub fn request_callsign(&self, callsign: &str) -> Result<(), MemoryError> {
+        self.set_callsign(callsign)?;
+        self.update_pending(|pending| pending.callsign = true);
+        self.request();
+        Ok(())
+    }
+
+    /// Ask the settings task to save the next contest serial number
+    pub fn request_serial(&self, serial: u16) {
+        self.update_pending(|pending| pending.serial = Some(serial));
+        self.request();
+    }
+
+    /// Take a pending request
+    ///
+    /// Take it before [`take_changes`](Self::take_changes): a save request
+    /// seen here then always finds its changes.
+    pub fn take(&self) -> bool {
+        self.requested.swap(false, Ordering::AcqRel)
+    }
+
+    /// Move pending changes into `settings`; returns true if there were any
+    pub fn take_changes(&self, settings: &mut Settings) -> bool {
+        let pending = critical_section::with(|cs| self.pending.borrow(cs).replace(Pending::NONE));
+        if let Some(beacon) = pending.beacon {
+            settings.beacon = beacon;
+        }
+        if pending.callsign {
+            settings.callsign = self.callsign();
+        }
+        if let Some(serial) = pending.serial {
+            settings.serial = serial;
+        }
+        pending.beacon.is_some() || pending.callsign || pending.serial.is_some()
+    }
+
+    fn update_pending(&self, change: impl FnOnce(&mut Pending)) {
+        critical_section::with(|cs| {
+            let cell = self.pending.borrow(cs);
+            let mut pending = cell.get();
+            change(&mut pending);
+            cell.set(pending);
+        });
+    }
+}
+
+impl Default for SaveRequest {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Settings {
+        let mut settings = Settings::default();
+        settings.keyer.mode = KeyerMode::Ultimatic;
+        settings.keyer.unit = Duration::from_millis(48);
+        settings.keyer.weighting = 55;
+        settings.keyer.farnsworth_wpm = Some(15);
+        settings.keyer.paddle_swap = true;
+        settings.callsign.push_str("JA1ABC").unwrap();
+        settings.serial = 321;
+        settings.beacon = BeaconSettings { enabled: true, slot: 2, interval_s: 300, carrier_s: 5 };
+        settings
+    }
+
+    #[test]
+    fn test_crc16() {
+        assert_eq!(crc16(b"123456789"), 0x29B1);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut store = RamStore::<64, 2>::new();
+        let settings = sample();
+        save(&mut store, 1, &settings).unwrap();
+
+        let loaded = load(&mut store, 1, &Settings::default()).unwrap();
+        assert_eq!(loaded.settings, settings);
+        assert!(!loaded.migrated);
+        assert_eq!(loaded.len, encode(&settings).len());
+        assert_eq!(load(&mut store, 0, &Settings::default()), Err(StorageError::Empty));
+
+        // queue_size is not stored: it comes from the defaults
+        let defaults = Settings {
+            keyer: KeyerConfig { queue_size: 8, ..KeyerConfig::default() },
+            ..Settings::default()
+        };
+        assert_eq!(load(&mut store, 1, &defaults).unwrap().settings.keyer.queue_size, 8);
+    }
+
+    #[test]
+    fn test_corruption_detected() {
+        let mut store = RamStore::<64, 1>::new();
+        save(&mut store, 0, &sample()).unwrap();
+        store.page_mut(0)[6] ^= 0x01;
+        assert_eq!(load(&mut store, 0, &Settings::default()), Err(StorageError::Crc));
+        assert_eq!(load_or_default(&mut store, 0, &Settings::default()), Settings::default());
+
+        // A zero unit is rejected, not divided by
+        let mut zero = sample();
+        zero.keyer.unit = Duration::from_millis(0);
+        assert_eq!(decode(&encode(&zero), &Settings::default()), Err(StorageError::InvalidValue));
+
+        // Flash writes cannot set bits
+        assert_eq!(store.write(0, 0, &[0xFF]), Err(StorageError::NotErased));
+        assert_eq!(store.write(1, 0, &[0]), Err(StorageError::OutOfRange));
+    }
+
+    #[test]
+    fn test_version_and_migration() {
+        // Incompatible format: rejected even with a valid CRC
+        let mut record = encode(&sample());
+        record[2] = RECORD_VERSION + 1;
+        let crc_at = record.len() - CRC_LEN;
+        let crc = crc16(&record[..crc_at]);
+        record[crc_at..].copy_from_slice(&crc.to_le_bytes());
+        assert_eq!(
+            decode(&record, &Settings::default()),
+            Err(StorageError::UnsupportedVersion(RECORD_VERSION + 1))
+        );
+
+        // Older firmware wrote only mode, flags and unit: the rest keeps defaults
+        let mut old = Record::new();
+        old.extend_from_slice(&[b'K', b'Y', 1, 4, 1, FLAG_SIDETONE | FLAG_TX, 40, 0]).unwrap();
+        let crc = crc16(&old);
+        old.extend_from_slice(&crc.to_le_bytes()).unwrap();
+
+        let mut store = RamStore::<64, 1>::new();
+        store.write(0, 0, &old).unwrap();
+        let settings = load_or_default(&mut store, 0, &sample());
+        assert_eq!(settings.keyer.mode, KeyerMode::ModeB);
+        assert_eq!(settings.keyer.unit, Duration::from_millis(40));
+        assert!(!settings.keyer.char_space_enabled);
+        assert_eq!(settings.callsign, sample().callsign);
+        assert_eq!(settings.beacon, sample().beacon);
+
+        // The record was rewritten in the current format
+        let reloaded = load(&mut store, 0, &Settings::default()).unwrap();
+        assert!(!reloaded.migrated);
+        assert_eq!(reloaded.settings, settings);
+
+        // Records from before the beacon settings keep the beacon off
+        let mut record = encode(&sample());
+        record.truncate(record.len() - CRC_LEN - 5);
+        record[3] -= 5;
+        let crc = crc16(&record);
+        record.extend_from_slice(&crc.to_le_bytes()).unwrap();
+        let loaded = decode(&record, &Settings::default()).unwrap();
+        assert!(loaded.migrated);
+        assert_eq!((loaded.settings.callsign.as_str(), loaded.settings.beacon.enabled), ("JA1ABC", false));
+    }
+
+    #[test]
+    fn test_log_wear_leveling() {
+        let mut log = SettingsLog::new(SimFlash::<256, 3>::new()).unwrap();
+        assert_eq!(log.load(&Settings::default()), Settings::default());
+
+        let mut settings = sample();
+        for serial in 1..=40 {
+            settings.serial = serial;
+            log.save(&settings).unwrap();
+        }
+        // Unchanged settings cost nothing
+        log.save(&settings).unwrap();
+
+        let mut log = SettingsLog::new(log.store.clone()).unwrap();
+        assert_eq!(log.load(&Settings::default()).serial, 40);
+        // 3 slots per page: 40 saves take 14 page erases, spread over all pages
+        let erases: Vec<u32, 3> = (0..3).map(|page| log.store().erase_count(page)).collect();
+        assert_eq!(erases.iter().sum::<u32>(), 14);
+        assert!(erases.iter().all(|&count| (4..=5).contains(&count)));
+
+        assert!(SettingsLog::new(SimFlash::<256, 1>::new()).is_err());
+        assert!(SettingsLog::new(SimFlash::<64, 2>::new()).is_err());
+    }
+
+    #[test]
+    fn test_log_power_loss() {
+        let defaults = Settings::default();
+        let mut old = sample();
+        let mut new = sample();
+        new.keyer.mode = KeyerMode::ModeB;
+
+        // Cut power at every byte of a save, including the one that rotates pages
+        for saved_before in [1, 3] {
+            for cut in 0..=SLOT_LEN {
+                let mut log = SettingsLog::new(SimFlash::<256, 2>::new()).unwrap();
+                for serial in 0..saved_before {
+                    old.serial = serial + 1;
+                    log.save(&old).unwrap();
+                }
+                new.serial = old.serial;
+
+                log.store().cut_power_after(cut);
+                let result = log.save(&new);
+                let torn = !log.store().is_powered();
+                log.store().power_cycle();
+
+                let loaded = log.load(&defaults);
+                if result.is_ok() {
+                    assert_eq!(loaded, new);
+                } else {
+                    assert!(torn);
+                    assert_eq!(loaded, old, "cut after {} bytes", cut);
+                }
+
+                // The log keeps working after the failure
+                new.serial += 100;
+                log.save(&new).unwrap();
+                assert_eq!(log.load(&defaults), new);
+                new.serial -= 100;
+            }
+        }
+    }
+
+    #[test]
+    fn test_autosave() {
+        let config = SharedConfig::new();
+        config.set(KeyerConfig::default());
+        let mut autosave = AutoSave::new(&config, Duration::from_millis(1000));
+        let at = Instant::from_millis;
+        assert!(autosave.poll(at(0), &config).is_none());
+
+        // Knob turned several times: one save, a second after the last change
+        config.update(|c| c.unit = Duration::from_millis(50));
+        assert!(autosave.poll(at(100), &config).is_none());
+        config.update(|c| c.unit = Duration::from_millis(48));
+        assert!(autosave.poll(at(600), &config).is_none());
+        assert!(autosave.poll(at(1500), &config).is_none());
+        assert_eq!(autosave.poll(at(1600), &config).map(|c| c.unit), Some(Duration::from_millis(48)));
+        assert!(autosave.poll(at(5000), &config).is_none());
+
+        // Command mode settings wait for the permanent ones
+        config.set_temporary(KeyerConfig { tx_enabled: false, ..KeyerConfig::default() });
+        assert!(autosave.poll(at(6000), &config).is_none());
+        assert!(autosave.poll(at(9000), &config).is_none());
+        config.set(KeyerConfig::default());
+        assert!(autosave.poll(at(9100), &config).is_none());
+        assert_eq!(autosave.poll(at(10_100), &config).map(|c| c.tx_enabled), Some(true));
+
+        // An explicit save covers the pending delayed one
+        config.update(|c| c.weighting = 60);
+        assert!(autosave.poll(at(10_200), &config).is_none());
+        assert_eq!(autosave.flush(&config).map(|c| c.weighting), Some(60));
+        assert!(autosave.poll(at(20_000), &config).is_none());
+    }
+
+    #[test]
+    fn test_autosave_mode_override() {
+        // Straight key plugged in at power-up over a stored Mode B
+        let mut settings = sample();
+        settings.keyer.mode = KeyerMode::ModeB;
+        let config = SharedConfig::new();
+        config.set(KeyerConfig { mode: KeyerMode::StraightKey, ..settings.keyer });
+        let mut autosave = AutoSave::new(&config, Duration::from_millis(1000))
+            .with_mode_override(KeyerMode::StraightKey, settings.keyer.mode);
+
+        // Other changes are saved, the stored mode is kept
+        let mut store = RamStore::<64, 1>::new();
+        config.update(|c| c.weighting = 60);
+        settings.keyer = autosave.flush(&config).unwrap();
+        save(&mut store, 0, &settings).unwrap();
+        let loaded = load(&mut store, 0, &Settings::default()).unwrap().settings;
+        assert_eq!((loaded.keyer.mode, loaded.keyer.weighting), (KeyerMode::ModeB, 60));
+
+        // A mode chosen by the operator is saved
+        config.update(|c| c.mode = KeyerMode::Ultimatic);
+        assert_eq!(autosave.flush(&config).map(|c| c.mode), Some(KeyerMode::Ultimatic));
+    }
+
+    #[test]
+    fn test_save_request_changes() {
+        let save = SaveRequest::new();
+        let mut settings = sample();
+        assert!(!save.take() && !save.take_changes(&mut settings));
+
+        // Each change asks for a save and is moved into the settings once
+        save.request_serial(42);
+        assert!(save.take());
+        assert!(save.take_changes(&mut settings));
+        assert_eq!(settings.serial, 42);
+        assert!(!save.take_changes(&mut settings));
+
+        // A callsign that cannot be sent is refused and not saved
+        assert!(save.request_callsign("JA1{").is_err());
+        assert!(!save.take());
+        save.set_callsign("JA1ABC").unwrap();
+        assert!(!save.take_changes(&mut settings));
+    }
+}
@@ -26,6 +26,12 @@ pub mod virtual_time {
         id: usize,
     }
     
+    impl Default for VirtualTime {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+    
     impl VirtualTime {
         pub fn new() -> Self {
             Self {
@@ -45,13 +51,13 @@ pub mod virtual_time {
         /// Advance virtual time by duration
         pub fn advance(&self, duration: Duration) {
             let mut inner = self.inner.lock().unwrap();
-            inner.current_time += duration.as_millis() as u64;
+            inner.current_time += duration.as_millis();
         }
         
         /// Schedule an event at specific time
         pub fn schedule_event(&self, delay: Duration) -> usize {
             let mut inner = self.inner.lock().unwrap();
-            let event_time = inner.current_time + delay.as_millis() as u64;
+            let event_time = inner.current_time + delay.as_millis();
             let event_id = inner.scheduled_events.len();
             
             inner.scheduled_events.push(Reverse(ScheduledEvent {
@@ -66,7 +72,7 @@ pub mod virtual_time {
         pub fn next_event_time(&self) -> Option<Duration> {
             let inner = self.inner.lock().unwrap();
             inner.scheduled_events.peek().map(|event| {
-                Duration::from_millis((event.0.time - inner.current_time) as u64)
+                Duration::from_millis(event.0.time - inner.current_time)
             })
         }
         
@@ -100,6 +106,17 @@ pub mod paddle_simulator {
         pub pressed: bool,
     }
     
+    /// Recorded edge, timed from the start of its trace
+    impl From<crate::trace::TraceEvent> for PaddleEvent {
+        fn from(event: crate::trace::TraceEvent) -> Self {
+            Self {
+                time: Duration::from_millis(u64::from(event.at_ms)),
+                side: event.side,
+                pressed: event.pressed,
+            }
+        }
+    }
+    
     /// Paddle pattern for simulation
     #[derive(Debug, Clone)]
     pub struct PaddlePattern {
@@ -198,6 +215,7 @@ pub mod paddle_simulator {
 pub mod output_capture {
     //! Output capture and analysis for testing
     
+    use crate::decoder::MorseDecoder;
     use crate::types::Element;
     use embassy_time::{Duration, Instant};
     use std::collections::VecDeque;
@@ -219,6 +237,12 @@ pub mod output_capture {
         element_start: Option<Instant>,
     }
     
+    impl Default for OutputCapture {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+    
     impl OutputCapture {
         pub fn new() -> Self {
             Self {
@@ -251,6 +275,16 @@ pub mod output_capture {
             }
         }
         
+        /// Record a character or word space marker
+        pub fn space(&mut self, element: Element, time: Instant) {
+            self.key_up(time);
+            self.events.push_back(OutputEvent {
+                element,
+                start_time: time,
+                duration: Duration::from_millis(0),
+            });
+        }
+        
         /// Get all captured events
         pub fn events(&self) -> &VecDeque<OutputEvent> {
             &self.events
@@ -269,16 +303,18 @@ pub mod output_capture {
             let mut dah_durations = Vec::<Duration, 32>::new();
             let mut inter_element_gaps = Vec::<Duration, 32>::new();
             
-            for (i, event) in self.events.iter().enumerate() {
+            // Space markers carry no key-down, so gaps are measured between keyed events
+            let mut keyed = self.events.iter().filter(|event| !event.element.is_space()).peekable();
+            while let Some(event) = keyed.next() {
                 match event.element {
                     Element::Dit => { dit_durations.push(event.duration).ok(); },
                     Element::Dah => { dah_durations.push(event.duration).ok(); },
-                    Element::CharSpace => {}
+                    // Manual keying has no fixed length to check
+                    Element::CharSpace | Element::WordSpace | Element::KeyDown | Element::KeyUp => {}
                 }
                 
                 // Calculate inter-element gap
-                if i + 1 < self.events.len() {
-                    let next_event = &self.events[i + 1];
+                if let Some(next_event) = keyed.peek() {
                     let gap = next_event.start_time.duration_since(
                         event.start_time + event.duration
                     );
@@ -311,11 +347,31 @@ pub mod output_capture {
                     Element::Dit => ".",
                     Element::Dah => "-",
                     Element::CharSpace => " ",
+                    Element::WordSpace => " / ",
+                    Element::KeyDown => "_",
+                    Element::KeyUp => "",
                 };
                 result.push_str(ch).ok();
             }
             result
         }
+        
+        /// Decode captured elements to text
+        pub fn to_text(&self) -> String<32> {
+            use core::fmt::Write;
+            
+            let mut decoder = MorseDecoder::new();
+            let mut result = String::new();
+            for event in &self.events {
+                for decoded in decoder.push(event.element) {
+                    write!(result, "{}", decoded).ok();
+                }
+            }
+            if let Some(decoded) = decoder.flush() {
+                write!(result, "{}", decoded).ok();
+            }
+            result
+        }
     }
     
     /// Timing analysis results
@@ -396,13 +452,14 @@ pub mod test_scenarios {
         }
     }
     
-    /// Test all three keyer modes with same input
+    /// Test all squeeze-capable keyer modes with same input
     pub fn mode_comparison_scenarios() -> Vec<(KeyerMode, PaddlePattern), 8> {
         let unit = Duration::from_millis(60);
         Vec::from_slice(&[
             (KeyerMode::ModeA, PaddlePattern::squeeze(unit, unit * 5)),
             (KeyerMode::ModeB, PaddlePattern::squeeze(unit, unit * 5)),
             (KeyerMode::SuperKeyer, PaddlePattern::squeeze(unit, unit * 5)),
+            (KeyerMode::Ultimatic, PaddlePattern::squeeze(unit, unit * 5)),
         ]).unwrap()
     }
 }
\ No newline at end of file
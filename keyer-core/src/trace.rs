@@ -0,0 +1,454 @@
+//! Paddle edge traces
+//!
+//! [`TraceRecorder`] keeps the latest paddle edges in a ring buffer so a glitch
+//! seen in the field can be dumped and replayed on the host. The dump is plain
+//! text, one line at a time, so it passes through a serial port or a defmt log:
+//!
+//! ```text
+//! KT1 MODE=B WPM=25 WEIGHT=50 RATIO=30 FARNS=OFF SIDETONE=ON SWAP=OFF TX=ON
+//! TIE=DIT SINGLE=OFF CHARSPACE=ON DEBOUNCE=10
+//! 0D 12A 40d 95a
+//! END 4 LOST=0
+//! ```
+//!
+//! | line     | content                                                     |
+//! |----------|-------------------------------------------------------------|
+//! | header   | `KT` + format version ([`TRACE_VERSION`]), then the settings |
+//! | paddle   | paddle handling settings the command line does not change   |
+//! | events   | up to 8 edges: milliseconds since the previous edge + code   |
+//! | `END`    | number of edges in the dump and edges lost to the ring buffer |
+//!
+//! Edge codes are `D`/`d` for Dit down/up and `A`/`a` for Dah down/up; the
+//! first edge of a dump is at 0 ms. Header settings use the serial command line
+//! names (see [`cli`](crate::cli)); with the paddle line, a trace replays with
+//! the keyer settings it was recorded with.
+
+use core::fmt::Write;
+use heapless::String;
+use crate::cli::{self, CliError};
+use crate::types::{KeyerConfig, PaddleSide, SqueezeTieRule};
+
+/// Current trace format version
+pub const TRACE_VERSION: u8 = 1;
+/// Longest trace line (without line ending)
+pub const TRACE_LINE_LEN: usize = 96;
+
+/// Trace line
+pub type TraceLine = String<TRACE_LINE_LEN>;
+
+const EVENTS_PER_LINE: usize = 8;
+/// Timestamps are kept modulo 2^30 ms (about 12 days)
+const TIME_MASK: u32 = (1 << 30) - 1;
+
+/// Paddle edge
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TraceEvent {
+    pub at_ms: u32,
+    pub side: PaddleSide,
+    pub pressed: bool,
+}
+
+impl TraceEvent {
+    const fn code(&self) -> char {
+        match (self.side, self.pressed) {
+            (PaddleSide::Dit, true) => 'D',
+            (PaddleSide::Dit, false) => 'd',
+            (PaddleSide::Dah, true) => 'A',
+            (PaddleSide::Dah, false) => 'a',
+        }
+    }
+
+    fn pack(&self) -> u32 {
+        ((self.at_ms & TIME_MASK) << 2) | (u32::from(self.side == PaddleSide::Dah) << 1) | u32::from(self.pressed)
+    }
+
+    fn unpack(slot: u32) -> Self {
+        Self {
+            at_ms: slot >> 2,
+            side: if slot & 2 != 0 { PaddleSide::Dah } else { PaddleSide::Dit },
+            pressed: slot & 1 != 0,
+        }
+    }
+}
+
+/// Trace errors
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TraceError {
+    /// First line is not a trace header
+    MissingHeader,
+    /// Trace written by an incompatible format version
+    UnsupportedVersion(u8),
+    /// Header setting not understood
+    InvalidSetting,
+    /// Edge token not understood
+    InvalidEvent,
+    /// `END` count differs from the edges read
+    CountMismatch,
+    /// Trace ended without an `END` line
+    Truncated,
+}
+
+#[cfg(feature = "std")]
+impl core::fmt::Display for TraceError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            TraceError::MissingHeader => write!(f, "Missing trace header"),
+            TraceError::UnsupportedVersion(version) => write!(f, "Unsupported trace version {}", version),
+            TraceError::InvalidSetting => write!(f, "Invalid setting in trace header"),
+            TraceError::InvalidEvent => write!(f, "Invalid paddle edge"),
+            TraceError::CountMismatch => write!(f, "Edge count does not match END"),
+            TraceError::Truncated => write!(f, "Trace ends without END"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for TraceError {}
+
+/// Ring buffer of the latest `N` paddle edges
+///
+/// Edges take 4 bytes each. Only state changes are recorded, so the recorder
+/// can be fed the sampled state of both paddles.
+#[derive(Clone, Debug)]
+pub struct TraceRecorder<const N: usize> {
+    slots: [u32; N],
+    /// Index of the oldest edge
+    head: usize,
+    len: usize,
+    lost: u32,
+    dit: bool,
+    dah: bool,
+}
+
+impl<const N: usize> Default for TraceRecorder<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> TraceRecorder<N> {
+    pub const fn new() -> Self {
+        Self { slots: [0; N], head: 0, len: 0, lost: 0, dit: false, dah: false }
+    }
+
+    /// Record a paddle state; returns true if it was an edge
+    pub fn record(&mut self, side: PaddleSide, pressed: bool, now_ms: u32) -> bool {
+        let state = match side {
+            PaddleSide::Dit => &mut self.dit,
+            PaddleSide::Dah => &mut self.dah,
+        };
+        if *state == pressed || N == 0 {
+            return false;
+        }
+        *state = pressed;
+
+        let slot = TraceEvent { at_ms: now_ms, side, pressed }.pack();
+        if self.len == N {
+            // Overwrite the oldest edge
+            self.slots[self.head] = slot;
+            self.head = (self.head + 1) % N;
+            self.lost = self.lost.saturating_add(1);
+        } else {
+            self.slots[(self.head + self.len) % N] = slot;
+            self.len += 1;
+        }
+        true
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Edges overwritten since the last clear
+    pub fn lost(&self) -> u32 {
+        self.lost
+    }
+
+    /// Forget recorded edges (the paddle state is kept)
+    pub fn clear(&mut self) {
+        self.head = 0;
+        self.len = 0;
+        self.lost = 0;
+    }
+
+    /// Recorded edge, oldest first; times are modulo 2^30 ms
+    pub fn get(&self, index: usize) -> Option<TraceEvent> {
+        (index < self.len).then(|| TraceEvent::unpack(self.slots[(self.head + index) % N]))
+    }
+
+    /// Number of lines in a dump
+    pub fn line_count(&self) -> usize {
+        3 + self.len.div_ceil(EVENTS_PER_LINE)
+    }
+
+    /// Line `index` of the dump
+    pub fn line(&self, index: usize, config: &KeyerConfig) -> Option<TraceLine> {
+        let mut line = TraceLine::new();
+        let events = self.line_count() - 3;
+        // Fits: the longest header is well below TRACE_LINE_LEN
+        match index {
+            0 => {
+                write!(line, "KT{} ", TRACE_VERSION).ok();
+                cli::write_settings(config, &mut line).ok();
+            }
+            1 => {
+                let tie = match config.squeeze_tie {
+                    SqueezeTieRule::Dit => "DIT",
+                    SqueezeTieRule::Dah => "DAH",
+                    SqueezeTieRule::Alternate => "ALT",
+                };
+                let flag = |on: bool| if on { "ON" } else { "OFF" };
+                write!(
+                    line,
+                    "TIE={} SINGLE={} CHARSPACE={} DEBOUNCE={}",
+                    tie,
+                    flag(config.single_lever),
+                    flag(config.char_space_enabled),
+                    config.debounce_ms,
+                )
+                .ok();
+            }
+            _ if index <= events + 1 => {
+                let first = (index - 2) * EVENTS_PER_LINE;
+                for i in first..self.len.min(first + EVENTS_PER_LINE) {
+                    let event = self.get(i)?;
+                    let delta = match i.checked_sub(1).and_then(|previous| self.get(previous)) {
+                        Some(previous) => event.at_ms.wrapping_sub(previous.at_ms) & TIME_MASK,
+                        None => 0,
+                    };
+                    let separator = if i == first { "" } else { " " };
+                    write!(line, "{}{}{}", separator, delta, event.code()).ok();
+                }
+            }
+            _ if index == events + 2 => {
+                write!(line, "END {} LOST={}", self.len, self.lost).ok();
+            }
+            _ => return None,
+        }
+        Some(line)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum ReaderState {
+    Header,
+    /// After the header, until the first edge
+    Settings,
+    Events,
+    Done,
+}
+
+/// Reads a trace dump line by line
+#[derive(Clone, Debug)]
+pub struct TraceReader {
+    config: KeyerConfig,
+    state: ReaderState,
+    at_ms: u32,
+    count: usize,
+    lost: u32,
+}
+
+impl TraceReader {
+    /// Settings missing from the header keep the values in `defaults`
+    pub fn new(defaults: KeyerConfig) -> Self {
+        Self { config: defaults, state: ReaderState::Header, at_ms: 0, count: 0, lost: 0 }
+    }
+
+    /// Settings from the header
+    pub fn config(&self) -> &KeyerConfig {
+        &self.config
+    }
+
+    /// Edges lost to the recorder's ring buffer before the first one in the trace
+    pub fn lost(&self) -> u32 {
+        self.lost
+    }
+
+    /// Returns true once the `END` line was read
+    pub fn is_complete(&self) -> bool {
+        self.state == ReaderState::Done
+    }
+
+    /// Read one line; edges are passed to `edge`
+    ///
+    /// Blank lines are skipped, as is anything after `END`.
+    pub fn read_line(&mut self, line: &str, mut edge: impl FnMut(TraceEvent)) -> Result<(), TraceError> {
+        let line = line.trim();
+        if line.is_empty() || self.state == ReaderState::Done {
+            return Ok(());
+        }
+        let mut words = line.split_ascii_whitespace();
+        let first = words.next().unwrap_or("");
+
+        match self.state {
+            ReaderState::Header => {
+                let version = first.strip_prefix("KT").ok_or(TraceError::MissingHeader)?;
+                let version = version.parse().map_err(|_| TraceError::MissingHeader)?;
+                if version != TRACE_VERSION {
+                    return Err(TraceError::UnsupportedVersion(version));
+                }
+                words.try_for_each(|setting| self.apply(setting))?;
+                self.state = ReaderState::Settings;
+            }
+            ReaderState::Settings if first.contains('=') => {
+                line.split_ascii_whitespace().try_for_each(|setting| self.apply(setting))?;
+            }
+            ReaderState::Settings | ReaderState::Events if first == "END" => {
+                let count: usize = words.next().and_then(|count| count.parse().ok()).ok_or(TraceError::CountMismatch)?;
+                if count != self.count {
+                    return Err(TraceError::CountMismatch);
+                }
+                self.lost = words
+                    .next()
+                    .and_then(|lost| lost.strip_prefix("LOST="))
+                    .and_then(|lost| lost.parse().ok())
+                    .unwrap_or(0);
+                self.state = ReaderState::Done;
+            }
+            ReaderState::Settings | ReaderState::Events => {
+                self.state = ReaderState::Events;
+                for token in line.split_ascii_whitespace() {
+                    let split = token.len().checked_sub(1).ok_or(TraceError::InvalidEvent)?;
+                    let (delta, code) = token.split_at(split);
+                    let (side, pressed) = match code {
+                        "D" => (PaddleSide::Dit, true),
+                        "d" => (PaddleSide::Dit, false),
+                        "A" => (PaddleSide::Dah, true),
+                        "a" => (PaddleSide::Dah, false),
+                        _ => return Err(TraceError::InvalidEvent),
+                    };
+                    let delta: u32 = delta.parse().map_err(|_| TraceError::InvalidEvent)?;
+                    self.at_ms = if self.count == 0 { delta } else { self.at_ms.saturating_add(delta) };
+                    self.count += 1;
+                    edge(TraceEvent { at_ms: self.at_ms, side, pressed });
+                }
+            }
+            ReaderState::Done => {}
+        }
+        Ok(())
+    }
+
+    /// Apply one `KEY=VALUE` setting
+    fn apply(&mut self, setting: &str) -> Result<(), TraceError> {
+        let (key, value) = setting.split_once('=').ok_or(TraceError::InvalidSetting)?;
+        let config = &mut self.config;
+        match key {
+            "TIE" => {
+                config.squeeze_tie = match value {
+                    "DIT" => SqueezeTieRule::Dit,
+                    "DAH" => SqueezeTieRule::Dah,
+                    "ALT" => SqueezeTieRule::Alternate,
+                    _ => return Err(TraceError::InvalidSetting),
+                }
+            }
+            "SINGLE" => config.single_lever = cli::on_off(value).map_err(|_| TraceError::InvalidSetting)?,
+            "CHARSPACE" => config.char_space_enabled = cli::on_off(value).map_err(|_| TraceError::InvalidSetting)?,
+            "DEBOUNCE" => {
+                // Same limit as KeyerConfig::new
+                let debounce = value.parse().ok().filter(|&ms| ms <= 100);
+                config.debounce_ms = debounce.ok_or(TraceError::InvalidSetting)?;
+            }
+            _ => {
+                // `KEY=VALUE` is the command line `KEY VALUE`
+                let mut command: String<24> = String::new();
+                write!(command, "{} {}", key, value).map_err(|_| TraceError::InvalidSetting)?;
+                *config = cli::parse(command.as_bytes())
+                    .and_then(|command| cli::apply_setting(*config, command))
+                    .map_err(|_: CliError| TraceError::InvalidSetting)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::KeyerMode;
+
+    fn dump<const N: usize>(recorder: &TraceRecorder<N>, config: &KeyerConfig) -> [Option<TraceLine>; 5] {
+        core::array::from_fn(|index| recorder.line(index, config))
+    }
+
+    #[test]
+    fn test_record_and_read() {
+        let config = KeyerConfig { mode: KeyerMode::ModeB, ..KeyerConfig::default() }.with_wpm(25).unwrap();
+        let mut recorder = TraceRecorder::<16>::new();
+        recorder.record(PaddleSide::Dit, false, 900);  // Not an edge
+        for (side, pressed, at) in [(PaddleSide::Dit, true, 1000), (PaddleSide::Dah, true, 1012), (PaddleSide::Dit, false, 1052)] {
+            assert!(recorder.record(side, pressed, at));
+            recorder.record(side, pressed, at + 5);
+        }
+        assert_eq!(recorder.len(), 3);
+
+        let lines = dump(&recorder, &config);
+        assert_eq!(lines[0].as_deref(), Some("KT1 MODE=B WPM=25 WEIGHT=50 RATIO=30 FARNS=OFF SIDETONE=ON SWAP=OFF TX=ON"));
+        assert_eq!(lines[1].as_deref(), Some("TIE=DIT SINGLE=OFF CHARSPACE=ON DEBOUNCE=10"));
+        assert_eq!(lines[2].as_deref(), Some("0D 12A 40d"));
+        assert_eq!(lines[3].as_deref(), Some("END 3 LOST=0"));
+        assert_eq!(lines[4], None);
+
+        let mut reader = TraceReader::new(KeyerConfig::default());
+        let mut edges = heapless::Vec::<TraceEvent, 8>::new();
+        for line in lines.iter().flatten() {
+            reader.read_line(line, |edge| edges.push(edge).unwrap()).unwrap();
+        }
+        assert!(reader.is_complete());
+        assert_eq!((reader.config().mode, reader.config().wpm()), (KeyerMode::ModeB, 25));
+        assert_eq!(edges[2], TraceEvent { at_ms: 52, side: PaddleSide::Dit, pressed: false });
+
+        // Paddle handling settings round-trip too
+        let config = KeyerConfig {
+            squeeze_tie: SqueezeTieRule::Alternate,
+            single_lever: true,
+            char_space_enabled: false,
+            debounce_ms: 25,
+            ..config
+        };
+        let mut reader = TraceReader::new(KeyerConfig::default());
+        for line in dump(&recorder, &config).iter().flatten() {
+            reader.read_line(line, |_| {}).unwrap();
+        }
+        assert_eq!(*reader.config(), config);
+    }
+
+    #[test]
+    fn test_ring_buffer_overflow() {
+        let mut recorder = TraceRecorder::<4>::new();
+        for i in 0..6 {
+            recorder.record(PaddleSide::Dah, i % 2 == 0, 100 * i);
+        }
+        assert_eq!((recorder.len(), recorder.lost()), (4, 2));
+        assert_eq!(recorder.get(0), Some(TraceEvent { at_ms: 200, side: PaddleSide::Dah, pressed: true }));
+        assert_eq!(recorder.line(2, &KeyerConfig::default()).as_deref(), Some("0A 100a 100A 100a"));
+        assert_eq!(recorder.line(3, &KeyerConfig::default()).as_deref(), Some("END 4 LOST=2"));
+
+        // Deltas survive the timestamp wrap
+        recorder.clear();
+        recorder.record(PaddleSide::Dit, true, TIME_MASK - 5);
+        recorder.record(PaddleSide::Dit, false, TIME_MASK + 10);
+        assert_eq!(recorder.line(2, &KeyerConfig::default()).as_deref(), Some("0D 15d"));
+    }
+
+    #[test]
+    fn test_reader_errors() {
+        let read = |text: &str| {
+            let mut reader = TraceReader::new(KeyerConfig::default());
+            text.lines().try_for_each(|line| reader.read_line(line, |_| {}))?;
+            if reader.is_complete() { Ok(()) } else { Err(TraceError::Truncated) }
+        };
+        assert_eq!(read("0D 5d\nEND 2"), Err(TraceError::MissingHeader));
+        assert_eq!(read("KT2\nEND 0"), Err(TraceError::UnsupportedVersion(2)));
+        assert_eq!(read("KT1 WPM=500\nEND 0"), Err(TraceError::InvalidSetting));
+        assert_eq!(read("KT1\nDEBOUNCE=500\nEND 0"), Err(TraceError::InvalidSetting));
+        assert_eq!(read("KT1\n0D\nTIE=DAH\nEND 1"), Err(TraceError::InvalidEvent));
+        assert_eq!(read("KT1\n0D 5x\nEND 2"), Err(TraceError::InvalidEvent));
+        assert_eq!(read("KT1\n0D 5d\nEND 3"), Err(TraceError::CountMismatch));
+        assert_eq!(read("KT1\n0D 5d\n"), Err(TraceError::Truncated));
+        assert_eq!(read("KT1 SWAP=ON\n\n0D 5d\r\nEND 2 LOST=7\n"), Ok(()));
+    }
+}
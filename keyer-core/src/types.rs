@@ -12,32 +12,53 @@ pub enum Element {
     Dah,
     /// Character space (inter-character pause)
     CharSpace,
+    /// Word space (inter-word pause, 7 units total)
+    WordSpace,
+    /// Manual key-down, held until the matching KeyUp (open-ended)
+    KeyDown,
+    /// Manual key-up ending a KeyDown
+    KeyUp,
 }
 
 impl Element {
-    /// Returns the duration of this element in units
+    /// Returns the nominal duration of this element in units (3:1 ratio, no weighting)
+    ///
+    /// Senders use [`KeyerConfig::element_timing`] for the actual timing.
     pub const fn duration_units(&self) -> u32 {
         match self {
             Element::Dit => 1,
             Element::Dah => 3,
             Element::CharSpace => 3,
+            Element::WordSpace => 7,
+            // Manual keying follows the operator's hand, no fixed length
+            Element::KeyDown | Element::KeyUp => 0,
         }
     }
 
     /// Returns true if this element produces key output
     pub const fn is_keyed(&self) -> bool {
         match self {
-            Element::Dit | Element::Dah => true,
-            Element::CharSpace => false,
+            Element::Dit | Element::Dah | Element::KeyDown => true,
+            Element::CharSpace | Element::WordSpace | Element::KeyUp => false,
         }
     }
 
-    /// Returns the opposite element (Dit <-> Dah), CharSpace unchanged
+    /// Returns true for character and word space markers
+    pub const fn is_space(&self) -> bool {
+        matches!(self, Element::CharSpace | Element::WordSpace)
+    }
+
+    /// Returns true for open-ended manual keying elements
+    pub const fn is_manual(&self) -> bool {
+        matches!(self, Element::KeyDown | Element::KeyUp)
+    }
+
+    /// Returns the opposite element (Dit <-> Dah), other elements unchanged
     pub const fn opposite(&self) -> Element {
         match self {
             Element::Dit => Element::Dah,
             Element::Dah => Element::Dit,
-            Element::CharSpace => Element::CharSpace,
+            other => *other,
         }
     }
 }
@@ -51,13 +72,19 @@ pub enum KeyerMode {
     ModeB, 
     /// SuperKeyer: Dah priority with advanced memory
     SuperKeyer,
+    /// Ultimatic: last-pressed paddle wins and repeats during squeeze, no memory
+    Ultimatic,
+    /// Bug emulation: Dit paddle sends automatic dits, Dah paddle is a straight key
+    Bug,
+    /// Straight key passthrough: the Dit input (jack tip) keys directly, no timing
+    StraightKey,
 }
 
 impl KeyerMode {
     /// Returns true if this mode supports memory after squeeze release
     pub const fn has_memory(&self) -> bool {
         match self {
-            KeyerMode::ModeA => false,
+            KeyerMode::ModeA | KeyerMode::Ultimatic | KeyerMode::Bug | KeyerMode::StraightKey => false,
             KeyerMode::ModeB | KeyerMode::SuperKeyer => true,
         }
     }
@@ -65,12 +92,23 @@ impl KeyerMode {
     /// Returns true if this mode uses priority logic
     pub const fn has_priority(&self) -> bool {
         match self {
-            KeyerMode::ModeA | KeyerMode::ModeB => false,
-            KeyerMode::SuperKeyer => true,
+            KeyerMode::ModeA | KeyerMode::ModeB | KeyerMode::Bug | KeyerMode::StraightKey => false,
+            KeyerMode::SuperKeyer | KeyerMode::Ultimatic => true,
         }
     }
 }
 
+/// Squeeze start rule when both paddles are pressed at the same instant
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum SqueezeTieRule {
+    /// Start the squeeze with Dit
+    Dit,
+    /// Start the squeeze with Dah
+    Dah,
+    /// Start with the opposite of the last element sent
+    Alternate,
+}
+
 /// Paddle side identification
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub enum PaddleSide {
@@ -113,14 +151,21 @@ pub enum FSMState {
     MemoryPending(Element),
     /// Character space timing, waiting for next character
     CharSpacePending(Instant),
+    /// Character space sent, waiting to see if the gap becomes a word space
+    WordSpacePending(Instant),
+    /// Manual key-down in progress (Bug mode Dah lever or straight key)
+    KeyDown,
 }
 
 impl FSMState {
     /// Returns true if this state represents active paddle input
     pub const fn has_paddle_input(&self) -> bool {
         match self {
-            FSMState::Idle | FSMState::MemoryPending(_) | FSMState::CharSpacePending(_) => false,
-            FSMState::DitHold | FSMState::DahHold | FSMState::Squeeze(_) => true,
+            FSMState::Idle
+            | FSMState::MemoryPending(_)
+            | FSMState::CharSpacePending(_)
+            | FSMState::WordSpacePending(_) => false,
+            FSMState::DitHold | FSMState::DahHold | FSMState::Squeeze(_) | FSMState::KeyDown => true,
         }
     }
 
@@ -131,13 +176,14 @@ impl FSMState {
             FSMState::DahHold => Some(Element::Dah),
             FSMState::Squeeze(element) => Some(*element),
             FSMState::MemoryPending(element) => Some(*element),
-            FSMState::Idle | FSMState::CharSpacePending(_) => None,
+            FSMState::KeyDown => Some(Element::KeyDown),
+            FSMState::Idle | FSMState::CharSpacePending(_) | FSMState::WordSpacePending(_) => None,
         }
     }
 }
 
 /// Keyer configuration parameters
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, PartialEq, Debug)]
 pub struct KeyerConfig {
     /// Operating mode
     pub mode: KeyerMode,
@@ -149,6 +195,31 @@ pub struct KeyerConfig {
     pub debounce_ms: u64,
     /// Queue size for element buffer
     pub queue_size: usize,
+    /// Squeeze start element for simultaneous presses (Mode A/B)
+    pub squeeze_tie: SqueezeTieRule,
+    /// Drive the sidetone while the key is down
+    pub sidetone_enabled: bool,
+    /// Dah length in tenths of a unit (25-45, 30 = standard 3:1)
+    pub dah_ratio_tenths: u8,
+    /// Mark/space weighting in percent (10-90, 50 = neutral)
+    pub weighting: u8,
+    /// Farnsworth effective speed in WPM (slower than the character speed), None = off
+    pub farnsworth_wpm: Option<u32>,
+    /// Swap Dit and Dah paddles (left-handed operation)
+    pub paddle_swap: bool,
+    /// Single-lever paddle: no squeeze, the last lever pressed wins
+    pub single_lever: bool,
+    /// Key the transmitter; false = sidetone only (practice, command mode)
+    pub tx_enabled: bool,
+}
+
+/// Key-down time and following silence for one element
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct ElementTiming {
+    /// Key-down (mark) time
+    pub key_down: Duration,
+    /// Silence after key-up; for CharSpace/WordSpace the total gap counted from the previous key-up
+    pub space: Duration,
 }
 
 impl Default for KeyerConfig {
@@ -159,6 +230,14 @@ impl Default for KeyerConfig {
             unit: Duration::from_millis(60), // 20 WPM
             debounce_ms: 10,  // 10ms debounce for practical noise immunity
             queue_size: 64,
+            squeeze_tie: SqueezeTieRule::Dit,
+            sidetone_enabled: true,
+            dah_ratio_tenths: 30,
+            weighting: 50,
+            farnsworth_wpm: None,
+            paddle_swap: false,
+            single_lever: false,
+            tx_enabled: true,
         }
     }
 }
@@ -178,7 +257,7 @@ impl KeyerConfig {
         if debounce_ms > 100 {
             return Err("Debounce must be <= 100ms");
         }
-        if queue_size < 8 || queue_size > 1024 {
+        if !(8..=1024).contains(&queue_size) {
             return Err("Queue size must be between 8 and 1024");
         }
 
@@ -191,9 +270,53 @@ impl KeyerConfig {
             unit,
             debounce_ms,
             queue_size,
+            squeeze_tie: SqueezeTieRule::Dit,
+            sidetone_enabled: true,
+            dah_ratio_tenths: 30,
+            weighting: 50,
+            farnsworth_wpm: None,
+            paddle_swap: false,
+            single_lever: false,
+            tx_enabled: true,
         })
     }
 
+    /// Set dah:dit ratio (in tenths) and weighting with validation
+    pub fn with_timing(mut self, dah_ratio_tenths: u8, weighting: u8) -> Result<Self, &'static str> {
+        if !(25..=45).contains(&dah_ratio_tenths) {
+            return Err("Dah ratio must be between 2.5 and 4.5");
+        }
+        if !(10..=90).contains(&weighting) {
+            return Err("Weighting must be between 10 and 90");
+        }
+        self.dah_ratio_tenths = dah_ratio_tenths;
+        self.weighting = weighting;
+        Ok(self)
+    }
+
+    /// Set Farnsworth effective speed with validation (None disables it)
+    pub fn with_farnsworth(mut self, effective_wpm: Option<u32>) -> Result<Self, &'static str> {
+        if let Some(wpm) = effective_wpm {
+            if wpm == 0 || wpm >= self.wpm() {
+                return Err("Farnsworth speed must be below the character speed");
+            }
+        }
+        self.farnsworth_wpm = effective_wpm;
+        Ok(self)
+    }
+
+    /// Set character speed with validation
+    pub fn with_wpm(mut self, wpm: u32) -> Result<Self, &'static str> {
+        if wpm == 0 || wpm > 100 {
+            return Err("WPM must be between 1 and 100");
+        }
+        if self.farnsworth_wpm.is_some_and(|effective| effective >= wpm) {
+            return Err("Farnsworth speed must be below the character speed");
+        }
+        self.unit = Duration::from_millis(1200 / wpm as u64);
+        Ok(self)
+    }
+
     /// Get Words Per Minute from current unit timing
     pub fn wpm(&self) -> u32 {
         (1200 / self.unit.as_millis() as u32).max(1)
@@ -206,6 +329,50 @@ impl KeyerConfig {
 
     /// Get character space duration  
     pub fn char_space_duration(&self) -> Duration {
-        Duration::from_millis(self.unit.as_millis() * 3)
+        match self.farnsworth_delay_ms() {
+            Some(delay) => Duration::from_millis(delay * 3 / 19),
+            None => Duration::from_millis(self.unit.as_millis() * 3),
+        }
+    }
+
+    /// Get word space duration
+    pub fn word_space_duration(&self) -> Duration {
+        match self.farnsworth_delay_ms() {
+            Some(delay) => Duration::from_millis(delay * 7 / 19),
+            None => Duration::from_millis(self.unit.as_millis() * 7),
+        }
+    }
+
+    /// Total Farnsworth spacing per PARIS word in milliseconds (ARRL formula)
+    ///
+    /// PARIS has 31 units of elements and 19 units of character and word space;
+    /// only the 19 spacing units are stretched to reach the effective speed.
+    fn farnsworth_delay_ms(&self) -> Option<u64> {
+        let char_wpm = self.wpm() as u64;
+        let effective_wpm = self.farnsworth_wpm? as u64;
+        if effective_wpm == 0 || effective_wpm >= char_wpm {
+            return None;
+        }
+        Some((60_000 * char_wpm - 37_200 * effective_wpm) / (effective_wpm * char_wpm))
+    }
+
+    /// Get key-down and space timing for an element
+    ///
+    /// Weighting moves time from the gap to the mark, so a dit plus its gap
+    /// always lasts two units. Manual elements have no timing of their own.
+    pub fn element_timing(&self, element: Element) -> ElementTiming {
+        let unit = self.unit.as_millis() as i64;
+        let weight = (self.weighting.clamp(10, 90) as i64 - 50) * unit / 50;
+        let dah = unit * self.dah_ratio_tenths.clamp(25, 45) as i64 / 10;
+        let ms = |value: i64| Duration::from_millis(value as u64);
+
+        match element {
+            Element::Dit => ElementTiming { key_down: ms(unit + weight), space: ms(unit - weight) },
+            Element::Dah => ElementTiming { key_down: ms(dah + weight), space: ms(unit - weight) },
+            // Spaces are measured from the previous key-up, so a late marker adds no delay
+            Element::CharSpace => ElementTiming { key_down: ms(0), space: self.char_space_duration() },
+            Element::WordSpace => ElementTiming { key_down: ms(0), space: self.word_space_duration() },
+            Element::KeyDown | Element::KeyUp => ElementTiming { key_down: ms(0), space: ms(0) },
+        }
     }
 }
\ No newline at end of file
@@ -0,0 +1,958 @@
+//! K1EL WinKeyer2 host protocol emulation
+//!
+//! Lets logging programs (N1MM, Win-Test, ...) drive the keyer as if it were a
+//! WinKeyer2. Host bytes go through [`Parser`] into [`Command`]s, which
+//! [`WinKeyer`] carries out:
+//!
+//! - settings (speed, weighting, ratio, Farnsworth, mode register) are published
+//!   through [`SharedConfig`] and reach the FSM between elements
+//! - text and buffered commands (PTT, key down, wait, speed change, merged
+//!   letters) are queued and played into the element queue
+//! - paddle presses break in: the buffer is dropped and the FSM takes over
+//! - replies (revision, status, echo, values) are collected for the serial port
+//!
+//! Not emulated: EEPROM dump/load, calibration, firmware update and the
+//! buffer pointer commands, which are parsed and ignored.
+
+use heapless::spsc::Producer;
+use heapless::Deque;
+use crate::controller::PaddleInput;
+use crate::encoder::char_pattern;
+use crate::fsm::KeyerFSM;
+use crate::hal::{Duration, Instant};
+use crate::message::PLAYBACK_LOOKAHEAD;
+use crate::shared::SharedConfig;
+use crate::types::{Element, KeyerConfig, KeyerMode};
+
+/// Revision reported on host open (WK2 firmware 2.3)
+pub const WK2_REVISION: u8 = 23;
+/// Text/command buffer size, as on the WK2
+pub const BUFFER_LEN: usize = 128;
+/// Pending reply bytes
+pub const RESPONSE_LEN: usize = 32;
+/// Bytes in a "load defaults" / "get values" block
+pub const VALUES_LEN: usize = 15;
+
+/// Admin (0x00) subcommands
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Admin {
+    Calibrate,
+    Reset,
+    HostOpen,
+    HostClose,
+    /// Echo a byte back
+    Echo(u8),
+    PaddleA2d,
+    SpeedA2d,
+    GetValues,
+    GetCal,
+    Wk1Mode,
+    Wk2Mode,
+    DumpEeprom,
+    /// EEPROM image follows (skipped)
+    LoadEeprom,
+    StandaloneMessage(u8),
+    LoadX1Mode(u8),
+    SetLowBaud,
+    SetHighBaud,
+    /// Unknown or unsupported subcommand
+    Other(u8),
+}
+
+impl Admin {
+    /// Argument bytes after the subcommand
+    fn arg_len(sub: u8) -> usize {
+        match sub {
+            4 | 14 | 15 => 1,
+            _ => 0,
+        }
+    }
+
+    fn from_bytes(sub: u8, arg: u8) -> Self {
+        match sub {
+            0 => Admin::Calibrate,
+            1 => Admin::Reset,
+            2 => Admin::HostOpen,
+            3 => Admin::HostClose,
+            4 => Admin::Echo(arg),
+            5 => Admin::PaddleA2d,
+            6 => Admin::SpeedA2d,
+            7 => Admin::GetValues,
+            9 => Admin::GetCal,
+            10 => Admin::Wk1Mode,
+            11 => Admin::Wk2Mode,
+            12 => Admin::DumpEeprom,
+            13 => Admin::LoadEeprom,
+            14 => Admin::StandaloneMessage(arg),
+            15 => Admin::LoadX1Mode(arg),
+            17 => Admin::SetLowBaud,
+            18 => Admin::SetHighBaud,
+            _ => Admin::Other(sub),
+        }
+    }
+}
+
+/// Host command
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+    Admin(Admin),
+    /// Sidetone control register
+    Sidetone(u8),
+    /// Speed in WPM (0 = speed pot)
+    Speed(u8),
+    /// Weighting 10..=90 (50 = normal)
+    Weighting(u8),
+    /// PTT lead-in and tail in 10 ms units
+    PttLeadTail { lead: u8, tail: u8 },
+    SpeedPotSetup { min: u8, range: u8 },
+    Pause(bool),
+    GetSpeedPot,
+    Backspace,
+    PinConfig(u8),
+    ClearBuffer,
+    KeyImmediate(bool),
+    Hscw(u8),
+    /// Farnsworth speed in WPM (0 = off)
+    Farnsworth(u8),
+    /// WinKeyer2 mode register
+    Mode(u8),
+    LoadDefaults([u8; VALUES_LEN]),
+    FirstExtension(u8),
+    KeyCompensation(u8),
+    PaddleSwitchpoint(u8),
+    Null,
+    /// Software paddle (bit 0 = dah, bit 1 = dit)
+    SoftwarePaddle(u8),
+    Status,
+    /// Buffer pointer command and its argument
+    Pointer(u8, u8),
+    /// Dit/dah ratio 33..=66 (50 = 1:3)
+    DitDahRatio(u8),
+    // Buffered commands, carried out in order with the text
+    PttBuffered(bool),
+    /// Key down for some seconds
+    KeyBuffered(u8),
+    /// Pause sending for some seconds
+    Wait(u8),
+    /// Two letters sent as one character (prosign)
+    Merge(u8, u8),
+    BufferedSpeed(u8),
+    BufferedHscw(u8),
+    CancelBufferedSpeed,
+    BufferedNop,
+    /// Character to send
+    Text(u8),
+}
+
+/// Byte-stream parser for host commands
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Parser {
+    opcode: Option<u8>,
+    args: [u8; VALUES_LEN],
+    have: usize,
+    /// Bytes still to be ignored (EEPROM image)
+    skip: usize,
+}
+
+impl Parser {
+    /// Create parser waiting for a command byte
+    pub const fn new() -> Self {
+        Self {
+            opcode: None,
+            args: [0; VALUES_LEN],
+            have: 0,
+            skip: 0,
+        }
+    }
+
+    /// Argument bytes needed so far for `opcode`
+    fn arg_len(opcode: u8, args: &[u8]) -> usize {
+        match opcode {
+            0x00 => 1 + args.first().map_or(0, |&sub| Admin::arg_len(sub)),
+            0x04 | 0x1B => 2,
+            0x05 => 3,
+            0x0F => VALUES_LEN,
+            // Pointer commands other than reset take one argument
+            0x16 => 1 + usize::from(args.first().is_some_and(|&sub| sub != 0)),
+            0x07 | 0x08 | 0x0A | 0x13 | 0x15 | 0x1E | 0x1F => 0,
+            0x01..=0x1D => 1,
+            _ => 0,
+        }
+    }
+
+    /// Feed one byte; returns a command once it is complete
+    pub fn push(&mut self, byte: u8) -> Option<Command> {
+        if self.skip > 0 {
+            self.skip -= 1;
+            return None;
+        }
+        let opcode = match self.opcode {
+            Some(opcode) => {
+                self.args[self.have] = byte;
+                self.have += 1;
+                opcode
+            }
+            None => byte,
+        };
+        if self.have < Self::arg_len(opcode, &self.args[..self.have]) {
+            self.opcode = Some(opcode);
+            return None;
+        }
+
+        let a = core::mem::take(&mut self.args);
+        self.opcode = None;
+        self.have = 0;
+        Some(match opcode {
+            0x00 => {
+                if a[0] == 13 {
+                    self.skip = 256;
+                }
+                Command::Admin(Admin::from_bytes(a[0], a[1]))
+            }
+            0x01 => Command::Sidetone(a[0]),
+            0x02 => Command::Speed(a[0]),
+            0x03 => Command::Weighting(a[0]),
+            0x04 => Command::PttLeadTail { lead: a[0], tail: a[1] },
+            0x05 => Command::SpeedPotSetup { min: a[0], range: a[1] },
+            0x06 => Command::Pause(a[0] != 0),
+            0x07 => Command::GetSpeedPot,
+            0x08 => Command::Backspace,
+            0x09 => Command::PinConfig(a[0]),
+            0x0A => Command::ClearBuffer,
+            0x0B => Command::KeyImmediate(a[0] != 0),
+            0x0C => Command::Hscw(a[0]),
+            0x0D => Command::Farnsworth(a[0]),
+            0x0E => Command::Mode(a[0]),
+            0x0F => Command::LoadDefaults(a),
+            0x10 => Command::FirstExtension(a[0]),
+            0x11 => Command::KeyCompensation(a[0]),
+            0x12 => Command::PaddleSwitchpoint(a[0]),
+            0x13 => Command::Null,
+            0x14 => Command::SoftwarePaddle(a[0]),
+            0x15 => Command::Status,
+            0x16 => Command::Pointer(a[0], a[1]),
+            0x17 => Command::DitDahRatio(a[0]),
+            0x18 => Command::PttBuffered(a[0] != 0),
+            0x19 => Command::KeyBuffered(a[0]),
+            0x1A => Command::Wait(a[0]),
+            0x1B => Command::Merge(a[0], a[1]),
+            0x1C => Command::BufferedSpeed(a[0]),
+            0x1D => Command::BufferedHscw(a[0]),
+            0x1E => Command::CancelBufferedSpeed,
+            0x1F => Command::BufferedNop,
+            // Text is 7-bit ASCII; anything above is ignored like a null
+            0x20..=0x7F => Command::Text(opcode),
+            _ => Command::Null,
+        })
+    }
+}
+
+// Mode register bits
+const MODE_PADDLE_WATCHDOG_OFF: u8 = 0x80;
+const MODE_PADDLE_ECHO: u8 = 0x40;
+const MODE_KEY_MASK: u8 = 0x30;
+const MODE_IAMBIC_A: u8 = 0x10;
+const MODE_ULTIMATIC: u8 = 0x20;
+const MODE_BUG: u8 = 0x30;
+const MODE_PADDLE_SWAP: u8 = 0x08;
+const MODE_SERIAL_ECHO: u8 = 0x04;
+const MODE_AUTOSPACE: u8 = 0x02;
+const MODE_CONTEST_SPACING: u8 = 0x01;
+
+// Status byte bits
+const STATUS: u8 = 0xC0;
+const STATUS_WAIT: u8 = 0x10;
+const STATUS_KEYDOWN: u8 = 0x08;
+const STATUS_BUSY: u8 = 0x04;
+const STATUS_BREAKIN: u8 = 0x02;
+const STATUS_XOFF: u8 = 0x01;
+
+/// Speed pot reply marker
+const SPEED_POT: u8 = 0x80;
+
+/// Pin configuration: sidetone enable
+const PIN_SIDETONE: u8 = 0x02;
+
+/// Registers kept for the host but not used by the keyer itself
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct Registers {
+    /// Mode register bits not held in `KeyerConfig`
+    mode_flags: u8,
+    sidetone: u8,
+    lead: u8,
+    tail: u8,
+    pot_min: u8,
+    pot_range: u8,
+    first_extension: u8,
+    key_compensation: u8,
+    switchpoint: u8,
+    pin_config: u8,
+}
+
+impl Registers {
+    /// Power-on register values
+    const DEFAULT: Self = Self {
+        mode_flags: MODE_SERIAL_ECHO,
+        sidetone: 5,
+        lead: 0,
+        tail: 0,
+        pot_min: 5,
+        pot_range: 30,
+        first_extension: 0,
+        key_compensation: 0,
+        switchpoint: 50,
+        pin_config: 0x05 | PIN_SIDETONE,
+    };
+}
+
+/// Entry in the send buffer
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Buffered {
+    Char(u8),
+    Merge(u8, u8),
+    Ptt(bool),
+    Key(u8),
+    Wait(u8),
+    Speed(u8),
+    CancelSpeed,
+    Nop,
+}
+
+/// Timed buffered command in progress
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Hold {
+    None,
+    /// `KeyDown` queued; timing starts once the sender has taken it
+    KeyQueued(Duration),
+    Keyed { since: Instant, length: Duration },
+    Waiting { since: Instant, length: Duration },
+}
+
+/// WinKeyer2 emulation
+pub struct WinKeyer {
+    parser: Parser,
+    open: bool,
+    paused: bool,
+    registers: Registers,
+    buffer: Deque<Buffered, BUFFER_LEN>,
+    responses: Deque<u8, RESPONSE_LEN>,
+    /// Remaining elements of the current character, and a merged second letter
+    pattern: &'static [u8],
+    merged: &'static [u8],
+    after_char: bool,
+    hold: Hold,
+    /// Key immediate request not yet queued
+    key_request: Option<bool>,
+    key_down: bool,
+    ptt: bool,
+    break_in: bool,
+    software_paddle: u8,
+    /// Speed to restore after a buffered speed change
+    saved_wpm: Option<u32>,
+    /// Status last reported to the host
+    reported: u8,
+    status: u8,
+    seen: u32,
+    /// Settings restored by the reset command
+    initial: Option<KeyerConfig>,
+}
+
+impl Default for WinKeyer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WinKeyer {
+    /// Create emulation; the host has to open it before it replies
+    pub const fn new() -> Self {
+        Self {
+            parser: Parser::new(),
+            open: false,
+            paused: false,
+            registers: Registers::DEFAULT,
+            buffer: Deque::new(),
+            responses: Deque::new(),
+            pattern: &[],
+            merged: &[],
+            after_char: false,
+            hold: Hold::None,
+            key_request: None,
+            key_down: false,
+            ptt: false,
+            break_in: false,
+            software_paddle: 0,
+            saved_wpm: None,
+            reported: STATUS,
+            status: STATUS,
+            seen: 0,
+            initial: None,
+        }
+    }
+
+    /// Returns true while a host has the keyer open
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    /// Returns true while buffered text or commands remain
+    pub fn is_busy(&self) -> bool {
+        self.status & STATUS_BUSY != 0
+    }
+
+    /// PTT requested by the host (buffered PTT command)
+    pub fn ptt(&self) -> bool {
+        self.ptt
+    }
+
+    /// PTT lead-in and tail times
+    pub fn ptt_lead_tail(&self) -> (Duration, Duration) {
+        (
+            Duration::from_millis(u64::from(self.registers.lead) * 10),
+            Duration::from_millis(u64::from(self.registers.tail) * 10),
+        )
+    }
+
+    /// Software paddle state from the host (dit, dah)
+    pub fn software_paddle(&self) -> (bool, bool) {
+        (self.software_paddle & 0x02 != 0, self.software_paddle & 0x01 != 0)
+    }
+
+    /// Current status byte
+    pub fn status(&self) -> u8 {
+        self.status
+    }
+
+    /// Next byte to send to the host
+    pub fn next_response(&mut self) -> Option<u8> {
+        self.responses.pop_front()
+    }
+
+    fn respond(&mut self, byte: u8) {
+        // A host that stops reading loses replies rather than stalling the keyer
+        self.responses.push_back(byte).ok();
+    }
+
+    /// Handle bytes received from the host
+    pub fn receive(&mut self, bytes: &[u8], config: &SharedConfig) {
+        for &byte in bytes {
+            if let Some(command) = self.parser.push(byte) {
+                self.execute(command, config);
+            }
+        }
+    }
+
+    fn queue(&mut self, item: Buffered) {
+        // The host watches XOFF; bytes beyond a full buffer are lost as on the WK2
+        self.buffer.push_back(item).ok();
+    }
+
+    /// Carry out one command
+    pub fn execute(&mut self, command: Command, config: &SharedConfig) {
+        if self.initial.is_none() {
+            self.initial = config.get();
+        }
+        match command {
+            Command::Admin(admin) => self.admin(admin, config),
+            Command::Sidetone(value) => self.registers.sidetone = value,
+            Command::Speed(wpm) => {
+                // 0 hands the speed to the pot, which keeps the current speed here
+                if wpm != 0 {
+                    set_wpm(config, wpm);
+                }
+            }
+            Command::Weighting(weighting) => config.update(|c| {
+                if let Ok(changed) = c.with_timing(c.dah_ratio_tenths, weighting.clamp(10, 90)) {
+                    *c = changed;
+                }
+            }),
+            Command::PttLeadTail { lead, tail } => {
+                self.registers.lead = lead;
+                self.registers.tail = tail;
+            }
+            Command::SpeedPotSetup { min, range } => {
+                self.registers.pot_min = min;
+                self.registers.pot_range = range;
+            }
+            Command::Pause(paused) => self.paused = paused,
+            Command::GetSpeedPot => self.respond(SPEED_POT),
+            Command::Backspace => {
+                self.buffer.pop_back();
+            }
+            Command::PinConfig(value) => {
+                self.registers.pin_config = value;
+                config.update(|c| c.sidetone_enabled = value & PIN_SIDETONE != 0);
+            }
+            Command::ClearBuffer => self.clear(config),
+            Command::KeyImmediate(down) => self.key_request = Some(down),
+            Command::Farnsworth(wpm) => config.update(|c| {
+                let effective = Some(u32::from(wpm)).filter(|&wpm| wpm != 0);
+                // Too fast for the character speed: Farnsworth off
+                *c = c.with_farnsworth(effective).unwrap_or(KeyerConfig { farnsworth_wpm: None, ..*c });
+            }),
+            Command::Mode(mode) => self.set_mode(mode, config),
+            Command::LoadDefaults(values) => self.load_values(&values, config),
+            Command::FirstExtension(value) => self.registers.first_extension = value,
+            Command::KeyCompensation(value) => self.registers.key_compensation = value,
+            Command::PaddleSwitchpoint(value) => self.registers.switchpoint = value,
+            Command::SoftwarePaddle(value) => self.software_paddle = value,
+            Command::Status => {
+                let status = self.status;
+                self.respond(status);
+            }
+            Command::DitDahRatio(ratio) => config.update(|c| {
+                // 50 = 1:3; the keyer runs 2.5..=4.5
+                let tenths = (u32::from(ratio.clamp(33, 66)) * 3 / 5).clamp(25, 45) as u8;
+                if let Ok(changed) = c.with_timing(tenths, c.weighting) {
+                    *c = changed;
+                }
+            }),
+            Command::PttBuffered(on) => self.queue(Buffered::Ptt(on)),
+            Command::KeyBuffered(seconds) => self.queue(Buffered::Key(seconds)),
+            Command::Wait(seconds) => self.queue(Buffered::Wait(seconds)),
+            Command::Merge(first, second) => self.queue(Buffered::Merge(first, second)),
+            Command::BufferedSpeed(wpm) => self.queue(Buffered::Speed(wpm)),
+            Command::CancelBufferedSpeed => self.queue(Buffered::CancelSpeed),
+            Command::BufferedNop | Command::BufferedHscw(_) => self.queue(Buffered::Nop),
+            Command::Text(byte) => self.queue(Buffered::Char(byte)),
+            Command::Hscw(_) | Command::Null | Command::Pointer(..) => {}
+        }
+    }
+
+    fn admin(&mut self, admin: Admin, config: &SharedConfig) {
+        match admin {
+            Admin::HostOpen => {
+                self.open = true;
+                self.clear(config);
+                self.respond(WK2_REVISION);
+            }
+            Admin::HostClose => {
+                self.open = false;
+                self.clear(config);
+            }
+            Admin::Reset => {
+                self.clear(config);
+                self.registers = Registers::DEFAULT;
+                self.paused = false;
+                if let Some(initial) = self.initial {
+                    config.set(initial);
+                }
+            }
+            Admin::Echo(byte) => self.respond(byte),
+            Admin::PaddleA2d | Admin::SpeedA2d | Admin::GetCal => self.respond(0),
+            Admin::GetValues => {
+                for byte in self.values(config) {
+                    self.respond(byte);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Drop buffered text and commands, releasing the key
+    fn clear(&mut self, config: &SharedConfig) {
+        self.buffer.clear();
+        self.pattern = &[];
+        self.merged = &[];
+        self.after_char = false;
+        if matches!(self.hold, Hold::KeyQueued(_) | Hold::Keyed { .. }) || self.key_down {
+            self.key_request = Some(false);
+        }
+        self.hold = Hold::None;
+        self.ptt = false;
+        if let Some(wpm) = self.saved_wpm.take() {
+            set_wpm(config, wpm as u8);
+        }
+    }
+
+    fn set_mode(&mut self, mode: u8, config: &SharedConfig) {
+        self.registers.mode_flags = mode & (MODE_PADDLE_WATCHDOG_OFF | MODE_PADDLE_ECHO | MODE_SERIAL_ECHO | MODE_CONTEST_SPACING);
+        config.update(|c| {
+            c.mode = match mode & MODE_KEY_MASK {
+                MODE_IAMBIC_A => KeyerMode::ModeA,
+                MODE_ULTIMATIC => KeyerMode::Ultimatic,
+                MODE_BUG => KeyerMode::Bug,
+                _ => KeyerMode::ModeB,
+            };
+            c.paddle_swap = mode & MODE_PADDLE_SWAP != 0;
+            c.char_space_enabled = mode & MODE_AUTOSPACE != 0;
+        });
+    }
+
+    fn mode_register(&self, config: &KeyerConfig) -> u8 {
+        let key_mode = match config.mode {
+            KeyerMode::ModeA => MODE_IAMBIC_A,
+            KeyerMode::Ultimatic => MODE_ULTIMATIC,
+            KeyerMode::Bug | KeyerMode::StraightKey => MODE_BUG,
+            KeyerMode::ModeB | KeyerMode::SuperKeyer => 0,
+        };
+        let mut mode = self.registers.mode_flags | key_mode;
+        if config.paddle_swap {
+            mode |= MODE_PADDLE_SWAP;
+        }
+        if config.char_space_enabled {
+            mode |= MODE_AUTOSPACE;
+        }
+        mode
+    }
+
+    /// Settings block in "load defaults" order
+    fn values(&self, config: &SharedConfig) -> [u8; VALUES_LEN] {
+        let c = config.get().unwrap_or_default();
+        let r = &self.registers;
+        [
+            self.mode_register(&c),
+            c.wpm().min(99) as u8,
+            r.sidetone,
+            c.weighting,
+            r.lead,
+            r.tail,
+            r.pot_min,
+            r.pot_range,
+            r.first_extension,
+            r.key_compensation,
+            c.farnsworth_wpm.map_or(0, |wpm| wpm.min(99) as u8),
+            r.switchpoint,
+            (u32::from(c.dah_ratio_tenths) * 5 / 3) as u8,
+            r.pin_config,
+            0,
+        ]
+    }
+
+    fn load_values(&mut self, values: &[u8; VALUES_LEN], config: &SharedConfig) {
+        self.set_mode(values[0], config);
+        self.execute(Command::Speed(values[1]), config);
+        self.registers.sidetone = values[2];
+        self.execute(Command::Weighting(values[3]), config);
+        self.registers.lead = values[4];
+        self.registers.tail = values[5];
+        self.registers.pot_min = values[6];
+        self.registers.pot_range = values[7];
+        self.registers.first_extension = values[8];
+        self.registers.key_compensation = values[9];
+        self.execute(Command::Farnsworth(values[10]), config);
+        self.registers.switchpoint = values[11];
+        self.execute(Command::DitDahRatio(values[12]), config);
+        self.execute(Command::PinConfig(values[13]), config);
+    }
+
+    /// Run one keyer step: host output when there is any, the paddles otherwise
+    ///
+    /// Replaces a plain `fsm.update_at` call. A paddle press drops the buffer
+    /// (break-in) and keys through the FSM.
+    pub fn update<const N: usize>(
+        &mut self,
+        now: Instant,
+        fsm: &mut KeyerFSM,
+        paddle: &PaddleInput,
+        config: &SharedConfig,
+        queue: &mut Producer<'_, Element, N>,
+    ) {
+        // Settings reach the FSM between elements
+        if !fsm.element_in_flight() {
+            if let Some(changed) = config.changed_since(&mut self.seen) {
+                fsm.set_config(changed);
+            }
+        }
+
+        if let Some(down) = self.key_request {
+            let element = if down { Element::KeyDown } else { Element::KeyUp };
+            if queue.enqueue(element).is_ok() {
+                self.key_request = None;
+                self.key_down = down;
+            }
+        }
+
+        self.break_in = paddle.dit() || paddle.dah();
+        if self.break_in && self.has_output() {
+            self.clear(config);
+        }
+        if self.break_in || fsm.element_in_flight() || !self.has_output() {
+            fsm.update_at(now, paddle, queue);
+        } else if !self.paused {
+            self.pump(now, config, queue);
+        }
+
+        self.status = self.compute_status(queue.len());
+        if self.open && self.status != self.reported {
+            self.reported = self.status;
+            let status = self.status;
+            self.respond(status);
+        }
+    }
+
+    fn has_output(&self) -> bool {
+        !self.buffer.is_empty() || !self.pattern.is_empty() || !self.merged.is_empty() || self.hold != Hold::None
+    }
+
+    fn compute_status(&self, queued: usize) -> u8 {
+        let mut status = STATUS;
+        if matches!(self.hold, Hold::Waiting { .. }) {
+            status |= STATUS_WAIT;
+        }
+        if self.key_down || matches!(self.hold, Hold::Keyed { .. }) {
+            status |= STATUS_KEYDOWN;
+        }
+        if self.has_output() || queued > 0 {
+            status |= STATUS_BUSY;
+        }
+        if self.break_in {
+            status |= STATUS_BREAKIN;
+        }
+        if self.buffer.len() >= BUFFER_LEN * 2 / 3 {
+            status |= STATUS_XOFF;
+        }
+        status
+    }
+
+    /// Move buffered text and commands into the element queue
+    fn pump<const N: usize>(&mut self, now: Instant, config: &SharedConfig, queue: &mut Producer<'_, Element, N>) {
+        while queue.ready() && queue.len() < PLAYBACK_LOOKAHEAD {
+            if let Some((&symbol, rest)) = self.pattern.split_first() {
+                self.pattern = rest;
+                queue.enqueue(if symbol == b'.' { Element::Dit } else { Element::Dah }).ok();
+                continue;
+            }
+            if !self.merged.is_empty() {
+                self.pattern = core::mem::take(&mut self.merged);
+                continue;
+            }
+
+            // Timed commands start once everything before them has been sent
+            let drained = queue.len() == 0;
+            match self.hold {
+                Hold::None => {}
+                Hold::KeyQueued(length) if drained => {
+                    self.hold = Hold::Keyed { since: now, length };
+                    return;
+                }
+                Hold::Keyed { since, length } if now.duration_since(since) >= length => {
+                    if queue.enqueue(Element::KeyUp).is_ok() {
+                        self.hold = Hold::None;
+                    }
+                    return;
+                }
+                Hold::Waiting { since, length } if now.duration_since(since) >= length => self.hold = Hold::None,
+                _ => return,
+            }
+
+            let Some(&item) = self.buffer.front() else {
+                return;
+            };
+            let needs_drain = !matches!(item, Buffered::Char(_) | Buffered::Merge(..));
+            if needs_drain && !drained {
+                return;
+            }
+            self.buffer.pop_front();
+            match item {
+                Buffered::Char(b' ') => {
+                    // Runs of spaces collapse into one word space
+                    if self.after_char {
+                        self.after_char = false;
+                        queue.enqueue(Element::WordSpace).ok();
+                    }
+                }
+                Buffered::Char(byte) => self.start_char(byte, None, queue),
+                Buffered::Merge(first, second) => self.start_char(first, Some(second), queue),
+                Buffered::Ptt(on) => self.ptt = on,
+                Buffered::Key(seconds) => {
+                    queue.enqueue(Element::KeyDown).ok();
+                    self.hold = Hold::KeyQueued(seconds_duration(seconds));
+                    return;
+                }
+                Buffered::Wait(seconds) => {
+                    self.hold = Hold::Waiting { since: now, length: seconds_duration(seconds) };
+                    return;
+                }
+                Buffered::Speed(wpm) => {
+                    let current = config.get().map(|c| c.wpm());
+                    self.saved_wpm = self.saved_wpm.or(current);
+                    set_wpm(config, wpm);
+                }
+                Buffered::CancelSpeed => {
+                    if let Some(wpm) = self.saved_wpm.take() {
+                        set_wpm(config, wpm as u8);
+                    }
+                }
+                Buffered::Nop => {}
+            }
+        }
+    }
+
+    /// Begin a character (or two merged letters); unknown characters are skipped
+    fn start_char<const N: usize>(&mut self, byte: u8, second: Option<u8>, queue: &mut Producer<'_, Element, N>) {
+        let Some(pattern) = char_pattern(byte as char) else {
+            return;
+        };
+        let merged = second.and_then(|second| char_pattern(second as char)).unwrap_or("");
+        if self.after_char {
+            queue.enqueue(Element::CharSpace).ok();
+        }
+        self.after_char = true;
+        self.pattern = pattern.as_bytes();
+        self.merged = merged.as_bytes();
+
+        if self.open && self.registers.mode_flags & MODE_SERIAL_ECHO != 0 {
+            self.respond(byte);
+            if let Some(second) = second {
+                self.respond(second);
+            }
+        }
+    }
+}
+
+fn seconds_duration(seconds: u8) -> Duration {
+    Duration::from_millis(u64::from(seconds) * 1000)
+}
+
+/// Publish a speed change (WK2 range 5..=99 WPM)
+fn set_wpm(config: &SharedConfig, wpm: u8) {
+    let wpm = u32::from(wpm.clamp(5, 99));
+    config.update(|c| {
+        let changed = c.with_wpm(wpm).or_else(|_| c.with_farnsworth(None).and_then(|c| c.with_wpm(wpm)));
+        if let Ok(changed) = changed {
+            *c = changed;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use heapless::spsc::Queue;
+    use heapless::Vec;
+
+    /// Run the emulation with a sender that takes one element per millisecond
+    fn run(wk: &mut WinKeyer, config: &SharedConfig, from_ms: i64, to_ms: i64) -> Vec<Element, 64> {
+        let paddle = PaddleInput::new();
+        let mut fsm = KeyerFSM::new(config.get().unwrap());
+        let mut queue = Queue::<Element, 8>::new();
+        let (mut producer, mut consumer) = queue.split();
+        let mut sent = Vec::new();
+        for ms in from_ms..to_ms {
+            wk.update(Instant::from_millis(ms as _), &mut fsm, &paddle, config, &mut producer);
+            if let Some(element) = consumer.dequeue() {
+                sent.push(element).unwrap();
+            }
+        }
+        sent
+    }
+
+    fn responses(wk: &mut WinKeyer) -> Vec<u8, RESPONSE_LEN> {
+        core::iter::from_fn(|| wk.next_response()).collect()
+    }
+
+    #[test]
+    fn test_parser_stream() {
+        // Host open, echo, load defaults, EEPROM load (skipped), pointer, merge, text
+        let mut stream: Vec<u8, 320> = Vec::new();
+        stream.extend_from_slice(&[0x00, 0x02, 0x00, 0x04, 0x55]).unwrap();
+        stream.extend_from_slice(&[0x0F, 0x54, 25, 5, 50, 1, 2, 10, 25, 0, 0, 0, 50, 50, 0x06, 0]).unwrap();
+        stream.extend_from_slice(&[0x00, 13]).unwrap();
+        for _ in 0..256 {
+            stream.push(0x41).unwrap();
+        }
+        stream.extend_from_slice(&[0x16, 0x00, 0x16, 0x01, 0x05, 0x1B, b'A', b'R', b'K', 0x15]).unwrap();
+
+        let mut parser = Parser::new();
+        let commands: Vec<Command, 16> = stream.iter().filter_map(|&b| parser.push(b)).collect();
+        assert_eq!(
+            commands.as_slice(),
+            &[
+                Command::Admin(Admin::HostOpen),
+                Command::Admin(Admin::Echo(0x55)),
+                Command::LoadDefaults([0x54, 25, 5, 50, 1, 2, 10, 25, 0, 0, 0, 50, 50, 0x06, 0]),
+                Command::Admin(Admin::LoadEeprom),
+                Command::Pointer(0, 0),
+                Command::Pointer(1, 5),
+                Command::Merge(b'A', b'R'),
+                Command::Text(b'K'),
+                Command::Status,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_host_session() {
+        // Recorded from a logger: open, mode (iambic A, echo, autospace), speed, ratio, status
+        let config = SharedConfig::new();
+        config.set(KeyerConfig::default());
+        let mut wk = WinKeyer::new();
+        wk.receive(&[0x00, 0x02, 0x0E, 0x16, 0x02, 28, 0x03, 60, 0x17, 50, 0x15], &config);
+        assert!(wk.is_open());
+        assert_eq!(responses(&mut wk).as_slice(), &[WK2_REVISION, STATUS]);
+
+        let c = config.get().unwrap();
+        assert_eq!((c.mode, c.wpm(), c.weighting, c.dah_ratio_tenths), (KeyerMode::ModeA, 28, 60, 30));
+        assert!(c.char_space_enabled && !c.paddle_swap);
+
+        // Values come back in load-defaults order
+        wk.receive(&[0x00, 0x07], &config);
+        let values = responses(&mut wk);
+        assert_eq!(&values[..4], &[0x16, 28, 5, 60]);
+        assert_eq!(values[12], 50);
+
+        // Out-of-range speeds are clamped; 0 leaves the speed to the pot
+        wk.receive(&[0x02, 0, 0x07], &config);
+        assert_eq!(config.get().unwrap().wpm(), 28);
+        assert_eq!(responses(&mut wk).as_slice(), &[SPEED_POT]);
+
+        wk.receive(&[0x00, 0x03], &config);
+        assert!(!wk.is_open());
+    }
+
+    #[test]
+    fn test_buffered_text_and_echo() {
+        let config = SharedConfig::new();
+        config.set(KeyerConfig::default());
+        let mut wk = WinKeyer::new();
+        wk.receive(&[0x00, 0x02, 0x0E, 0x04], &config);
+        responses(&mut wk);
+
+        // "E  T", a prosign and a buffered speed change that is cancelled again
+        wk.receive(b"E  T", &config);
+        wk.receive(&[0x1C, 30, 0x1B, b'A', b'R', 0x1E], &config);
+        let sent = run(&mut wk, &config, 0, 100);
+        assert_eq!(
+            sent.as_slice(),
+            &[
+                Element::Dit, Element::WordSpace, Element::Dah, Element::CharSpace,
+                Element::Dit, Element::Dah, Element::Dit, Element::Dah, Element::Dit,
+            ]
+        );
+        assert_eq!(config.get().unwrap().wpm(), 20);
+
+        // Echo as characters start, then busy → idle status changes
+        let replies = responses(&mut wk);
+        let echoed: Vec<u8, 8> = replies.iter().copied().filter(|b| b & STATUS != STATUS).collect();
+        assert_eq!(echoed.as_slice(), b"ETAR");
+        assert_eq!(replies.last(), Some(&STATUS));
+        assert!(replies.contains(&(STATUS | STATUS_BUSY)));
+        assert!(!wk.is_busy());
+    }
+
+    #[test]
+    fn test_key_wait_and_clear() {
+        let config = SharedConfig::new();
+        config.set(KeyerConfig::default());
+        let mut wk = WinKeyer::new();
+
+        // Key down 1 s, wait 1 s, then "E"
+        wk.receive(&[0x19, 1, 0x1A, 1, b'E'], &config);
+        let sent = run(&mut wk, &config, 0, 1500);
+        assert_eq!(sent.as_slice(), &[Element::KeyDown, Element::KeyUp]);
+        assert_eq!(wk.status() & STATUS_WAIT, STATUS_WAIT);
+        let sent = run(&mut wk, &config, 1500, 2200);
+        assert_eq!(sent.as_slice(), &[Element::Dit]);
+
+        // Clearing the buffer drops pending text and releases a held key
+        wk.receive(&[0x19, 5, b'T', 0x0A], &config);
+        let sent = run(&mut wk, &config, 2200, 2300);
+        assert!(sent.is_empty());
+        wk.receive(&[0x0B, 1], &config);
+        assert_eq!(run(&mut wk, &config, 2300, 2310).as_slice(), &[Element::KeyDown]);
+        assert_eq!(wk.status() & STATUS_KEYDOWN, STATUS_KEYDOWN);
+        wk.receive(&[0x0A], &config);
+        assert_eq!(run(&mut wk, &config, 2310, 2320).as_slice(), &[Element::KeyUp]);
+    }
+}
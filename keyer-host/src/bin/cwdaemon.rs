@@ -0,0 +1,70 @@
+//! cwdaemon-compatible keyer
+//!
+//! Usage: `keyer-cwdaemon [-p PORT] [-s WPM] [-o stdout|FILE|pcm:FILE]`
+//!
+//! Keys a virtual output with the keyer-core timing model: an event log on
+//! stdout (default) or in a file, or raw 8 kHz S16LE PCM in a file (`-` = stdout).
+
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::process::ExitCode;
+use keyer_core::KeyerConfig;
+use keyer_host::cwdaemon::DEFAULT_PORT;
+use keyer_host::{EventLog, KeyOutput, Server, ToneWriter};
+
+/// PCM output sample rate
+const SAMPLE_RATE: u32 = 8000;
+
+const USAGE: &str = "usage: keyer-cwdaemon [-p PORT] [-s WPM] [-o stdout|FILE|pcm:FILE]";
+
+fn open_output(spec: &str) -> io::Result<Box<dyn KeyOutput>> {
+    Ok(match spec.strip_prefix("pcm:") {
+        Some("-") => Box::new(ToneWriter::new(io::stdout(), SAMPLE_RATE)),
+        Some(path) => Box::new(ToneWriter::new(BufWriter::new(File::create(path)?), SAMPLE_RATE)),
+        None if spec == "stdout" || spec == "-" => Box::new(EventLog::new(io::stdout())),
+        None => Box::new(EventLog::new(File::create(spec)?)),
+    })
+}
+
+fn main() -> ExitCode {
+    let mut port = DEFAULT_PORT;
+    let mut config = KeyerConfig::default();
+    let mut output = String::from("stdout");
+
+    let mut args = std::env::args().skip(1);
+    while let Some(flag) = args.next() {
+        let value = args.next();
+        let ok = match (flag.as_str(), value) {
+            ("-p", Some(value)) => value.parse().map(|value| port = value).is_ok(),
+            ("-s", Some(value)) => value
+                .parse()
+                .ok()
+                .and_then(|wpm| config.with_wpm(wpm).ok())
+                .map(|changed| config = changed)
+                .is_some(),
+            ("-o", Some(value)) => {
+                output = value;
+                true
+            }
+            _ => false,
+        };
+        if !ok {
+            eprintln!("{}", USAGE);
+            return ExitCode::FAILURE;
+        }
+    }
+
+    let result = open_output(&output)
+        .and_then(|output| Server::bind(("0.0.0.0", port), config, output))
+        .and_then(|mut server| {
+            eprintln!("keyer-cwdaemon listening on {}", server.local_addr()?);
+            server.run()
+        });
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("keyer-cwdaemon: {}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
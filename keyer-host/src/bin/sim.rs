@@ -0,0 +1,45 @@
+//! Paddle script simulator
+//!
+//! Usage: `keyer-sim [SCRIPT]`
+//!
+//! Runs a paddle event script or a recorded paddle trace (stdin when no file is
+//! given) through the keyer state machine on virtual time and prints the key
+//! timeline, the decoded text and the timing accuracy. See [`keyer_host::sim`]
+//! for the script format.
+
+use std::io::{self, Read};
+use std::process::ExitCode;
+use keyer_core::KeyerConfig;
+use keyer_host::sim;
+
+const USAGE: &str = "usage: keyer-sim [SCRIPT]";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let text = match args.as_slice() {
+        [] => {
+            let mut text = String::new();
+            io::stdin().read_to_string(&mut text).map(|_| text)
+        }
+        [path] if !path.starts_with('-') || path == "-" => match path.as_str() {
+            "-" => io::read_to_string(io::stdin()),
+            path => std::fs::read_to_string(path),
+        },
+        _ => {
+            eprintln!("{}", USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let result = text
+        .map_err(|error| error.to_string())
+        .and_then(|text| sim::load(&text, KeyerConfig::default()).map_err(|error| error.to_string()))
+        .and_then(|script| sim::simulate(&script).write(&mut io::stdout().lock()).map_err(|error| error.to_string()));
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("keyer-sim: {}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,106 @@
+//! cwdaemon UDP protocol
+//!
+//! Each datagram is either text to send or an escape command: ESC (0x1B), a
+//! command character and an optional ASCII argument. Text ending in `^` asks for
+//! the text to be sent back once it has been keyed. Values outside the ranges
+//! cwdaemon accepts are ignored, as cwdaemon does.
+
+/// Default cwdaemon UDP port
+pub const DEFAULT_PORT: u16 = 6789;
+
+/// Escape byte starting a command
+const ESC: u8 = 0x1B;
+/// Text suffix requesting a reply when sent
+const REPLY_CARET: char = '^';
+
+/// Decoded datagram
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Request {
+    /// Text to send; `reply` asks for it to be echoed once sent
+    Text { text: String, reply: bool },
+    /// Restore default settings
+    Reset,
+    /// Speed in WPM (4..=60)
+    Speed(u32),
+    /// Sidetone frequency in Hz (0 = silent)
+    Tone(u32),
+    /// Stop sending and drop queued text
+    Abort,
+    /// Shut the daemon down
+    Exit,
+    /// Uninterruptible word mode
+    WordMode(bool),
+    /// Weighting -50..=50
+    Weighting(i32),
+    Ptt(bool),
+    /// Key down for some seconds (0..=10)
+    Tune(u32),
+    /// PTT delay before keying in ms (0..=50)
+    PttDelay(u32),
+    /// Send `h` and this text back after the next message
+    ReplyRequest(String),
+    /// Recognised but not applicable here (device, port, sound system, ...)
+    Ignored(char),
+}
+
+/// Decode a datagram; None for malformed commands and out-of-range values
+pub fn parse(datagram: &[u8]) -> Option<Request> {
+    let Some((&ESC, rest)) = datagram.split_first() else {
+        let text = String::from_utf8_lossy(datagram);
+        let text = text.trim_end_matches(['\r', '\n']);
+        return Some(match text.strip_suffix(REPLY_CARET) {
+            Some(text) => Request::Text { text: text.into(), reply: true },
+            None => Request::Text { text: text.into(), reply: false },
+        });
+    };
+    let (&command, arg) = rest.split_first()?;
+    let arg = core::str::from_utf8(arg).ok()?.trim();
+    let number = |range: core::ops::RangeInclusive<i32>| arg.parse::<i32>().ok().filter(|value| range.contains(value));
+
+    Some(match command {
+        b'0' => Request::Reset,
+        b'2' => Request::Speed(number(4..=60)? as u32),
+        b'3' => Request::Tone(number(0..=4000)? as u32),
+        b'4' => Request::Abort,
+        b'5' => Request::Exit,
+        b'6' => Request::WordMode(number(0..=1)? != 0),
+        b'7' => Request::Weighting(number(-50..=50)?),
+        b'a' => Request::Ptt(number(0..=1)? != 0),
+        b'c' => Request::Tune(number(0..=10)? as u32),
+        b'd' => Request::PttDelay(number(0..=50)? as u32),
+        b'h' => Request::ReplyRequest(arg.into()),
+        b'1' | b'8'..=b'9' | b'b' | b'e'..=b'g' => Request::Ignored(command as char),
+        _ => return None,
+    })
+}
+
+/// Reply datagram for a finished message
+pub fn reply(text: &str) -> String {
+    format!("{}\r\n", text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_commands() {
+        assert_eq!(parse(b"\x1b228"), Some(Request::Speed(28)));
+        assert_eq!(parse(b"\x1b2 99"), None);
+        assert_eq!(parse(b"\x1b3600"), Some(Request::Tone(600)));
+        assert_eq!(parse(b"\x1b7-10"), Some(Request::Weighting(-10)));
+        assert_eq!(parse(b"\x1b4"), Some(Request::Abort));
+        assert_eq!(parse(b"\x1bc3"), Some(Request::Tune(3)));
+        assert_eq!(parse(b"\x1bhQSO1"), Some(Request::ReplyRequest("QSO1".into())));
+        assert_eq!(parse(b"\x1b8/dev/ttyS0"), Some(Request::Ignored('8')));
+        assert_eq!(parse(b"\x1bz"), None);
+        assert_eq!(parse(b"\x1b"), None);
+    }
+
+    #[test]
+    fn test_parse_text() {
+        assert_eq!(parse(b"CQ TEST"), Some(Request::Text { text: "CQ TEST".into(), reply: false }));
+        assert_eq!(parse(b"TU^\r\n"), Some(Request::Text { text: "TU".into(), reply: true }));
+        assert_eq!(reply("TU"), "TU\r\n");
+    }
+}
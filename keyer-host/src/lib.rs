@@ -0,0 +1,20 @@
+//! # Keyer Host
+//!
+//! Host-side tools built on keyer-core timing.
+//!
+//! - [`cwdaemon`]: cwdaemon UDP protocol, so loggers can send CW through the keyer timing model
+//! - [`player`]: plays text on a millisecond schedule using the core encoder and element timing
+//! - [`output`]: virtual key outputs (event log, raw PCM tone)
+//! - [`server`]: UDP server tying the three together
+//! - [`sim`]: runs the keyer state machine on virtual time from a paddle event script
+
+pub mod cwdaemon;
+pub mod player;
+pub mod output;
+pub mod server;
+pub mod sim;
+
+pub use cwdaemon::Request;
+pub use output::{EventLog, KeyOutput, ToneWriter};
+pub use player::Player;
+pub use server::Server;
@@ -0,0 +1,143 @@
+//! Virtual key outputs
+//!
+//! Event times are milliseconds on the player's clock. [`EventLog`] writes one
+//! line per key or PTT change (stdout, a file, ...). [`ToneWriter`] renders the
+//! keying as raw signed 16-bit mono PCM, a stand-in for a sound device that can
+//! be piped into e.g. `aplay -f S16_LE -r 8000`.
+
+use std::io::{self, Write};
+
+/// Key line driven by the player
+pub trait KeyOutput {
+    /// Key changed at `at_ms`; `tone_hz` is the sidetone pitch (0 = silent)
+    fn key(&mut self, at_ms: u64, down: bool, tone_hz: u32) -> io::Result<()>;
+
+    /// PTT changed at `at_ms`
+    fn ptt(&mut self, _at_ms: u64, _on: bool) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<O: KeyOutput + ?Sized> KeyOutput for Box<O> {
+    fn key(&mut self, at_ms: u64, down: bool, tone_hz: u32) -> io::Result<()> {
+        (**self).key(at_ms, down, tone_hz)
+    }
+
+    fn ptt(&mut self, at_ms: u64, on: bool) -> io::Result<()> {
+        (**self).ptt(at_ms, on)
+    }
+}
+
+/// Text log of key events
+pub struct EventLog<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> EventLog<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write> KeyOutput for EventLog<W> {
+    fn key(&mut self, at_ms: u64, down: bool, tone_hz: u32) -> io::Result<()> {
+        match down {
+            true => writeln!(self.writer, "{:>8} key down {} Hz", at_ms, tone_hz)?,
+            false => writeln!(self.writer, "{:>8} key up", at_ms)?,
+        }
+        self.writer.flush()
+    }
+
+    fn ptt(&mut self, at_ms: u64, on: bool) -> io::Result<()> {
+        writeln!(self.writer, "{:>8} ptt {}", at_ms, if on { "on" } else { "off" })?;
+        self.writer.flush()
+    }
+}
+
+/// Raw PCM rendering of the keyed tone
+pub struct ToneWriter<W: Write> {
+    writer: W,
+    sample_rate: u32,
+    /// Samples written so far
+    written: u64,
+    tone_hz: Option<u32>,
+    phase: f32,
+}
+
+/// Peak amplitude (about -6 dBFS)
+const AMPLITUDE: f32 = 16_000.0;
+
+impl<W: Write> ToneWriter<W> {
+    pub fn new(writer: W, sample_rate: u32) -> Self {
+        Self {
+            writer,
+            sample_rate,
+            written: 0,
+            tone_hz: None,
+            phase: 0.0,
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    /// Write samples up to `at_ms` in the current key state
+    fn render_until(&mut self, at_ms: u64) -> io::Result<()> {
+        let until = at_ms * u64::from(self.sample_rate) / 1000;
+        let step = self.tone_hz.map_or(0.0, |hz| core::f32::consts::TAU * hz as f32 / self.sample_rate as f32);
+        while self.written < until {
+            let sample = match self.tone_hz {
+                Some(_) => (self.phase.sin() * AMPLITUDE) as i16,
+                None => 0,
+            };
+            self.phase = (self.phase + step) % core::f32::consts::TAU;
+            self.writer.write_all(&sample.to_le_bytes())?;
+            self.written += 1;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> KeyOutput for ToneWriter<W> {
+    fn key(&mut self, at_ms: u64, down: bool, tone_hz: u32) -> io::Result<()> {
+        self.render_until(at_ms)?;
+        self.tone_hz = Some(tone_hz).filter(|&hz| down && hz > 0);
+        self.phase = 0.0;
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_log() {
+        let mut log = EventLog::new(Vec::new());
+        log.ptt(0, true).unwrap();
+        log.key(10, true, 600).unwrap();
+        log.key(70, false, 600).unwrap();
+        let text = String::from_utf8(log.into_inner()).unwrap();
+        assert_eq!(text, "       0 ptt on\n      10 key down 600 Hz\n      70 key up\n");
+    }
+
+    #[test]
+    fn test_tone_writer() {
+        let mut tone = ToneWriter::new(Vec::new(), 8000);
+        tone.key(10, true, 1000).unwrap();
+        tone.key(20, false, 1000).unwrap();
+        tone.key(30, true, 0).unwrap();
+        let samples: Vec<i16> = tone.into_inner().chunks(2).map(|b| i16::from_le_bytes([b[0], b[1]])).collect();
+
+        // 10 ms silence, 10 ms of 1 kHz, 10 ms silence again
+        assert_eq!(samples.len(), 240);
+        assert!(samples[..80].iter().all(|&s| s == 0));
+        assert!(samples[80..160].iter().any(|&s| s > 15_000));
+        assert!(samples[160..].iter().all(|&s| s == 0));
+    }
+}
@@ -0,0 +1,297 @@
+//! Text playback on a millisecond schedule
+//!
+//! Text is encoded into elements when it arrives; each element takes its timing
+//! from the current [`KeyerConfig`] when it starts, so speed and weighting
+//! changes apply to queued text from the next element on. The player works on a
+//! caller-supplied clock, which keeps it deterministic under test.
+
+use std::collections::VecDeque;
+use std::io;
+use keyer_core::encoder::char_pattern;
+use keyer_core::{Element, KeyerConfig, MorseEncoder};
+use crate::output::KeyOutput;
+
+/// Default sidetone pitch in Hz
+pub const DEFAULT_TONE_HZ: u32 = 800;
+/// Longest tune (key down) in seconds
+pub const MAX_TUNE_SECONDS: u32 = 10;
+
+/// Scheduled step
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Step {
+    Element(Element),
+    /// Key down for some milliseconds
+    Tune(u64),
+    /// Report text once everything before it was sent
+    Reply(String),
+}
+
+/// Keys queued text through a [`KeyOutput`]
+#[derive(Clone, Debug)]
+pub struct Player {
+    defaults: KeyerConfig,
+    config: KeyerConfig,
+    tone_hz: u32,
+    ptt_delay_ms: u64,
+    steps: VecDeque<Step>,
+    /// Last queued element, for the space between messages
+    tail: Option<Element>,
+    /// Pending key-up of the current mark
+    key_up_at: Option<u64>,
+    last_key_up: u64,
+    /// When the next step may start
+    due: u64,
+    ptt: bool,
+    replies: Vec<String>,
+}
+
+impl Player {
+    /// Create player with the given default settings
+    pub fn new(defaults: KeyerConfig) -> Self {
+        Self {
+            defaults,
+            config: defaults,
+            tone_hz: DEFAULT_TONE_HZ,
+            ptt_delay_ms: 0,
+            steps: VecDeque::new(),
+            tail: None,
+            key_up_at: None,
+            last_key_up: 0,
+            due: 0,
+            ptt: false,
+            replies: Vec::new(),
+        }
+    }
+
+    pub fn config(&self) -> &KeyerConfig {
+        &self.config
+    }
+
+    pub fn tone_hz(&self) -> u32 {
+        self.tone_hz
+    }
+
+    /// Returns true when nothing is queued or keyed
+    pub fn is_idle(&self) -> bool {
+        self.steps.is_empty() && self.key_up_at.is_none()
+    }
+
+    /// Restore the default settings
+    pub fn reset(&mut self) {
+        self.config = self.defaults;
+        self.tone_hz = DEFAULT_TONE_HZ;
+        self.ptt_delay_ms = 0;
+    }
+
+    /// Change the speed; a Farnsworth speed that no longer fits is dropped
+    pub fn set_wpm(&mut self, wpm: u32) -> Result<(), &'static str> {
+        let config = self.config;
+        self.config = config.with_wpm(wpm).or_else(|_| config.with_farnsworth(None)?.with_wpm(wpm))?;
+        Ok(())
+    }
+
+    /// Change the weighting (-50..=50, 0 = neutral)
+    pub fn set_weighting(&mut self, weighting: i32) -> Result<(), &'static str> {
+        let weighting = (50 + weighting).clamp(10, 90) as u8;
+        self.config = self.config.with_timing(self.config.dah_ratio_tenths, weighting)?;
+        Ok(())
+    }
+
+    pub fn set_tone(&mut self, tone_hz: u32) {
+        self.tone_hz = tone_hz;
+    }
+
+    /// PTT lead time before keying starts (0 = no automatic PTT)
+    pub fn set_ptt_delay(&mut self, delay_ms: u64) {
+        self.ptt_delay_ms = delay_ms;
+    }
+
+    /// Queue text; characters without a Morse pattern are skipped
+    pub fn send_text(&mut self, text: &str, now: u64) {
+        let text: String = text
+            .chars()
+            .filter(|&c| c.is_ascii_whitespace() || c == '<' || c == '>' || char_pattern(c).is_some())
+            .collect();
+        self.start_if_idle(now);
+        let mut elements = MorseEncoder::new(&text).map_while(Result::ok).peekable();
+        // Consecutive messages without a space still get a character space
+        if elements.peek().is_some_and(|e| e.is_keyed()) && self.tail.is_some_and(|tail| tail.is_keyed()) {
+            self.push(Element::CharSpace);
+        }
+        for element in elements {
+            self.push(element);
+        }
+    }
+
+    /// Queue a key down (tune) for some seconds
+    pub fn tune(&mut self, seconds: u32, now: u64) {
+        self.start_if_idle(now);
+        let ms = u64::from(seconds.min(MAX_TUNE_SECONDS)) * 1000;
+        self.steps.push_back(Step::Tune(ms));
+        self.tail = None;
+    }
+
+    /// Report `text` once everything queued so far has been sent
+    pub fn reply_when_sent(&mut self, text: String, now: u64) {
+        self.start_if_idle(now);
+        self.steps.push_back(Step::Reply(text));
+    }
+
+    /// Replies that became due
+    pub fn take_replies(&mut self) -> Vec<String> {
+        core::mem::take(&mut self.replies)
+    }
+
+    /// Drop queued text and release the key
+    pub fn abort(&mut self, now: u64, output: &mut impl KeyOutput) -> io::Result<()> {
+        self.steps.clear();
+        self.tail = None;
+        if self.key_up_at.take().is_some() {
+            self.last_key_up = now;
+            output.key(now, false, self.tone_hz)?;
+        }
+        self.set_ptt(now, false, output)
+    }
+
+    /// Switch PTT by hand
+    pub fn set_ptt(&mut self, now: u64, on: bool, output: &mut impl KeyOutput) -> io::Result<()> {
+        if self.ptt != on {
+            self.ptt = on;
+            output.ptt(now, on)?;
+        }
+        Ok(())
+    }
+
+    fn push(&mut self, element: Element) {
+        self.steps.push_back(Step::Element(element));
+        self.tail = Some(element);
+    }
+
+    fn start_if_idle(&mut self, now: u64) {
+        if self.is_idle() {
+            self.due = self.due.max(now);
+        }
+    }
+
+    /// Carry out everything due by `now`; returns the time of the next event
+    pub fn poll(&mut self, now: u64, output: &mut impl KeyOutput) -> io::Result<Option<u64>> {
+        loop {
+            if let Some(up) = self.key_up_at {
+                if now < up {
+                    return Ok(Some(up));
+                }
+                self.key_up_at = None;
+                self.last_key_up = up;
+                output.key(up, false, self.tone_hz)?;
+                continue;
+            }
+            if now < self.due {
+                return Ok(Some(self.due));
+            }
+
+            let Some(step) = self.steps.pop_front() else {
+                if self.ptt && self.ptt_delay_ms > 0 {
+                    self.set_ptt(self.due, false, output)?;
+                }
+                return Ok(None);
+            };
+            let start = self.due;
+            if !self.ptt && self.ptt_delay_ms > 0 && !matches!(step, Step::Reply(_)) {
+                // Assert PTT and try the step again after the lead time
+                self.set_ptt(start, true, output)?;
+                self.steps.push_front(step);
+                self.due = start + self.ptt_delay_ms;
+                continue;
+            }
+
+            match step {
+                Step::Element(element) if element.is_keyed() => {
+                    let timing = self.config.element_timing(element);
+                    let key_down = timing.key_down.as_millis();
+                    output.key(start, true, self.tone_hz)?;
+                    self.key_up_at = Some(start + key_down);
+                    self.due = start + key_down + timing.space.as_millis();
+                }
+                Step::Element(element) => {
+                    // Spaces count from the previous key-up
+                    let gap = self.config.element_timing(element).space.as_millis();
+                    self.due = start.max(self.last_key_up + gap);
+                }
+                Step::Tune(ms) => {
+                    output.key(start, true, self.tone_hz)?;
+                    self.key_up_at = Some(start + ms);
+                    self.due = start + ms;
+                }
+                Step::Reply(text) => self.replies.push(text),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::EventLog;
+
+    /// Run the player to completion and return its event log
+    fn play(player: &mut Player, from: u64) -> String {
+        let mut log = EventLog::new(Vec::new());
+        let mut now = from;
+        while let Some(next) = player.poll(now, &mut log).unwrap() {
+            now = next;
+        }
+        String::from_utf8(log.into_inner()).unwrap()
+    }
+
+    fn times(log: &str) -> Vec<u64> {
+        log.lines().map(|line| line.split_whitespace().next().unwrap().parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_text_timing() {
+        // 20 WPM: 60 ms unit
+        let mut player = Player::new(KeyerConfig::default());
+        player.send_text("EE T", 1000);
+        assert_eq!(times(&play(&mut player, 1000)), vec![1000, 1060, 1240, 1300, 1720, 1900]);
+
+        // Next message starts from its arrival time, after a character space
+        player.send_text("E", 5000);
+        player.send_text("E", 5000);
+        assert_eq!(times(&play(&mut player, 5000)), vec![5000, 5060, 5240, 5300]);
+    }
+
+    #[test]
+    fn test_settings_and_reply() {
+        let mut player = Player::new(KeyerConfig::default());
+        player.set_wpm(30).unwrap();
+        player.set_weighting(10).unwrap();
+        player.set_tone(600);
+        assert!(player.set_wpm(0).is_err());
+
+        // Unsupported characters are skipped
+        player.send_text("#E", 0);
+        player.reply_when_sent("hQSO".into(), 0);
+        let log = play(&mut player, 0);
+        assert_eq!(log, "       0 key down 600 Hz\n      48 key up\n");
+        assert_eq!(player.take_replies(), vec!["hQSO".to_string()]);
+
+        player.reset();
+        assert_eq!((player.config().wpm(), player.tone_hz()), (20, DEFAULT_TONE_HZ));
+    }
+
+    #[test]
+    fn test_abort_tune_and_ptt_delay() {
+        let mut player = Player::new(KeyerConfig::default());
+        let mut log = EventLog::new(Vec::new());
+        player.set_ptt_delay(20);
+        player.tune(3, 0);
+        player.send_text("TEST", 0);
+        assert_eq!(player.poll(0, &mut log).unwrap(), Some(20));
+        assert_eq!(player.poll(20, &mut log).unwrap(), Some(3020));
+
+        player.abort(500, &mut log).unwrap();
+        assert!(player.is_idle());
+        let text = String::from_utf8(log.into_inner()).unwrap();
+        assert_eq!(text, "       0 ptt on\n      20 key down 800 Hz\n     500 key up\n     500 ptt off\n");
+    }
+}
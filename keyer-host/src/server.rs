@@ -0,0 +1,162 @@
+//! cwdaemon UDP server
+//!
+//! Receives datagrams, applies them to a [`Player`] and keys the output on the
+//! wall clock. Replies go back to the address that asked for them.
+
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant};
+use keyer_core::KeyerConfig;
+use crate::cwdaemon::{self, Request};
+use crate::output::KeyOutput;
+use crate::player::Player;
+
+/// Longest wait for a datagram while nothing is scheduled
+const IDLE_POLL: Duration = Duration::from_millis(100);
+/// Largest datagram handled
+const DATAGRAM_LEN: usize = 512;
+
+/// cwdaemon-compatible server keying a virtual output
+pub struct Server<O: KeyOutput> {
+    socket: UdpSocket,
+    player: Player,
+    output: O,
+    start: Instant,
+    /// Where to send replies
+    reply_to: Option<SocketAddr>,
+    /// Reply requested for the next message
+    pending_reply: Option<String>,
+}
+
+impl<O: KeyOutput> Server<O> {
+    /// Bind the UDP socket
+    pub fn bind(addr: impl ToSocketAddrs, config: KeyerConfig, output: O) -> io::Result<Self> {
+        Ok(Self {
+            socket: UdpSocket::bind(addr)?,
+            player: Player::new(config),
+            output,
+            start: Instant::now(),
+            reply_to: None,
+            pending_reply: None,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    pub fn player(&self) -> &Player {
+        &self.player
+    }
+
+    pub fn into_output(self) -> O {
+        self.output
+    }
+
+    /// Milliseconds since the server started
+    fn now(&self) -> u64 {
+        self.start.elapsed().as_millis() as u64
+    }
+
+    /// Serve until an exit request arrives
+    pub fn run(&mut self) -> io::Result<()> {
+        let mut datagram = [0u8; DATAGRAM_LEN];
+        loop {
+            let now = self.now();
+            let next = self.player.poll(now, &mut self.output)?;
+            for text in self.player.take_replies() {
+                if let Some(to) = self.reply_to {
+                    self.socket.send_to(cwdaemon::reply(&text).as_bytes(), to)?;
+                }
+            }
+
+            let wait = next.map_or(IDLE_POLL, |next| Duration::from_millis(next.saturating_sub(now)));
+            self.socket.set_read_timeout(Some(wait.clamp(Duration::from_millis(1), IDLE_POLL)))?;
+            match self.socket.recv_from(&mut datagram) {
+                Ok((len, from)) => {
+                    let Some(request) = cwdaemon::parse(&datagram[..len]) else {
+                        continue;
+                    };
+                    if !self.handle(request, from)? {
+                        return self.player.abort(self.now(), &mut self.output);
+                    }
+                }
+                Err(error) if matches!(error.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => {}
+                Err(error) => return Err(error),
+            }
+        }
+    }
+
+    /// Apply one request; returns false on exit
+    pub fn handle(&mut self, request: Request, from: SocketAddr) -> io::Result<bool> {
+        let now = self.now();
+        match request {
+            Request::Text { text, reply } => {
+                self.player.send_text(&text, now);
+                if let Some(pending) = self.pending_reply.take() {
+                    self.player.reply_when_sent(pending, now);
+                }
+                if reply {
+                    self.reply_to = Some(from);
+                    self.player.reply_when_sent(text, now);
+                }
+            }
+            Request::ReplyRequest(text) => {
+                self.reply_to = Some(from);
+                self.pending_reply = Some(format!("h{}", text));
+            }
+            Request::Reset => self.player.reset(),
+            // Values were range-checked by the parser
+            Request::Speed(wpm) => {
+                self.player.set_wpm(wpm).ok();
+            }
+            Request::Weighting(weighting) => {
+                self.player.set_weighting(weighting).ok();
+            }
+            Request::Tone(hz) => self.player.set_tone(hz),
+            Request::Abort => self.player.abort(now, &mut self.output)?,
+            Request::Exit => return Ok(false),
+            Request::Ptt(on) => self.player.set_ptt(now, on, &mut self.output)?,
+            Request::Tune(seconds) => self.player.tune(seconds, now),
+            Request::PttDelay(ms) => self.player.set_ptt_delay(u64::from(ms)),
+            Request::WordMode(_) | Request::Ignored(_) => {}
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::EventLog;
+
+    #[test]
+    fn test_udp_session() {
+        let mut server = Server::bind("127.0.0.1:0", KeyerConfig::default(), EventLog::new(Vec::new())).unwrap();
+        let addr = server.local_addr().unwrap();
+        let daemon = std::thread::spawn(move || {
+            server.run().unwrap();
+            server
+        });
+
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+        client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        for datagram in [&b"\x1b240"[..], b"\x1b3700", b"\x1bhTEST", b"EE^"] {
+            client.send_to(datagram, addr).unwrap();
+        }
+
+        // The reply request and the caret reply arrive once "EE" has been keyed
+        let mut buf = [0u8; 64];
+        let (len, _) = client.recv_from(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"hTEST\r\n");
+        let (len, _) = client.recv_from(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"EE\r\n");
+
+        client.send_to(b"\x1b5", addr).unwrap();
+        let server = daemon.join().unwrap();
+        assert_eq!(server.player().config().wpm(), 40);
+        let log = String::from_utf8(server.into_output().into_inner()).unwrap();
+        assert_eq!(log.lines().filter(|line| line.ends_with("key down 700 Hz")).count(), 2);
+        assert_eq!(log.lines().filter(|line| line.ends_with("key up")).count(), 2);
+    }
+}
@@ -0,0 +1,390 @@
+//! Paddle script simulator
+//!
+//! Runs the real [`KeyerFSM`] and a sender model on virtual time, driven by a
+//! paddle event script, and reports the key timeline, the decoded text and the
+//! timing accuracy.
+//!
+//! Script lines (`#` starts a comment):
+//!
+//! - `<ms> dit|dah|both down|up`: paddle event; `+<ms>` is relative to the previous event
+//! - `end <ms>`: stop time (default: two seconds after the last event)
+//! - anything else is a settings command of the serial command line, e.g. `MODE B`, `WPM 25`
+//!
+//! Scripts and traces are limited to one hour of virtual time.
+//!
+//! A paddle trace dumped by the firmware ([`keyer_core::trace`]) is accepted
+//! too, which replays a recorded session with the settings it was recorded with.
+
+use std::fmt;
+use std::io::{self, Write};
+use keyer_core::cli::{self, CliError};
+use keyer_core::test_utils::output_capture::OutputCapture;
+use keyer_core::test_utils::virtual_time::VirtualTime;
+use keyer_core::trace::TraceError;
+use keyer_core::{Duration, Element, KeyerConfig, KeyerFSM, PaddleInput, PaddleSide, TraceReader};
+use heapless::spsc::Queue;
+
+/// Virtual time at script time 0; paddle debounce counts from an edge at 0 ms
+const START_MS: u64 = 1_000;
+/// Run time after the last paddle event when the script has no `end`
+const DEFAULT_TAIL_MS: u64 = 2_000;
+/// Longest script or trace; the simulator steps every millisecond
+const MAX_SCRIPT_MS: u64 = 3_600_000;
+
+/// Paddle event at a script time
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ScriptEvent {
+    pub at_ms: u64,
+    pub side: PaddleSide,
+    pub pressed: bool,
+}
+
+/// Parsed paddle script
+#[derive(Clone, Debug, PartialEq)]
+pub struct Script {
+    pub config: KeyerConfig,
+    pub events: Vec<ScriptEvent>,
+    pub end_ms: u64,
+}
+
+/// Script error with its 1-based line number
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ScriptError {
+    pub line: usize,
+    pub message: &'static str,
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+/// Parse a paddle script
+pub fn parse_script(text: &str, defaults: KeyerConfig) -> Result<Script, ScriptError> {
+    let mut config = defaults;
+    let mut events = Vec::new();
+    let mut last_ms = 0u64;
+    let mut end_ms = None;
+
+    for (index, line) in text.lines().enumerate() {
+        let fail = |message| ScriptError { line: index + 1, message };
+        let line = line.split('#').next().unwrap_or("").trim();
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            [] => {}
+            ["end", time] => {
+                let time = time.parse().ok().filter(|&time| time <= MAX_SCRIPT_MS);
+                end_ms = Some(time.ok_or(fail("Invalid end time"))?);
+            }
+            [time, side, state] if time.starts_with(|c: char| c.is_ascii_digit() || c == '+') => {
+                let at_ms = match time.strip_prefix('+') {
+                    Some(delta) => delta.parse::<u64>().ok().and_then(|delta| last_ms.checked_add(delta)),
+                    None => time.parse().ok(),
+                };
+                let at_ms = at_ms.ok_or(fail("Invalid time"))?;
+                if at_ms > MAX_SCRIPT_MS {
+                    return Err(fail("Script longer than one hour"));
+                }
+                if at_ms < last_ms {
+                    return Err(fail("Events must be in time order"));
+                }
+                let pressed = match *state {
+                    "down" => true,
+                    "up" => false,
+                    _ => return Err(fail("Expected down or up")),
+                };
+                let sides: &[PaddleSide] = match *side {
+                    "dit" => &[PaddleSide::Dit],
+                    "dah" => &[PaddleSide::Dah],
+                    "both" => &[PaddleSide::Dit, PaddleSide::Dah],
+                    _ => return Err(fail("Expected dit, dah or both")),
+                };
+                events.extend(sides.iter().map(|&side| ScriptEvent { at_ms, side, pressed }));
+                last_ms = at_ms;
+            }
+            _ => {
+                let command = cli::parse(line.as_bytes()).map_err(|_| fail("Unknown line"))?;
+                config = cli::apply_setting(config, command).map_err(|error| match error {
+                    CliError::UnknownCommand => fail("Not a setting"),
+                    _ => fail("Invalid setting"),
+                })?;
+            }
+        }
+    }
+
+    Ok(Script {
+        config,
+        events,
+        end_ms: end_ms.unwrap_or(last_ms + DEFAULT_TAIL_MS),
+    })
+}
+
+/// Parse a paddle trace dump
+pub fn parse_trace(text: &str, defaults: KeyerConfig) -> Result<Script, ScriptError> {
+    let mut reader = TraceReader::new(defaults);
+    let mut events = Vec::new();
+    let fail = |line, error| {
+        let message = match error {
+            TraceError::MissingHeader => "Missing trace header",
+            TraceError::UnsupportedVersion(_) => "Unsupported trace version",
+            TraceError::InvalidSetting => "Invalid setting",
+            TraceError::InvalidEvent => "Invalid paddle edge",
+            TraceError::CountMismatch => "Edge count does not match END",
+            TraceError::Truncated => "Trace ends without END",
+        };
+        ScriptError { line, message }
+    };
+
+    for (index, line) in text.lines().enumerate() {
+        reader
+            .read_line(line, |edge| {
+                events.push(ScriptEvent { at_ms: u64::from(edge.at_ms), side: edge.side, pressed: edge.pressed })
+            })
+            .map_err(|error| fail(index + 1, error))?;
+    }
+    if !reader.is_complete() {
+        return Err(fail(text.lines().count(), TraceError::Truncated));
+    }
+
+    let last_ms = events.last().map_or(0, |event| event.at_ms);
+    if last_ms > MAX_SCRIPT_MS {
+        let line = text.lines().position(|line| line.starts_with("END")).map_or(0, |index| index + 1);
+        return Err(ScriptError { line, message: "Trace longer than one hour" });
+    }
+    Ok(Script {
+        config: *reader.config(),
+        events,
+        end_ms: last_ms + DEFAULT_TAIL_MS,
+    })
+}
+
+/// Parse a paddle script or trace dump, whichever `text` is
+pub fn load(text: &str, defaults: KeyerConfig) -> Result<Script, ScriptError> {
+    match text.trim_start().starts_with("KT") {
+        true => parse_trace(text, defaults),
+        false => parse_script(text, defaults),
+    }
+}
+
+/// Timeline entry kinds
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TimelineKind {
+    Paddle { side: PaddleSide, pressed: bool },
+    KeyDown(Element),
+    KeyUp { held_ms: u64 },
+    Space(Element),
+}
+
+/// Timeline entry at a script time
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TimelineEntry {
+    pub at_ms: u64,
+    pub kind: TimelineKind,
+}
+
+/// Simulation result
+#[derive(Debug)]
+pub struct SimReport {
+    pub config: KeyerConfig,
+    pub timeline: Vec<TimelineEntry>,
+    pub capture: OutputCapture,
+}
+
+/// Sender model: keys elements with the configured timing, spaces from the last key-up
+#[derive(Default)]
+struct Sender {
+    key_up_at: Option<u64>,
+    key_down_at: u64,
+    busy_until: u64,
+    last_key_up: u64,
+}
+
+/// Run a script
+pub fn simulate(script: &Script) -> SimReport {
+    let clock = VirtualTime::new();
+    clock.advance(Duration::from_millis(START_MS));
+    let paddle = PaddleInput::new();
+    let mut fsm = KeyerFSM::new(script.config);
+    let mut queue = Queue::<Element, 8>::new();
+    let (mut producer, mut consumer) = queue.split();
+    let mut sender = Sender::default();
+    let mut capture = OutputCapture::new();
+    let mut timeline = Vec::new();
+    let mut events = script.events.iter().peekable();
+
+    let mut log = |at: u64, kind| timeline.push(TimelineEntry { at_ms: at - START_MS, kind });
+    loop {
+        let now = clock.now();
+        let ms = now.as_millis();
+        let idle = sender.key_up_at.is_none() && consumer.len() == 0 && ms >= sender.busy_until;
+        if ms >= START_MS + script.end_ms && (idle || ms >= START_MS + script.end_ms + DEFAULT_TAIL_MS) {
+            break;
+        }
+
+        while let Some(event) = events.next_if(|event| START_MS + event.at_ms <= ms) {
+            paddle.update(event.side, event.pressed, ms as u32);
+            log(ms, TimelineKind::Paddle { side: event.side, pressed: event.pressed });
+        }
+
+        if sender.key_up_at.is_some_and(|up| ms >= up) {
+            sender.key_up_at = None;
+            sender.last_key_up = ms;
+            capture.key_up(now);
+            fsm.element_finished(now);
+            log(ms, TimelineKind::KeyUp { held_ms: ms - sender.key_down_at });
+        }
+
+        fsm.update_at(now, &paddle, &mut producer);
+
+        while sender.key_up_at.is_none() && ms >= sender.busy_until {
+            let Some(element) = consumer.dequeue() else {
+                break;
+            };
+            let timing = fsm.config().element_timing(element);
+            match element {
+                Element::Dit | Element::Dah => {
+                    capture.key_down(element, now);
+                    fsm.element_started(now);
+                    sender.key_down_at = ms;
+                    sender.key_up_at = Some(ms + timing.key_down.as_millis());
+                    sender.busy_until = ms + timing.key_down.as_millis() + timing.space.as_millis();
+                    log(ms, TimelineKind::KeyDown(element));
+                }
+                Element::CharSpace | Element::WordSpace => {
+                    capture.space(element, now);
+                    sender.busy_until = ms.max(sender.last_key_up + timing.space.as_millis());
+                    log(ms, TimelineKind::Space(element));
+                }
+                Element::KeyDown => {
+                    capture.key_down(element, now);
+                    sender.key_down_at = ms;
+                    log(ms, TimelineKind::KeyDown(element));
+                }
+                Element::KeyUp => {
+                    capture.key_up(now);
+                    sender.last_key_up = ms;
+                    log(ms, TimelineKind::KeyUp { held_ms: ms - sender.key_down_at });
+                }
+            }
+        }
+
+        clock.advance(Duration::from_millis(1));
+    }
+
+    SimReport {
+        config: script.config,
+        timeline,
+        capture,
+    }
+}
+
+impl SimReport {
+    /// Decoded text
+    pub fn text(&self) -> String {
+        self.capture.to_text().as_str().into()
+    }
+
+    /// Print timeline, decoded text and timing accuracy
+    pub fn write(&self, out: &mut impl Write) -> io::Result<()> {
+        let c = &self.config;
+        writeln!(out, "Mode {:?}, {} WPM (unit {} ms)", c.mode, c.wpm(), c.unit.as_millis())?;
+        for entry in &self.timeline {
+            write!(out, "{:>7} ms  ", entry.at_ms)?;
+            match entry.kind {
+                TimelineKind::Paddle { side, pressed } => {
+                    writeln!(out, "paddle {:?} {}", side, if pressed { "down" } else { "up" })?
+                }
+                TimelineKind::KeyDown(element) => writeln!(out, "key down  {:?}", element)?,
+                TimelineKind::KeyUp { held_ms } => writeln!(out, "key up    ({} ms)", held_ms)?,
+                TimelineKind::Space(element) => writeln!(out, "{:?}", element)?,
+            }
+        }
+
+        let analysis = self.capture.analyze_timing(c.unit);
+        writeln!(out, "Elements: {}", self.capture.to_morse_string())?;
+        writeln!(out, "Text: {}", self.text())?;
+        writeln!(
+            out,
+            "Timing error: dit {:.1}%, dah {:.1}%, spacing {:.1}%",
+            analysis.dit_accuracy(),
+            analysis.dah_accuracy(),
+            analysis.spacing_accuracy(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use keyer_core::KeyerMode;
+
+    #[test]
+    fn test_parse_script() {
+        let script = parse_script("MODE B\nwpm 25 # faster\n\n100 both down\n+150 dit up\nend 900\n", KeyerConfig::default()).unwrap();
+        assert_eq!((script.config.mode, script.config.wpm(), script.end_ms), (KeyerMode::ModeB, 25, 900));
+        assert_eq!(script.events.len(), 3);
+        assert_eq!(script.events[2], ScriptEvent { at_ms: 250, side: PaddleSide::Dit, pressed: false });
+
+        let error = parse_script("0 dit down\nSEND CQ\n", KeyerConfig::default()).unwrap_err();
+        assert_eq!(error, ScriptError { line: 2, message: "Not a setting" });
+        assert!(parse_script("100 dit down\n50 dit up\n", KeyerConfig::default()).is_err());
+
+        // Times that overflow or would run for days are rejected, not simulated
+        let error = parse_script("100 dit down\n+18446744073709551615 dit up\n", KeyerConfig::default()).unwrap_err();
+        assert_eq!(error, ScriptError { line: 2, message: "Invalid time" });
+        let error = parse_script("3600001 dit down\n", KeyerConfig::default()).unwrap_err();
+        assert_eq!(error, ScriptError { line: 1, message: "Script longer than one hour" });
+        let error = parse_script("end 99999999999\n", KeyerConfig::default()).unwrap_err();
+        assert_eq!(error, ScriptError { line: 1, message: "Invalid end time" });
+    }
+
+    #[test]
+    fn test_simulate_letters() {
+        // Dah held for three elements, then a dit: "O" then "E"
+        let script = parse_script("0 dah down\n+500 dah up\n+400 dit down\n+30 dit up\n", KeyerConfig::default()).unwrap();
+        let report = simulate(&script);
+        // The keyer closes the word once the paddles go quiet
+        assert_eq!(report.text(), "OE ");
+
+        let analysis = report.capture.analyze_timing(script.config.unit);
+        assert_eq!(analysis.dah_durations.len(), 3);
+        assert_eq!(analysis.dit_durations.len(), 1);
+        assert!(analysis.dit_accuracy() < 1.0 && analysis.dah_accuracy() < 1.0);
+        assert_eq!(report.timeline[1], TimelineEntry { at_ms: 0, kind: TimelineKind::KeyDown(Element::Dah) });
+        assert_eq!(report.timeline[2], TimelineEntry { at_ms: 180, kind: TimelineKind::KeyUp { held_ms: 180 } });
+    }
+
+    #[test]
+    fn test_simulate_squeeze_modes() {
+        // A short squeeze: Mode B adds the opposite element after release
+        let squeeze = "0 both down\n+100 both up\n";
+        let text = |mode: &str| {
+            let script = parse_script(&format!("MODE {}\n{}", mode, squeeze), KeyerConfig::default()).unwrap();
+            simulate(&script).capture.to_morse_string().trim_end_matches([' ', '/']).to_string()
+        };
+        assert_eq!(text("A"), ".-");
+        assert_eq!(text("B"), ".-.");
+    }
+
+    #[test]
+    fn test_replay_trace() {
+        // A recorded "N" with the settings it was sent with
+        let mut recorder = keyer_core::TraceRecorder::<8>::new();
+        let config = KeyerConfig::default().with_wpm(25).unwrap();
+        for (side, pressed, at) in [(PaddleSide::Dah, true, 5000), (PaddleSide::Dah, false, 5100), (PaddleSide::Dit, true, 5120), (PaddleSide::Dit, false, 5200)] {
+            recorder.record(side, pressed, at);
+        }
+        let dump: String = (0..recorder.line_count()).map(|i| format!("{}\r\n", recorder.line(i, &config).unwrap())).collect();
+
+        let script = load(&dump, KeyerConfig::default()).unwrap();
+        assert_eq!(script.config.wpm(), 25);
+        assert_eq!(script.events[3], ScriptEvent { at_ms: 200, side: PaddleSide::Dit, pressed: false });
+        assert_eq!(simulate(&script).text(), "N ");
+
+        let error = load("KT1\n0D 20d\n", KeyerConfig::default()).unwrap_err();
+        assert_eq!(error, ScriptError { line: 2, message: "Trace ends without END" });
+    }
+}